@@ -0,0 +1,27 @@
+// Issue a ConnectToken file for offline testing of secure servers.
+// usage: issue_token <username> [token_file]
+// reads the private key and public addresses from server.settings.json in the working directory.
+// set the client's `auth_token_file` to the generated file.
+
+use ethertia::{net::auth, server::prelude::ServerSettings};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(username) = args.get(1) else {
+        eprintln!("usage: issue_token <username> [token_file]");
+        std::process::exit(1);
+    };
+    let token_file = args.get(2).cloned().unwrap_or(format!("{username}.token"));
+
+    let cfg: ServerSettings = serde_json::from_str(&std::fs::read_to_string("server.settings.json").expect("server.settings.json not found"))
+        .expect("invalid server.settings.json");
+    let private_key = auth::parse_private_key(&cfg.auth_private_key).expect("invalid auth_private_key");
+
+    let token = auth::issue_connect_token(&private_key, cfg.connect_addresses(), username).unwrap();
+    auth::write_token_file(&token_file, &token).unwrap();
+
+    println!(
+        "Issued token for {} (user id {}) to {}, expires in {}s",
+        username, token.client_id, token_file, auth::TOKEN_EXPIRE_SECS
+    );
+}
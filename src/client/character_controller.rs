@@ -1,10 +1,13 @@
 use std::f32::consts::{FRAC_PI_2, PI};
 
 use crate::client::prelude::*;
+use crate::net::EYE_HEIGHT;
 use crate::util::SmoothValue;
+use crate::voxel::{ChunkSystem, ClientChunkSystem, VoxTex};
 
 use bevy::{
     input::mouse::{MouseMotion, MouseWheel},
+    math::vec3,
     prelude::*, transform::TransformSystem,
 };
 use avian3d::prelude::*;
@@ -12,6 +15,21 @@ use leafwing_input_manager::action_state::ActionState;
 
 pub struct CharacterControllerPlugin;
 
+/// fully submerged, the fluid cancels this fraction of the gravity. the character sinks slowly.
+const FLUID_BUOYANCY: f32 = 0.9;
+
+/// velocity factor per second when fully submerged.
+const FLUID_DRAG: f32 = 0.15;
+
+/// submersion to swim, instead of walk.
+const SWIM_SUBMERSION: f32 = 0.5;
+
+/// vertical acceleration of swimming up (Jump) or down (Sneak).
+const SWIM_ACCELERATION: f32 = 8.;
+
+/// vertical samples of the submersion, along the collider.
+const SUBMERSION_SAMPLES: usize = 8;
+
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<CharacterController>();
@@ -67,16 +85,26 @@ pub struct CharacterController {
     pub yaw: f32,
 
     pub is_flying: bool,
+    // allow toggling is_flying by double jump.
+    pub can_fly: bool,
     // sprint: bool,
     // sneak: bool,
     // jump: bool,
 
     // Readonly State
     pub is_grounded: bool,
+    // vertical speed (positive downwards) of the last landing. taken by the consumer, for fall damage.
+    pub landed_velocity: Option<f32>,
 
     pub is_sprinting: bool,
     pub is_sneaking: bool,
 
+    // fraction of the collider height in fluid, 0..1.
+    pub submersion: f32,
+    pub is_swimming: bool,
+    // for the underwater fog.
+    pub is_eye_in_fluid: bool,
+
     // Control Param
     pub jump_impulse: f32,
     pub acceleration: f32,
@@ -116,11 +144,16 @@ impl Default for CharacterController {
             yaw: 0.,
             pitch: 0.,
             is_flying: false,
+            can_fly: true,
             enable_input: true,
             enable_input_cursor_look: true,
             is_grounded: false,
+            landed_velocity: None,
             is_sprinting: false,
             is_sneaking: false,
+            submersion: 0.,
+            is_swimming: false,
+            is_eye_in_fluid: false,
             jump_impulse: 7.,
             acceleration: 50.,
             max_slope_angle: PI * 0.25,
@@ -136,6 +169,15 @@ impl Default for CharacterController {
 
 // }
 
+/// whether the point is under the fluid surface. the surface of flowing fluid is lower, unless there's fluid above.
+fn is_in_fluid(chunk_sys: &ClientChunkSystem, p: Vec3) -> bool {
+    let vp = p.floor().as_ivec3();
+    let Some(vox) = chunk_sys.get_voxel(vp).filter(|v| v.tex_id == VoxTex::Water) else {
+        return false;
+    };
+    chunk_sys.get_voxel(vp + IVec3::Y).is_some_and(|v| v.tex_id == VoxTex::Water) || p.y - vp.y as f32 <= vox.fluid_height()
+}
+
 fn input_move(
     input_key: Res<ButtonInput<KeyCode>>,
     input_mouse_button: Res<ButtonInput<MouseButton>>,
@@ -152,8 +194,11 @@ fn input_move(
         &mut GravityScale,
         &ShapeHits,
         &Rotation,
+        &Position,
+        &ColliderAabb,
     )>,
     mut cam_dist_smoothed: Local<SmoothValue>,
+    chunk_sys: Res<ClientChunkSystem>,
 ) {
     let mouse_delta = mouse_motion_events.read().fold(Vec2::ZERO, |acc, v| acc + v.delta);
     let wheel_delta = mouse_wheel_events.read().fold(0.0, |acc, v| acc + v.x + v.y);
@@ -161,12 +206,23 @@ fn input_move(
 
     let action_state = query_input.single().unwrap();
 
-    for (mut trans, mut ctl, mut linvel, mut gravity_scale, hits, rotation) in query.iter_mut() {
+    for (mut trans, mut ctl, mut linvel, mut gravity_scale, hits, rotation, position, aabb) in query.iter_mut() {
         // A Local-Space Movement.  Speed/Acceleration/Delta will applied later on this.
         let mut movement = Vec3::ZERO;
 
-        // Flying
-        gravity_scale.0 = if ctl.is_flying { 0. } else { 2. };
+        // Submersion: sample the fluid along the collider height.
+        let in_fluid = (0..SUBMERSION_SAMPLES)
+            .filter(|&i| {
+                let y = aabb.min.y + (aabb.max.y - aabb.min.y) * (i as f32 + 0.5) / SUBMERSION_SAMPLES as f32;
+                is_in_fluid(&chunk_sys, vec3(position.x, y, position.z))
+            })
+            .count();
+        ctl.submersion = in_fluid as f32 / SUBMERSION_SAMPLES as f32;
+        ctl.is_swimming = !ctl.is_flying && ctl.submersion >= SWIM_SUBMERSION;
+        ctl.is_eye_in_fluid = is_in_fluid(&chunk_sys, position.0 + Vec3::Y * EYE_HEIGHT);
+
+        // Flying. Buoyancy in fluid.
+        gravity_scale.0 = if ctl.is_flying { 0. } else { 2. * (1. - ctl.submersion * FLUID_BUOYANCY) };
 
         if ctl.enable_input {
             // View Rotation
@@ -257,6 +313,7 @@ fn input_move(
 
             // Is Grouned
             // The character is grounded if the shape caster has a hit with a normal that isn't too steep.
+            let was_grounded = ctl.is_grounded;
             ctl.is_grounded = hits.iter().any(|hit| {
                 // if ctl.max_slope_angle == 0. {
                 //     true
@@ -267,6 +324,10 @@ fn input_move(
                 // }
             });
 
+            if ctl.is_grounded && !was_grounded && !ctl.is_flying {
+                ctl.landed_velocity = Some(-linvel.y);
+            }
+
             // Fly Move
             if ctl.is_flying {
                 if ctl.is_sneaking {
@@ -278,7 +339,7 @@ fn input_move(
             }
             // Fly Toggle: Double Space
             let time_now = time.elapsed_secs();
-            if is_jump_just_pressed {
+            if is_jump_just_pressed && ctl.can_fly {
                 unsafe {
                     static mut LAST_FLY_JUMP: f32 = 0.;
                     if time_now - LAST_FLY_JUMP < 0.3 {
@@ -312,6 +373,16 @@ fn input_move(
                 }
             }
 
+            // Swim Up / Down
+            if ctl.is_swimming {
+                if is_jump_hold {
+                    linvel.y += SWIM_ACCELERATION * dt_sec;
+                }
+                if ctl.is_sneaking {
+                    linvel.y -= SWIM_ACCELERATION * dt_sec;
+                }
+            }
+
             // Jump
             if is_jump_hold && ctl.is_grounded && !ctl.is_flying {
                 unsafe {
@@ -365,6 +436,11 @@ fn input_move(
             // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
             linvel.x *= damping_factor;
             linvel.z *= damping_factor;
+
+            // Fluid Drag, on all axes.
+            if ctl.submersion > 0. {
+                linvel.0 *= FLUID_DRAG.powf(dt_sec * ctl.submersion);
+            }
         }
         // if ctl.flying {
         //     linvel.0 *= damping_factor;
@@ -391,7 +467,7 @@ fn sync_camera(
             // if !input_key.pressed(KeyCode::AltLeft) {
             cam_trans.rotation = Quat::from_euler(EulerRot::YXZ, ctl.yaw, ctl.pitch, 0.0);
             // }
-            cam_trans.translation = char_pos.0 + Vec3::new(0., 0.8, 0.) + cam_trans.forward() * -ctl.cam_distance;
+            cam_trans.translation = char_pos.0 + Vec3::Y * EYE_HEIGHT + cam_trans.forward() * -ctl.cam_distance;
 
             // Smoothed FOV on sprinting
             fov_val.target = if input_key.pressed(KeyCode::KeyC) {
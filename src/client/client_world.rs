@@ -7,11 +7,13 @@ use bevy::core_pipeline::Skybox;
 use bevy::pbr::{ScreenSpaceReflections, VolumetricFog, VolumetricLight};
 use bevy_renet::netcode::NetcodeClientTransport;
 use bevy_renet::renet::RenetClient;
+use avian3d::prelude::{ColliderDisabled, LinearVelocity, Position};
 
 use crate::client::prelude::*;
-use crate::net::{CPacket, RenetClientHelper};
+use crate::net::{CPacket, ConnectionState, RenetClientHelper};
 use crate::prelude::*;
 use crate::util::TimeIntervals;
+use crate::item::recipe::CRAFTING_GRID;
 
 pub fn init(app: &mut App) {
     app.register_type::<WorldInfo>();
@@ -25,31 +27,56 @@ pub fn init(app: &mut App) {
     app.add_systems(First, on_world_init.run_if(condition::load_world)); // Camera, Player, Sun
     app.add_systems(Last, on_world_exit.run_if(condition::unload_world()));
     app.add_systems(Update, tick_world.run_if(condition::in_world)); // Sun, World Timing.
+    app.add_systems(Update, sync_hotbar_index.run_if(condition::in_world));
+    app.add_systems(Update, sync_load_distance.run_if(condition::in_world));
+    app.add_systems(Update, apply_game_mode.run_if(condition::in_world));
+    app.add_systems(Update, (send_player_land, apply_player_teleport).run_if(condition::in_world));
 }
 
 #[derive(Resource, Reflect)]
 #[reflect(Resource)]
 pub struct ClientPlayerInfo {
+    // synced from the server. modified by sending CPacket::InventoryClick etc.
     #[reflect(ignore)]
     pub inventory: Inventory,
+    // the stack held by the mouse in the inventory ui
+    #[reflect(ignore)]
+    pub cursor: ItemStack,
+    // synced from the server by SPacket::CraftingContent
+    #[reflect(ignore)]
+    pub crafting_grid: Inventory,
+    #[reflect(ignore)]
+    pub crafting_result: ItemStack,
 
     pub hotbar_index: u32,
 
+    // synced from the server by SPacket::PlayerGameMode
+    pub game_mode: GameMode,
+
+    // synced from the server by SPacket::PlayerHealth. 0: dead
     pub health: u32,
     pub health_max: u32,
+
+    // move the local player there. e.g. on respawn
+    pub pending_teleport: Option<Vec3>,
 }
 
 impl ClientPlayerInfo {
-    pub const HOTBAR_SLOTS: u32 = 9;
+    pub const HOTBAR_SLOTS: u32 = Inventory::HOTBAR_SLOTS as u32;
 }
 
 impl Default for ClientPlayerInfo {
     fn default() -> Self {
         Self {
-            inventory: Inventory::new(36),
+            inventory: Inventory::new(Inventory::PLAYER_SLOTS),
+            cursor: ItemStack::default(),
+            crafting_grid: Inventory::new(CRAFTING_GRID * CRAFTING_GRID),
+            crafting_result: ItemStack::default(),
             hotbar_index: 0,
+            game_mode: GameMode::default(),
             health: 20,
             health_max: 20,
+            pending_teleport: None,
         }
     }
 }
@@ -249,15 +276,19 @@ fn reinterpret_skybox_cubemap(
     }
 }
 
+const UNDERWATER_FOG_COLOR: Color = Color::srgb(0.05, 0.2, 0.35);
+const UNDERWATER_FOG_VISIBILITY: f32 = 14.;
+
 fn tick_world(
     // #[cfg(feature = "target_native_os")] mut atmosphere: bevy_atmosphere::system_param::AtmosphereMut<bevy_atmosphere::prelude::Nishita>,
     mut query_sun: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
     mut worldinfo: ResMut<WorldInfo>,
     time: Res<Time>,
 
-    query_player: Query<&Transform, (With<CharacterController>, Without<Sun>)>,
+    query_player: Query<(&Transform, &CharacterController), Without<Sun>>,
     mut net_client: ResMut<RenetClient>,
-    mut last_player_pos: Local<Vec3>,
+    mut last_player_pos: Local<(Vec3, f32)>,
+    mut player_pos_seq: Local<u32>,
 
     mut query_fog: Query<&mut DistanceFog>,
     cli: Res<ClientInfo>,
@@ -287,18 +318,19 @@ fn tick_world(
     }
 
     // Send PlayerPos
-    if let Ok(player_loc) = query_player.get_single() {
+    if let Ok((player_loc, ctl)) = query_player.get_single() {
         let player_pos = player_loc.translation;
 
-        if player_pos.distance_squared(*last_player_pos) > 0.01 * 0.01 {
-            *last_player_pos = player_pos;
-            net_client.send_packet(&CPacket::PlayerPos { position: player_pos });
+        if player_pos.distance_squared(last_player_pos.0) > 0.01 * 0.01 || (ctl.yaw - last_player_pos.1).abs() > 0.01 {
+            *last_player_pos = (player_pos, ctl.yaw);
+            *player_pos_seq = player_pos_seq.wrapping_add(1);
+            net_client.send_packet(&CPacket::PlayerPos {
+                position: player_pos,
+                yaw: ctl.yaw,
+                seq: *player_pos_seq,
+            });
         }
     }
-    // net_client.send_packet(&CPacket::LoadDistance {
-    //     load_distance: cli.chunks_load_distance,
-    // }); // todo: Only Send after Edit Dist Config
-
     // Ping Network
     if time.at_interval(1.0) {
         net_client.send_packet(&CPacket::Ping {
@@ -316,6 +348,11 @@ fn tick_world(
     } else {
         fog.falloff = FogFalloff::from_visibility_squared(cli.sky_fog_visibility / 4.0);
     }
+    // Underwater: dense tinted fog.
+    if query_player.single().is_ok_and(|(_, ctl)| ctl.is_eye_in_fluid) {
+        fog.color = UNDERWATER_FOG_COLOR;
+        fog.falloff = FogFalloff::from_visibility(UNDERWATER_FOG_VISIBILITY);
+    }
 
     // Sun Pos
     let sun_angle = worldinfo.daytime * PI * 2.;
@@ -334,3 +371,86 @@ fn tick_world(
         light_trans.rotation = Quat::from_rotation_z(sun_angle) * Quat::from_rotation_y(PI / 2.3);
     }
 }
+
+/// adapt the local player controller to the game mode: flying, and no collision for spectators.
+fn apply_game_mode(
+    player: Res<ClientPlayerInfo>,
+    mut query: Query<(Entity, &mut CharacterController, Has<ColliderDisabled>)>,
+    mut cmds: Commands,
+) {
+    let mode = player.game_mode;
+    for (entity, mut ctl, collider_disabled) in query.iter_mut() {
+        if ctl.can_fly != mode.can_fly() {
+            ctl.can_fly = mode.can_fly();
+        }
+        if mode.noclip() && (!ctl.is_flying || ctl.unfly_on_ground) {
+            ctl.is_flying = true;
+            ctl.unfly_on_ground = false;
+        } else if !mode.noclip() && !ctl.unfly_on_ground {
+            ctl.unfly_on_ground = true;
+        }
+        if !ctl.can_fly && ctl.is_flying {
+            ctl.is_flying = false;
+        }
+        if mode.noclip() != collider_disabled {
+            if mode.noclip() {
+                cmds.entity(entity).insert(ColliderDisabled);
+            } else {
+                cmds.entity(entity).remove::<ColliderDisabled>();
+            }
+        }
+    }
+}
+
+/// report landings to the server, for fall damage.
+fn send_player_land(mut query: Query<&mut CharacterController>, cli: Res<ClientInfo>, mut net_client: ResMut<RenetClient>) {
+    for mut ctl in query.iter_mut() {
+        let Some(fall_velocity) = ctl.landed_velocity.take() else {
+            continue;
+        };
+        if cli.conn_state == ConnectionState::Play {
+            net_client.send_packet(&CPacket::PlayerLand { fall_velocity });
+        }
+    }
+}
+
+fn apply_player_teleport(
+    mut player: ResMut<ClientPlayerInfo>,
+    mut query: Query<(&mut Transform, &mut Position, &mut LinearVelocity), With<CharacterController>>,
+) {
+    let Some(pos) = player.pending_teleport else {
+        return;
+    };
+    // keep it until the player entity is spawned.
+    let Ok((mut trans, mut position, mut linvel)) = query.single_mut() else {
+        return;
+    };
+    trans.translation = pos;
+    position.0 = pos;
+    linvel.0 = Vec3::ZERO;
+    player.pending_teleport = None;
+}
+
+/// send the hotbar selection to the server when changed.
+fn sync_hotbar_index(player: Res<ClientPlayerInfo>, cli: Res<ClientInfo>, mut net_client: ResMut<RenetClient>, mut last_sent: Local<Option<u32>>) {
+    if cli.conn_state == ConnectionState::Play && *last_sent != Some(player.hotbar_index) {
+        *last_sent = Some(player.hotbar_index);
+        net_client.send_packet(&CPacket::HotbarSelect {
+            index: player.hotbar_index as u8,
+        });
+    }
+}
+
+/// send the chunks load distance after login, and when the setting changes.
+fn sync_load_distance(cfg: Res<ClientSettings>, cli: Res<ClientInfo>, mut net_client: ResMut<RenetClient>, mut last_sent: Local<Option<IVec2>>) {
+    if cli.conn_state != ConnectionState::Play {
+        *last_sent = None; // send again on the next login.
+        return;
+    }
+    if *last_sent != Some(cfg.chunks_load_distance) {
+        *last_sent = Some(cfg.chunks_load_distance);
+        net_client.send_packet(&CPacket::LoadDistance {
+            load_distance: cfg.chunks_load_distance,
+        });
+    }
+}
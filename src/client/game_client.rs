@@ -1,7 +1,7 @@
-use std::net::ToSocketAddrs;
+use std::{net::ToSocketAddrs, path::PathBuf};
 
 use bevy::{ecs::system::SystemParam, math::vec3, pbr::DirectionalLightShadowMap, prelude::*};
-use bevy_renet::renet::RenetClient;
+use bevy_renet::{netcode::ClientAuthentication, renet::RenetClient};
 use avian3d::prelude::*;
 
 #[cfg(feature = "target_native_os")]
@@ -9,8 +9,8 @@ use bevy_atmosphere::prelude::*;
 
 use crate::client::prelude::*;
 use crate::item::ItemPlugin;
-use crate::net::{CPacket, ClientNetworkPlugin, RenetClientHelper};
-use crate::server::prelude::IntegratedServerPlugin;
+use crate::net::{CPacket, ClientNetworkPlugin, ConnectionState, RenetClientHelper};
+use crate::server::prelude::IntegratedServer;
 use crate::ui::prelude::*;
 use crate::voxel::ClientVoxelPlugin;
 
@@ -59,7 +59,6 @@ impl Plugin for ClientGamePlugin {
 
         // Network
         app.add_plugins(ClientNetworkPlugin); // Client Network
-        app.add_plugins(IntegratedServerPlugin);
 
         // ClientInfo
         app.insert_resource(ClientInfo::default());
@@ -70,6 +69,8 @@ impl Plugin for ClientGamePlugin {
 
         // World
         super::client_world::init(app);
+        super::interpolation::init(app); // Remote Entities
+        super::item_drop::init(app);
 
         // Debug
         {
@@ -148,6 +149,8 @@ pub struct ClientInfo {
     pub disconnected_reason: String,
     pub ping: (u64, i64, i64, u64),     // ping. (rtt, c2s, ping-begin) in ms.
     pub playerlist: Vec<(String, u32)>, // as same as SPacket::PlayerList. username, ping.
+    #[reflect(ignore)]
+    pub conn_state: ConnectionState,
 
     // Debug Draw
     pub dbg_text: bool,
@@ -180,6 +183,7 @@ impl Default for ClientInfo {
             disconnected_reason: String::new(),
             ping: (0, 0, 0, 0),
             playerlist: Vec::new(),
+            conn_state: ConnectionState::Handshake,
             server_addr: String::new(),
 
             dbg_text: false,
@@ -224,6 +228,24 @@ impl<'w, 's> EthertiaClient<'w, 's> {
     }
 
     pub fn connect_server(&mut self, server_addr: String) {
+        self.connect(server_addr, true);
+    }
+
+    /// connect to the Integrated Server, which runs in Unsecure auth mode.
+    pub fn connect_local_server(&mut self, server_addr: String) {
+        self.connect(server_addr, false);
+    }
+
+    /// start the Integrated Server of the local world, and join it. the previous one is stopped.
+    /// open_to_lan: other players in the local network can find and join it.
+    pub fn open_local_world(&mut self, world_dir: PathBuf, open_to_lan: bool) {
+        let server = IntegratedServer::start(world_dir, open_to_lan, &self.cfg.username);
+        let server_addr = format!("127.0.0.1:{}", server.port);
+        self.cmds.insert_resource(server);
+        self.connect_local_server(server_addr);
+    }
+
+    fn connect(&mut self, server_addr: String, secure: bool) {
         info!("Connecting to {}", server_addr);
 
         let mut addrs = match server_addr.trim().to_socket_addrs() {
@@ -242,23 +264,45 @@ impl<'w, 's> EthertiaClient<'w, 's> {
             }
         };
 
+        let username = self.cfg.username.clone();
+        let (token_file, auth_service_url) = if secure {
+            (self.cfg.auth_token_file.as_str(), self.cfg.auth_service_url.as_str())
+        } else {
+            ("", "")
+        };
+        let authentication = match crate::net::auth::client_authentication(token_file, auth_service_url, addr, &username) {
+            Ok(authentication) => authentication,
+            Err(err) => {
+                error!("Failed to authenticate: {}", err);
+                self.clientinfo.disconnected_reason = format!("Failed to authenticate: {}", err);
+                self.data().curr_ui = CurrentUI::DisconnectedReason;
+                return;
+            }
+        };
+        // the user id is bound to the ConnectToken in secure mode.
+        let uuid = match &authentication {
+            ClientAuthentication::Secure { connect_token } => connect_token.client_id,
+            ClientAuthentication::Unsecure { .. } => crate::net::auth::user_id_of(&username),
+        };
+
         self.data().curr_ui = CurrentUI::ConnectingServer;
         self.clientinfo.server_addr.clone_from(&server_addr);
 
-        let mut net_client = RenetClient::new(bevy_renet::renet::ConnectionConfig::default());
+        let mut net_client = RenetClient::new(crate::net::connection_config());
 
-        let username = &self.cfg.username;
+        net_client.send_packet(&CPacket::Handshake {
+            protocol_version: crate::net::PROTOCOL_VERSION,
+            next_state: ConnectionState::Login,
+        });
         net_client.send_packet(&CPacket::Login {
-            uuid: crate::util::hashcode(username),
-            access_token: 123,
-            username: username.clone(),
+            uuid,
+            access_token: 0,
+            username,
         });
 
         self.cmds.insert_resource(net_client);
-        self.cmds.insert_resource(crate::net::new_netcode_client_transport(
-            addr,
-            Some("userData123".to_string().into_bytes()),
-        ));
+        self.cmds.insert_resource(crate::net::new_netcode_client_transport(authentication));
+        self.clientinfo.conn_state = ConnectionState::Login;
 
         // clear DisconnectReason on new connect, to prevents display old invalid reason.
         self.clientinfo.disconnected_reason.clear();
@@ -274,6 +318,8 @@ impl<'w, 's> EthertiaClient<'w, 's> {
 
     pub fn exit_world(&mut self) {
         self.cmds.remove_resource::<WorldInfo>();
+        // stop the Integrated Server if it's a local world, saves the world.
+        self.cmds.remove_resource::<IntegratedServer>();
         self.data().curr_ui = CurrentUI::MainMenu;
     }
 }
@@ -102,7 +102,7 @@ pub fn input_handle(
     let mut window = query_window.single_mut().unwrap();
 
     // ESC
-    if action_state.just_pressed(&InputAction::ESC) {
+    if action_state.just_pressed(&InputAction::ESC) && cli.curr_ui != CurrentUI::Respawn {
         if worldinfo.is_some() {
             cli.curr_ui = if cli.curr_ui == CurrentUI::None {
                 CurrentUI::PauseMenu
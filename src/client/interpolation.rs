@@ -0,0 +1,172 @@
+//! Remote Entity Snapshot Interpolation.
+//!
+//! EntityPos packets are buffered per entity as snapshots, stamped with the server tick.
+//! Remote entities are rendered at `interp_delay_ms` behind the latest known server tick, interpolated between the two snapshots around it.
+//! If the snapshots run out (packet loss), extrapolate by the last velocity for at most `extrap_limit_ms`, then hold.
+
+use std::{collections::VecDeque, f32::consts::PI};
+
+use bevy::platform::collections::HashMap;
+
+use crate::client::prelude::*;
+use crate::net::{replication::EntityMap, seq_newer, EntityId};
+use crate::prelude::*;
+
+pub fn init(app: &mut App) {
+    app.insert_resource(EntitySnapshots::default());
+
+    app.add_systems(Update, interpolate_entities.run_if(condition::in_world));
+}
+
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub position: Vec3,
+    pub yaw: f32,
+}
+
+#[derive(Default)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>, // ordered by tick
+    last_seq: u32,
+}
+
+impl SnapshotBuffer {
+    const MAX_SNAPSHOTS: usize = 64;
+
+    fn push(&mut self, snapshot: Snapshot) {
+        // in the same tick, the latest wins.
+        while self.snapshots.back().is_some_and(|s| s.tick >= snapshot.tick) {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(snapshot);
+        if self.snapshots.len() > Self::MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// sample the state at the render tick. None if no snapshots.
+    fn sample(&self, render_tick: f64, extrap_ticks: f64) -> Option<(Vec3, f32)> {
+        let first = self.snapshots.front()?;
+        if render_tick <= first.tick as f64 {
+            return Some((first.position, first.yaw));
+        }
+        // interpolate between the two snapshots around the render tick
+        for (a, b) in self.snapshots.iter().zip(self.snapshots.iter().skip(1)) {
+            if render_tick <= b.tick as f64 {
+                let t = ((render_tick - a.tick as f64) / (b.tick - a.tick) as f64) as f32;
+                return Some((a.position.lerp(b.position, t), lerp_angle(a.yaw, b.yaw, t)));
+            }
+        }
+        // ran out of snapshots. extrapolate by the last velocity, briefly.
+        let last = self.snapshots.back()?;
+        let Some(prev) = self.snapshots.iter().rev().nth(1) else {
+            return Some((last.position, last.yaw));
+        };
+        let dt = (render_tick - last.tick as f64).min(extrap_ticks) as f32;
+        let vel = (last.position - prev.position) / (last.tick - prev.tick) as f32;
+        Some((last.position + vel * dt, last.yaw))
+    }
+
+    /// drop the snapshots no longer needed. keeps the one before the render tick.
+    fn prune(&mut self, render_tick: f64) {
+        while self.snapshots.len() > 2 && self.snapshots[1].tick as f64 <= render_tick {
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let d = (b - a + PI).rem_euclid(2. * PI) - PI; // shortest
+    a + d * t
+}
+
+/// Snapshot buffers of remote entities, and the render clock in server ticks.
+#[derive(Resource, Default)]
+pub struct EntitySnapshots {
+    pub entities: HashMap<EntityId, SnapshotBuffer>,
+
+    // server ticks per second, from LoginSuccess. 0 until logged in.
+    pub tick_rate: u32,
+
+    // latest received server tick, and the local time it was received at.
+    latest_tick: u64,
+    latest_tick_at: f64,
+
+    // the interpolated render time, in server ticks. negative: not synced yet.
+    render_tick: f64,
+}
+
+impl EntitySnapshots {
+    pub fn reset(&mut self, tick_rate: u32) {
+        *self = Self {
+            tick_rate,
+            render_tick: -1.,
+            ..default()
+        };
+    }
+
+    /// returns false if the snapshot is outdated (reordered).
+    pub fn receive(&mut self, entity_id: EntityId, seq: u32, snapshot: Snapshot, now: f64) -> bool {
+        let buf = self.entities.entry(entity_id).or_default();
+        if !seq_newer(seq, buf.last_seq) {
+            return false;
+        }
+        buf.last_seq = seq;
+        buf.push(snapshot);
+
+        if snapshot.tick > self.latest_tick {
+            self.latest_tick = snapshot.tick;
+            self.latest_tick_at = now;
+        }
+        true
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId) {
+        self.entities.remove(&entity_id);
+    }
+}
+
+fn interpolate_entities(
+    mut snapshots: ResMut<EntitySnapshots>,
+    mut query_transform: Query<&mut Transform>,
+    entity_map: Res<EntityMap>,
+    cfg: Res<ClientSettings>,
+    time: Res<Time>,
+) {
+    if snapshots.tick_rate == 0 || snapshots.entities.is_empty() {
+        return;
+    }
+    let snapshots = &mut *snapshots;
+    let rate = snapshots.tick_rate as f64;
+    let now = time.elapsed_secs_f64();
+
+    // estimate the current server tick, minus the interpolation delay.
+    let server_tick = snapshots.latest_tick as f64 + (now - snapshots.latest_tick_at) * rate;
+    let target_tick = server_tick - cfg.interp_delay_ms as f64 / 1000. * rate;
+
+    // advance the render clock smoothly, snap if it's too far off (e.g. first sync, long stall).
+    let drift = target_tick - snapshots.render_tick;
+    if snapshots.render_tick < 0. || drift.abs() > rate {
+        snapshots.render_tick = target_tick;
+    } else {
+        snapshots.render_tick += time.delta_secs_f64() * rate + drift * (time.delta_secs_f64() * 2.).min(1.);
+    }
+    let render_tick = snapshots.render_tick;
+    let extrap_ticks = cfg.extrap_limit_ms as f64 / 1000. * rate;
+
+    for (entity_id, buf) in snapshots.entities.iter_mut() {
+        buf.prune(render_tick);
+
+        let Some((position, yaw)) = buf.sample(render_tick, extrap_ticks) else {
+            continue;
+        };
+        let Some(entity) = entity_map.get(*entity_id) else {
+            continue;
+        };
+        if let Ok(mut trans) = query_transform.get_mut(entity) {
+            trans.translation = position;
+            trans.rotation = Quat::from_rotation_y(yaw);
+        }
+    }
+}
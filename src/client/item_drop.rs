@@ -0,0 +1,64 @@
+//! Item Drop Visuals. a spinning quad of the item's icon, see `Items::icon`.
+
+use bevy::render::mesh::VertexAttributeValues;
+
+use crate::client::prelude::*;
+use crate::item::{ItemStack, Items};
+use crate::prelude::*;
+
+pub fn init(app: &mut App) {
+    app.add_systems(Update, (spawn_item_drop_visuals, spin_item_drops).run_if(condition::in_world));
+}
+
+/// Client: the item of a replicated item drop entity.
+#[derive(Component)]
+pub struct ItemDropVisual(pub ItemStack);
+
+// Marker: the spinning child mesh
+#[derive(Component)]
+struct ItemDropSpin;
+
+const SPIN_SPEED: f32 = 2.;
+
+fn spawn_item_drop_visuals(
+    query: Query<(Entity, &ItemDropVisual), Added<ItemDropVisual>>,
+    items: Res<Items>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cmds: Commands,
+) {
+    for (entity, visual) in query.iter() {
+        let icon = items.icon(visual.0.item_id);
+
+        let mut mesh = Mesh::from(Rectangle::new(0.4, 0.4));
+        if let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+            for uv in uvs.iter_mut() {
+                let p = icon.uv.min + Vec2::from(*uv) * icon.uv.size();
+                *uv = p.to_array();
+            }
+        }
+
+        cmds.entity(entity).with_children(|parent| {
+            parent.spawn((
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(icon.atlas),
+                    alpha_mode: AlphaMode::Mask(0.5),
+                    cull_mode: None,
+                    double_sided: true,
+                    ..default()
+                })),
+                Transform::from_xyz(0., 0.25, 0.),
+                ItemDropSpin,
+            ));
+        });
+    }
+}
+
+fn spin_item_drops(mut query: Query<&mut Transform, With<ItemDropSpin>>, time: Res<Time>) {
+    let t = time.elapsed_secs();
+    for mut trans in query.iter_mut() {
+        trans.rotation = Quat::from_rotation_y(t * SPIN_SPEED);
+        trans.translation.y = 0.25 + (t * 2.).sin() * 0.05; // bob
+    }
+}
@@ -4,6 +4,9 @@ pub mod ui;
 
 mod client_world;
 mod input;
+mod interpolation;
+mod item_drop;
+pub mod saves;
 mod settings;
 
 pub mod prelude {
@@ -12,10 +15,13 @@ pub mod prelude {
     pub use client_world::{ClientPlayerInfo, DespawnOnWorldUnload, WorldInfo};
     pub use game_client::{condition, ClientGamePlugin, ClientInfo, EthertiaClient};
     pub use input::InputAction;
+    pub use interpolation::{EntitySnapshots, Snapshot};
+    pub use item_drop::ItemDropVisual;
     pub use settings::{ClientSettings, ServerListItem};
     pub use ui::{CurrentUI, UiExtra};
 
     pub use crate::item::{Inventory, ItemStack};
+    pub use crate::server::game_mode::GameMode;
 }
 
 #[cfg(feature = "target_native_os")]
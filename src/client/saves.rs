@@ -0,0 +1,132 @@
+//! Local Worlds (Singleplayer), in the saves directory.
+//!
+//! Each world is a server world directory `saves/<dir>/`: level.json (the metadata, see `LevelData`),
+//! chunks/ (see `ChunkLoader`) and playerdata/. Opened by the Integrated Server.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+use crate::server::prelude::{GameMode, LevelData};
+
+pub const SAVES_DIR: &str = "saves";
+
+#[derive(Debug, Clone)]
+pub struct LocalWorld {
+    pub dir: PathBuf,
+    pub level: LevelData,
+    // bytes of the whole directory
+    pub size: u64,
+}
+
+impl LocalWorld {
+    fn load(dir: PathBuf) -> Self {
+        Self {
+            level: LevelData::load(&dir),
+            size: dir_size(&dir),
+            dir,
+        }
+    }
+
+    /// all worlds in the saves directory, the last modified first.
+    pub fn list() -> Vec<LocalWorld> {
+        let Ok(entries) = std::fs::read_dir(SAVES_DIR) else {
+            return Vec::new();
+        };
+        let mut worlds: Vec<LocalWorld> = entries
+            .filter_map(|e| Some(e.ok()?.path()))
+            .filter(|p| p.join("level.json").is_file())
+            .map(LocalWorld::load)
+            .collect();
+        worlds.sort_by(|a, b| b.level.time_modified.cmp(&a.level.time_modified));
+        worlds
+    }
+
+    pub fn create(name: &str, seed: u64, game_mode: GameMode) -> io::Result<LocalWorld> {
+        let dir = unique_dir(name);
+        std::fs::create_dir_all(&dir)?;
+
+        let mut level = LevelData {
+            name: name.into(),
+            seed,
+            game_mode,
+            ..default()
+        };
+        level.try_save(&dir)?;
+        info!("Created world {} in {}", name, dir.display());
+        Ok(LocalWorld::load(dir))
+    }
+
+    /// rename the world. the directory stays. the name is trimmed, and can't be empty.
+    pub fn rename(&mut self, name: &str) -> io::Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The world name can't be empty"));
+        }
+        self.level.name = name.into();
+        self.level.try_save(&self.dir)
+    }
+
+    pub fn delete(&self) -> io::Result<()> {
+        info!("Deleting world {}", self.dir.display());
+        std::fs::remove_dir_all(&self.dir)
+    }
+
+    /// copy the world into a new directory, named "<name> Copy".
+    pub fn duplicate(&self) -> io::Result<LocalWorld> {
+        let name = format!("{} Copy", self.level.name);
+        let dir = unique_dir(&name);
+        copy_dir(&self.dir, &dir)?;
+
+        let mut copy = LocalWorld::load(dir);
+        copy.rename(&name)?;
+        Ok(copy)
+    }
+}
+
+/// a new directory in the saves for the world name. invalid chars are replaced, and suffixed if exists.
+fn unique_dir(name: &str) -> PathBuf {
+    let mut base: String = name.trim().chars().map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' { c } else { '_' }).collect();
+    if base.is_empty() {
+        base = "World".into();
+    }
+    let saves = Path::new(SAVES_DIR);
+    let mut dir = saves.join(&base);
+    let mut n = 1;
+    while dir.exists() {
+        dir = saves.join(format!("{base} ({n})"));
+        n += 1;
+    }
+    dir
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&e.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dst = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dst)?;
+        } else {
+            std::fs::copy(entry.path(), dst)?;
+        }
+    }
+    Ok(())
+}
@@ -32,6 +32,7 @@ pub fn build_plugin(app: &mut App) {
 
 #[derive(Resource, Deserialize, Serialize, Reflect)]
 #[reflect(Resource)]
+#[serde(default)]
 pub struct ClientSettings {
     #[reflect(ignore)]
     pub serverlist: Vec<ServerListItem>,
@@ -42,6 +43,17 @@ pub struct ClientSettings {
     pub vsync: bool,
 
     pub chunks_load_distance: IVec2,
+
+    // remote entities are rendered this far behind the server, interpolated. and extrapolated at most this long on packet loss.
+    pub interp_delay_ms: u32,
+    pub extrap_limit_ms: u32,
+
+    // Authentication. ConnectToken from the token file (offline testing) or the auth service url.
+    // both empty: Unsecure connection, only for LAN servers.
+    #[serde(default)]
+    pub auth_token_file: String,
+    #[serde(default)]
+    pub auth_service_url: String,
 }
 
 impl Default for ClientSettings {
@@ -54,6 +66,12 @@ impl Default for ClientSettings {
             vsync: true,
 
             chunks_load_distance: IVec2::new(4, 3),
+
+            interp_delay_ms: 100,
+            extrap_limit_ms: 250,
+
+            auth_token_file: String::new(),
+            auth_service_url: String::new(),
         }
     }
 }
@@ -188,8 +188,9 @@ pub fn hud_chat(
         });
 }
 
-pub fn hud_hotbar(mut ctx: EguiContexts, cfg: Res<ClientSettings>, mut player: ResMut<ClientPlayerInfo>,
+pub fn hud_hotbar(mut ctx: EguiContexts, cfg: Res<ClientSettings>, player: Res<ClientPlayerInfo>,
     mut voxbrush: ResMut<VoxelBrush>,
+    mut net_client: ResMut<RenetClient>,
     // chunk_sys: Res<ClientChunkSystem>,
 ) {
 
@@ -210,6 +211,11 @@ pub fn hud_hotbar(mut ctx: EguiContexts, cfg: Res<ClientSettings>, mut player: R
     //         }
     //     });
 
+    // spectators have no hotbar.
+    if !player.game_mode.can_interact() {
+        return;
+    }
+
     egui::Window::new("HUD Hotbar")
         .title_bar(false)
         .resizable(false)
@@ -217,7 +223,7 @@ pub fn hud_hotbar(mut ctx: EguiContexts, cfg: Res<ClientSettings>, mut player: R
         .frame(Frame::default().fill(Color32::from_black_alpha(0)))
         .show(ctx.ctx_mut().unwrap(), |ui| {
             // Health bar
-            {
+            if player.game_mode.takes_damage() {
                 let health_bar_size = Vec2::new(250., 4.);
                 let mut rect = ui.min_rect();
                 rect.set_height(health_bar_size.y);
@@ -238,10 +244,13 @@ pub fn hud_hotbar(mut ctx: EguiContexts, cfg: Res<ClientSettings>, mut player: R
             }
 
             ui.horizontal(|ui| {
-                for i in 0..ClientPlayerInfo::HOTBAR_SLOTS {
-                    let item = player.inventory.items.get_mut(i as usize).unwrap();
+                for i in 0..ClientPlayerInfo::HOTBAR_SLOTS as usize {
+                    let item = &player.inventory.items[i];
 
-                    ui_item_stack(ui, item);
+                    let resp = ui_item_stack(ui, item);
+                    if let Some(packet) = slot_click_packet(ui, &resp, i) {
+                        net_client.send_packet(&packet);
+                    }
                 }
             });
         });
@@ -1,39 +1,34 @@
-use bevy_egui::egui::Painter;
+use bevy::prelude::Res;
+use bevy_egui::egui::{Painter, Response};
 
 use crate::{
-    item::{Inventory, ItemStack},
+    client::client_world::ClientPlayerInfo,
+    item::{recipe::CRAFTING_GRID, Inventory, ItemStack, Recipes},
+    net::CPacket,
     ui::prelude::*,
 };
 
-pub fn ui_holding_item() -> &'static mut ItemStack {
-    static mut CURR_HOLD: ItemStack = ItemStack { count: 0, item_id: 0 };
-    unsafe { &mut CURR_HOLD }
-}
-
-pub fn draw_ui_holding_item(mut ctx: EguiContexts) {
-    let hold = ui_holding_item();
+/// draw the cursor stack (held by the mouse) of the inventory ui.
+pub fn draw_ui_holding_item(mut ctx: EguiContexts, player: Res<ClientPlayerInfo>) {
+    let hold = &player.cursor;
 
     if !hold.is_empty() {
-        let curpos = ctx.ctx_mut().unwrap().pointer_latest_pos().unwrap();
+        let Some(curpos) = ctx.ctx_mut().unwrap().pointer_latest_pos() else {
+            return;
+        };
         let size = vec2(50., 50.);
 
-        draw_item(&hold, Rect::from_min_size(curpos - size / 2., size), &ctx.ctx_mut().unwrap().debug_painter());
+        draw_item(hold, Rect::from_min_size(curpos - size / 2., size), &ctx.ctx_mut().unwrap().debug_painter());
     }
 }
 
 pub fn draw_item(slot: &ItemStack, rect: Rect, painter: &Painter) {
     let reg = unsafe { &*crate::item::_ITEMS_REG };
-    let num_all_items = reg.reg.len();
 
     // Item Texture
-    let uv_siz = 1. / num_all_items as f32;
-    let uv_x = uv_siz * (slot.item_id - 1) as f32;
-    painter.image(
-        reg.atlas_egui,
-        rect.shrink(3.),
-        Rect::from_min_size(pos2(uv_x, 0.), vec2(uv_siz, 1.)),
-        Color32::WHITE,
-    );
+    let icon = reg.icon(slot.item_id);
+    let uv = Rect::from_min_max(pos2(icon.uv.min.x, icon.uv.min.y), pos2(icon.uv.max.x, icon.uv.max.y));
+    painter.image(icon.atlas_egui, rect.shrink(3.), uv, Color32::WHITE);
     // Item Count
     painter.text(
         rect.max - vec2(4., 2.),
@@ -44,7 +39,7 @@ pub fn draw_item(slot: &ItemStack, rect: Rect, painter: &Painter) {
     );
 }
 
-pub fn ui_item_stack(ui: &mut egui::Ui, slot: &mut ItemStack) {
+pub fn ui_item_stack(ui: &mut egui::Ui, slot: &ItemStack) -> Response {
     let reg = unsafe { &*crate::item::_ITEMS_REG };
     let num_all_items = reg.reg.len();
 
@@ -62,28 +57,93 @@ pub fn ui_item_stack(ui: &mut egui::Ui, slot: &mut ItemStack) {
             if let Some(name) = reg.reg.at((slot.item_id - 1) as u16) {
                 ui.label(name);
                 ui.small(format!("{} [{}/{}] x{}", name, slot.item_id, num_all_items, slot.count));
+                if slot.durability > 0 {
+                    ui.small(format!("Durability {}", slot.durability));
+                }
             }
         });
 
         draw_item(slot, resp.rect, ui.painter())
     }
+    resp
+}
 
-    let hold = ui_holding_item();
-
-    if resp.clicked() {
-        ItemStack::swap(hold, slot);
+/// the inventory action of a click on the slot. the inventory is server-side, the result comes back by SPacket::InventorySlots.
+/// - click: put/swap the cursor stack. shift-click: quick move.
+/// - right-click: take half / put one.
+pub fn slot_click_packet(ui: &egui::Ui, resp: &Response, slot: usize) -> Option<CPacket> {
+    let slot = slot as u16;
+    if resp.clicked() && ui.input(|i| i.modifiers.shift) {
+        Some(CPacket::InventoryTransfer { slot })
+    } else if resp.clicked() {
+        Some(CPacket::InventoryClick { slot, secondary: false })
     } else if resp.secondary_clicked() {
-        crate::util::as_mut(slot).count += 1;
-        crate::util::as_mut(slot).item_id += 1;
+        Some(CPacket::InventoryClick { slot, secondary: true })
+    } else {
+        None
     }
 }
 
-pub fn ui_inventory(ui: &mut egui::Ui, inv: &mut Inventory) -> InnerResponse<()> {
+/// returns the action packet if a slot is clicked.
+pub fn ui_inventory(ui: &mut egui::Ui, inv: &Inventory) -> InnerResponse<Option<CPacket>> {
     ui.with_layout(egui::Layout::left_to_right(egui::Align::Min).with_main_wrap(true), |ui| {
         ui.style_mut().spacing.item_spacing = vec2(4., 4.);
 
-        for item in inv.items.iter_mut() {
-            ui_item_stack(ui, item);
+        let mut action = None;
+        for (i, item) in inv.items.iter().enumerate() {
+            let resp = ui_item_stack(ui, item);
+            action = action.or(slot_click_packet(ui, &resp, i));
         }
+        action
     })
 }
+
+/// the crafting grid and its result slot. returns the action packet if a slot is clicked.
+pub fn ui_crafting(ui: &mut egui::Ui, grid: &Inventory, result: &ItemStack) -> Option<CPacket> {
+    let mut action = None;
+    ui.horizontal(|ui| {
+        egui::Grid::new("crafting_grid").spacing(vec2(4., 4.)).show(ui, |ui| {
+            for (i, stack) in grid.items.iter().enumerate() {
+                let resp = ui_item_stack(ui, stack);
+                if resp.clicked() || resp.secondary_clicked() {
+                    action = Some(CPacket::CraftingClick {
+                        slot: i as u16,
+                        secondary: resp.secondary_clicked(),
+                    });
+                }
+                if (i + 1) % CRAFTING_GRID == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.label("→");
+
+        let resp = ui_item_stack(ui, result);
+        if resp.clicked() {
+            let all = ui.input(|i| i.modifiers.shift);
+            action = Some(CPacket::CraftingTakeResult { all });
+        }
+    });
+    action
+}
+
+/// Recipe Book: the recipes craftable with the items in the inventory. returns the action packet if one is clicked.
+pub fn ui_recipe_book(ui: &mut egui::Ui, recipes: &Recipes, inv: &Inventory) -> Option<CPacket> {
+    let craftable = recipes.craftable(inv);
+    if craftable.is_empty() {
+        ui.small("Nothing craftable");
+        return None;
+    }
+    let mut action = None;
+    ui.with_layout(egui::Layout::left_to_right(egui::Align::Min).with_main_wrap(true), |ui| {
+        ui.style_mut().spacing.item_spacing = vec2(4., 4.);
+
+        for recipe in craftable {
+            let resp = ui_item_stack(ui, &recipe.result).on_hover_text(format!("Craft {}", recipe.id));
+            if resp.clicked() {
+                action = Some(CPacket::CraftRecipe { recipe: recipe.id.clone() });
+            }
+        }
+    });
+    action
+}
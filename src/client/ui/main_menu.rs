@@ -4,7 +4,11 @@ use bevy_egui::{
     EguiContexts,
 };
 
+use bevy_renet::renet::RenetClient;
+
 use crate::client::prelude::*;
+use crate::item::Recipes;
+use crate::net::{CPacket, RenetClientHelper};
 use crate::{client::client_world::ClientPlayerInfo, ui::prelude::*};
 
 pub fn ui_main_menu(
@@ -110,11 +114,22 @@ pub fn ui_main_menu(
 pub fn ui_pause_menu(
     mut ctx: EguiContexts,
     mut cli: EthertiaClient,
-    mut player: ResMut<ClientPlayerInfo>,
-    // mut net_client: ResMut<RenetClient>,
+    player: Res<ClientPlayerInfo>,
+    recipes: Res<Recipes>,
+    mut net_client: ResMut<RenetClient>,
 ) {
     egui::Window::new("Inventory").show(ctx.ctx_mut().unwrap(), |ui| {
-        ui_inventory(ui, &mut player.inventory);
+        let mut action = ui_crafting(ui, &player.crafting_grid, &player.crafting_result);
+        ui.separator();
+        action = action.or(ui_inventory(ui, &player.inventory).inner);
+        ui.separator();
+        ui.collapsing("Recipe Book", |ui| {
+            action = action.take().or(ui_recipe_book(ui, &recipes, &player.inventory));
+        });
+
+        if let Some(packet) = action {
+            net_client.send_packet(&packet);
+        }
     });
 
     super::new_egui_window("Pause")
@@ -192,3 +207,24 @@ pub fn ui_pause_menu(
     //         // });
     //     });
 }
+
+pub fn ui_respawn(mut ctx: EguiContexts, mut cli: EthertiaClient, mut net_client: ResMut<RenetClient>) {
+    super::new_egui_window("Respawn").show(ctx.ctx_mut().unwrap(), |ui| {
+        let h = ui.available_height();
+
+        ui.vertical_centered(|ui| {
+            ui.add_space(h * 0.2);
+
+            ui.heading(RichText::new("You Died!").color(Color32::LIGHT_RED));
+
+            ui.add_space(h * 0.3);
+
+            if ui.btn_normal("Respawn").clicked() {
+                net_client.send_packet(&CPacket::Respawn);
+            }
+            if ui.btn_normal("Title screen").clicked() {
+                cli.exit_world();
+            }
+        });
+    });
+}
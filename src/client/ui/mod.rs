@@ -6,7 +6,7 @@ pub mod serverlist;
 mod settings;
 
 pub mod prelude {
-    pub use super::items::{ui_inventory, ui_item_stack};
+    pub use super::items::{slot_click_packet, ui_crafting, ui_inventory, ui_item_stack, ui_recipe_book};
     pub use super::sfx_play;
     pub use super::CurrentUI;
     pub use super::UiExtra;
@@ -42,6 +42,7 @@ impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<UiState>();
         app.insert_resource(hud::ChatHistory::default());
+        app.init_resource::<serverlist::LanListener>();
         if !app.is_plugin_added::<EguiPlugin>() || true {
             app.add_plugins(EguiPlugin::default());
         }
@@ -73,11 +74,13 @@ impl Plugin for UiPlugin {
                     (
                         settings::ui_settings.run_if(condition::in_ui(CurrentUI::Settings)),
                         main_menu::ui_pause_menu.run_if(condition::in_ui(CurrentUI::PauseMenu)),
+                        main_menu::ui_respawn.run_if(condition::in_ui(CurrentUI::Respawn)),
                         // Menus
                         main_menu::ui_main_menu.run_if(condition::in_ui(CurrentUI::MainMenu)),
                         serverlist::ui_localsaves.run_if(condition::in_ui(CurrentUI::LocalWorldList)),
                         serverlist::ui_create_world.run_if(condition::in_ui(CurrentUI::LocalWorldNew)),
                         serverlist::ui_serverlist.run_if(condition::in_ui(CurrentUI::ServerList)),
+                        serverlist::close_lan_listener.run_if(not(condition::in_ui(CurrentUI::ServerList))),
                         serverlist::ui_connecting_server.run_if(condition::in_ui(CurrentUI::ConnectingServer)),
                         serverlist::ui_disconnected_reason.run_if(condition::in_ui(CurrentUI::DisconnectedReason)),
                     )
@@ -154,6 +157,8 @@ pub enum CurrentUI {
     ChatInput,
     LocalWorldList,
     LocalWorldNew,
+    // dead. see ui_respawn
+    Respawn,
 }
 
 // for fn new_egui_window
@@ -1,9 +1,10 @@
 use crate::{
-    client::prelude::*,
-    server::{dedicated_server::rcon::Motd, prelude::ServerSettings},
-    util,
+    client::{prelude::*, saves::LocalWorld},
+    net::{lan::LanDiscovery, ServerStatus, PROTOCOL_VERSION},
+    util::current_timestamp_millis,
 };
 use bevy::{
+    diagnostic::FrameCount,
     prelude::*,
     tasks::{AsyncComputeTaskPool, Task},
 };
@@ -12,6 +13,7 @@ use bevy_egui::{
     EguiContexts,
 };
 use bevy_renet::renet::RenetClient;
+use human_bytes::human_bytes;
 
 use super::{sfx_play, ui_lr_panel, CurrentUI, UiExtra};
 
@@ -63,23 +65,51 @@ pub fn ui_disconnected_reason(
     });
 }
 
-#[derive(Default, Debug)]
+#[derive(Default)]
 pub struct UiServerInfo {
     pub motd: String,
     pub num_players_online: u32,
     pub num_players_limit: u32,
     pub ping: u32,
-    pub gameplay_addr: String,
+    pub protocol_version: u64,
+    pub version_name: String,
+    pub favicon: Option<egui::TextureHandle>,
 
     pub is_editing: bool,
-    pub refreshing_task: Option<(Task<anyhow::Result<Motd>>, u64)>,
+    pub refreshing_task: Option<Task<anyhow::Result<(ServerStatus, std::time::Duration)>>>,
+}
+
+fn load_favicon(ctx: &egui::Context, name: &str, png: &[u8]) -> Option<egui::TextureHandle> {
+    let img = image::load_from_memory(png).ok()?.to_rgba8();
+    let size = [img.width() as usize, img.height() as usize];
+    let color_img = egui::ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+    Some(ctx.load_texture(format!("favicon/{name}"), color_img, egui::TextureOptions::LINEAR))
+}
+
+/// listens for LAN games while the server list is shown.
+#[derive(Resource, Default)]
+pub struct LanListener(Option<std::io::Result<LanDiscovery>>);
+
+/// stop listening for LAN games when the server list is closed, frees the discovery port.
+pub fn close_lan_listener(mut listener: ResMut<LanListener>) {
+    if listener.0.take().is_some() {
+        debug!("Stopped listening for LAN games");
+    }
 }
 
 pub fn ui_serverlist(
     mut ctx: EguiContexts,
     mut cli: EthertiaClient,
     // mut refreshing_indices: Local<HashMap<usize, (Task<anyhow::Result<Motd>>, u64)>>,
+    mut lan_listener: ResMut<LanListener>,
 ) {
+    // listen for LAN games since the server list is shown.
+    let lan = lan_listener.0.get_or_insert_with(|| LanDiscovery::bind().inspect_err(|err| warn!("Failed to listen for LAN games: {}", err)));
+    if let Ok(lan) = lan.as_mut() {
+        lan.poll();
+    }
+    let lan_games = lan.as_ref().map(|lan| lan.games.as_slice()).unwrap_or_default();
+
     new_egui_window("Server List").show(ctx.ctx_mut().unwrap(), |ui| {
         let serverlist = &mut cli.cfg.serverlist;
 
@@ -89,6 +119,7 @@ pub fn ui_serverlist(
         let do_stop_refreshing = std::cell::Cell::new(false);
         let mut do_acquire_list = false;
         let mut do_join_addr = None;
+        let mut do_join_lan = None;
         let mut do_del_idx = None;
 
         let show_btn_stop_refresh = serverlist.iter().any(|e| e.ui.refreshing_task.is_some());
@@ -125,6 +156,9 @@ pub fn ui_serverlist(
                             if is_editing {
                                 ui.text_edit_singleline(&mut server_item.name);
                             } else {
+                                if let Some(favicon) = &ui_server_info.favicon {
+                                    ui.image((favicon.id(), egui::vec2(32., 32.)));
+                                }
                                 // Left: Name
                                 ui.colored_label(Color32::WHITE, server_item.name.clone())
                                     .on_hover_text(server_item.addr.clone());
@@ -137,6 +171,16 @@ pub fn ui_serverlist(
                                             "{}ms · {}/{}",
                                             ui_server_info.ping, ui_server_info.num_players_online, ui_server_info.num_players_limit
                                         ));
+                                        if ui_server_info.protocol_version != PROTOCOL_VERSION {
+                                            let outdated = if ui_server_info.protocol_version < PROTOCOL_VERSION { "Outdated Server" } else { "Outdated Client" };
+                                            ui.colored_label(Color32::DARK_RED, outdated).on_hover_text(format!(
+                                                "Server: {} (protocol {})\nClient: {} (protocol {})",
+                                                ui_server_info.version_name,
+                                                ui_server_info.protocol_version,
+                                                crate::VERSION_NAME,
+                                                PROTOCOL_VERSION
+                                            ));
+                                        }
                                     });
                                 }
                             }
@@ -175,15 +219,7 @@ pub fn ui_serverlist(
                                         is_refreshing = true;
                                     }
                                     if ui.btn("▶").on_hover_text("Join & Play").clicked() {
-                                        do_join_addr = Some(if ui_server_info.gameplay_addr.is_empty() {
-                                            server_item.addr.clone()
-                                        } else if ui_server_info.gameplay_addr.starts_with(":") {
-                                            // Concat: same ip but different port.
-                                            let i = server_item.addr.find(":").unwrap_or(server_item.addr.len());
-                                            format!("{}{}", &server_item.addr[0..i], ui_server_info.gameplay_addr)
-                                        } else {
-                                            ui_server_info.gameplay_addr.clone()
-                                        });
+                                        do_join_addr = Some(server_item.addr.clone());
                                     }
                                 }
                             });
@@ -193,20 +229,24 @@ pub fn ui_serverlist(
                     // ServerStatus Process
                     if is_refreshing {
                         let addr = server_item.addr.clone(); // opt
-                        let (task, time) = ui_server_info.refreshing_task.get_or_insert_with(|| {
-                            (
-                                AsyncComputeTaskPool::get().spawn(async move { util::http_get_json::<Motd>(&format!("http://{}", addr)) }),
-                                util::current_timestamp_millis(),
-                            )
+                        let task = ui_server_info.refreshing_task.get_or_insert_with(|| {
+                            AsyncComputeTaskPool::get().spawn(async move {
+                                let game_addr = std::net::ToSocketAddrs::to_socket_addrs(addr.trim())?
+                                    .next()
+                                    .ok_or(anyhow::anyhow!("Failed to resolve {}", addr))?;
+                                crate::net::query::query_server(game_addr, std::time::Duration::from_secs(3))
+                            })
                         });
                         if task.is_finished() {
                             match futures_lite::future::block_on(futures_lite::future::poll_once(task)).unwrap() {
-                                Ok(r) => {
-                                    ui_server_info.motd = r.motd;
-                                    ui_server_info.num_players_limit = r.num_player_limit;
-                                    ui_server_info.num_players_online = r.num_player_online;
-                                    ui_server_info.gameplay_addr = r.game_addr;
-                                    ui_server_info.ping = (util::current_timestamp_millis() - *time) as u32;
+                                Ok((status, rtt)) => {
+                                    ui_server_info.motd = status.motd;
+                                    ui_server_info.num_players_limit = status.num_players_limit;
+                                    ui_server_info.num_players_online = status.num_players_online;
+                                    ui_server_info.protocol_version = status.protocol_version;
+                                    ui_server_info.version_name = status.version_name;
+                                    ui_server_info.favicon = load_favicon(ui.ctx(), &server_item.addr, &status.favicon);
+                                    ui_server_info.ping = (rtt.as_millis() as u32).max(1);
                                 }
                                 Err(err) => {
                                     info!("Failed to access server status: {}", err);
@@ -222,10 +262,40 @@ pub fn ui_serverlist(
                     }
                 }
 
+                if !lan_games.is_empty() {
+                    ui.separator();
+                    ui.label("LAN Games");
+                }
+                for game in lan_games {
+                    let announcement = &game.announcement;
+                    ui.group(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(Color32::WHITE, &announcement.motd);
+                            ui.small(game.game_addr.to_string());
+
+                            ui.with_layout(Layout::right_to_left(egui::Align::Min), |ui| {
+                                ui.label(format!("LAN · {}/{}", announcement.num_players_online, announcement.num_players_limit));
+                            });
+                        });
+                        ui.horizontal(|ui| {
+                            if announcement.protocol_version != PROTOCOL_VERSION {
+                                ui.colored_label(Color32::DARK_RED, "Incompatible Version");
+                            } else {
+                                ui.label("Local Network");
+                            }
+                            ui.with_layout(Layout::right_to_left(egui::Align::Max), |ui| {
+                                if ui.btn("▶").on_hover_text("Join & Play").clicked() {
+                                    do_join_lan = Some(game.game_addr.to_string());
+                                }
+                            });
+                        });
+                    });
+                }
+
                 if do_new_server.get() {
                     serverlist.push(ServerListItem {
                         name: "Server Name".into(),
-                        addr: "0.0.0.0:4000".into(),
+                        addr: "127.0.0.1:4060".into(),
                         ..default()
                     });
                     ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
@@ -247,61 +317,144 @@ pub fn ui_serverlist(
         if let Some(addr) = do_join_addr {
             cli.connect_server(addr);
         }
+        // LAN games run in Unsecure auth mode.
+        if let Some(addr) = do_join_lan {
+            cli.connect_local_server(addr);
+        }
     });
 }
 
-pub fn ui_localsaves(mut ctx: EguiContexts, mut cli: EthertiaClient, mut idx_editing: Local<Option<usize>>, serv_cfg: Res<ServerSettings>) {
+/// an operation on a local world of the list, applied after the list is drawn.
+enum LocalWorldOp {
+    Play(usize),
+    PlayOpenToLan(usize),
+    Rename(usize),
+    Delete(usize),
+    Duplicate(usize),
+}
+
+/// e.g. "3 days ago"
+fn time_ago(unix_millis: u64) -> String {
+    let secs = current_timestamp_millis().saturating_sub(unix_millis) / 1000;
+    match secs {
+        0..60 => "just now".into(),
+        60..3600 => format!("{} minutes ago", secs / 60),
+        3600..86400 => format!("{} hours ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+fn format_time(unix_millis: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(unix_millis as i64)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y.%m.%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn ui_localsaves(
+    mut ctx: EguiContexts,
+    mut cli: EthertiaClient,
+    mut idx_editing: Local<Option<usize>>,
+    mut tx_rename: Local<String>,
+    // the world asked to confirm the deletion
+    mut idx_deleting: Local<Option<usize>>,
+    // the error of the last operation, shown above the list
+    mut err_msg: Local<String>,
+    mut worlds: Local<Vec<LocalWorld>>,
+    mut last_frame: Local<u32>,
+    frame: Res<FrameCount>,
+) {
+    // refresh on entering the ui
+    if frame.0 != last_frame.wrapping_add(1) {
+        *worlds = LocalWorld::list();
+        *idx_editing = None;
+        *idx_deleting = None;
+        err_msg.clear();
+    }
+    *last_frame = frame.0;
+
+    let mut op = None;
+    let do_new_world = std::cell::Cell::new(false);
+    let do_refresh = std::cell::Cell::new(false);
+
     new_egui_window("Local Worlds").show(ctx.ctx_mut().unwrap(), |ui| {
         ui_lr_panel(
             ui,
             false,
             |ui| {
                 if ui.btn_borderless("New World").clicked() {
-                    // cli.data().curr_ui = CurrentUI::LocalWorldNew;
+                    do_new_world.set(true);
+                }
+                if ui.btn_borderless("Refresh").clicked() {
+                    do_refresh.set(true);
                 }
-                if ui.btn_borderless("Refresh").clicked() {}
             },
             |ui| {
-                for idx in 0..28 {
+                if !err_msg.is_empty() {
+                    ui.colored_label(Color32::LIGHT_RED, err_msg.as_str());
+                }
+                if worlds.is_empty() {
+                    ui.label("No worlds yet.");
+                }
+                for (idx, world) in worlds.iter().enumerate() {
                     let is_editing = idx_editing.is_some_and(|i| i == idx);
+                    let level = &world.level;
 
                     // World Item
                     ui.group(|ui| {
                         // Line1:
                         ui.horizontal(|ui| {
                             // Left: Title
-                            ui.colored_label(Color32::WHITE, "World Name").on_hover_text(
-                                "Path: /Saves/Saa
-Size: 10.3 MiB
-Time Modified: 2024.02.01 11:20 AM
-Time Created: 2024.02.01 11:20 AM
-Inhabited: 10.3 hours",
-                            );
+                            if is_editing {
+                                ui.text_edit_singleline(&mut *tx_rename);
+                            } else {
+                                ui.colored_label(Color32::WHITE, &level.name).on_hover_text(format!(
+                                    "Path: {}\nSize: {}\nTime Modified: {}\nTime Created: {}\nInhabited: {:.1} hours",
+                                    world.dir.display(),
+                                    human_bytes(world.size as f64),
+                                    format_time(level.time_modified),
+                                    format_time(level.time_created),
+                                    level.time_inhabited / 3600.,
+                                ));
+                            }
                             // Right: Info
                             ui.with_layout(Layout::right_to_left(egui::Align::Min), |ui| {
-                                ui.label("3 days ago · 13 MB");
+                                ui.label(format!("{} · {}", time_ago(level.time_modified), human_bytes(world.size as f64)));
                             });
                         });
                         // Line2:
                         ui.horizontal(|ui| {
                             // Left: Description
-                            ui.label("Survival · Cheats");
+                            ui.label(format!("{} · Seed {}", level.game_mode.name(), level.seed));
                             // Right: Ops
                             ui.with_layout(Layout::right_to_left(egui::Align::Max), |ui| {
-                                if is_editing {
-                                    if ui.btn("✅").clicked() {
-                                        *idx_editing = None;
+                                if idx_deleting.is_some_and(|i| i == idx) {
+                                    if ui.btn("Cancel").clicked() {
+                                        *idx_deleting = None;
+                                    }
+                                    if ui.btn("Delete").on_hover_text("Delete the world permanently").clicked() {
+                                        op = Some(LocalWorldOp::Delete(idx));
+                                    }
+                                    ui.colored_label(Color32::LIGHT_RED, "Delete this world? It can't be undone.");
+                                } else if is_editing {
+                                    if ui.btn("✅").on_hover_text("Rename").clicked() {
+                                        op = Some(LocalWorldOp::Rename(idx));
+                                    }
+                                    if ui.btn("🗑").on_hover_text("Delete").clicked() {
+                                        *idx_deleting = Some(idx);
+                                    }
+                                    if ui.btn("⎘").on_hover_text("Duplicate").clicked() {
+                                        op = Some(LocalWorldOp::Duplicate(idx));
                                     }
-
-                                    ui.btn("🗑").on_hover_text("Delete").clicked();
                                 } else {
                                     if ui.btn("⛭").on_hover_text("Edit").clicked() {
                                         *idx_editing = Some(idx);
+                                        tx_rename.clone_from(&level.name);
+                                    }
+                                    if ui.btn("📡").on_hover_text("Play, Open to LAN").clicked() {
+                                        op = Some(LocalWorldOp::PlayOpenToLan(idx));
                                     }
                                     if ui.btn("▶").on_hover_text("Play").clicked() {
-                                        // cli.enter_world();
-
-                                        cli.connect_server(format!("127.0.0.1:{}", serv_cfg.port));
+                                        op = Some(LocalWorldOp::Play(idx));
                                     }
                                 }
                             });
@@ -311,22 +464,57 @@ Inhabited: 10.3 hours",
             },
         );
     });
+
+    if do_new_world.get() {
+        cli.data().curr_ui = CurrentUI::LocalWorldNew;
+    }
+    let Some(op) = op else {
+        if do_refresh.get() {
+            *worlds = LocalWorld::list();
+        }
+        return;
+    };
+    let result = match op {
+        LocalWorldOp::Play(idx) => {
+            cli.open_local_world(worlds[idx].dir.clone(), false);
+            return;
+        }
+        LocalWorldOp::PlayOpenToLan(idx) => {
+            cli.open_local_world(worlds[idx].dir.clone(), true);
+            return;
+        }
+        LocalWorldOp::Rename(idx) => worlds[idx].rename(&tx_rename),
+        LocalWorldOp::Delete(idx) => worlds[idx].delete(),
+        LocalWorldOp::Duplicate(idx) => worlds[idx].duplicate().map(|_| ()),
+    };
+    *idx_deleting = None;
+    if let Err(err) = result {
+        // keep editing, e.g. to correct the name.
+        error!("Failed to modify the world: {err}");
+        *err_msg = format!("Failed to modify the world: {err}");
+        return;
+    }
+    err_msg.clear();
+    *idx_editing = None;
+    *worlds = LocalWorld::list();
 }
 
-#[derive(Default, Debug, PartialEq)]
-pub enum Difficulty {
-    Peace,
-    #[default]
-    Normal,
-    Hard,
+/// the world seed: a number, the hash of a text, or random if empty.
+fn parse_seed(s: &str) -> u64 {
+    let s = s.trim();
+    if s.is_empty() {
+        return rand::random();
+    }
+    // stable, the same text gives the same world on any build.
+    s.parse().unwrap_or_else(|_| crate::util::fnv1a_64(s.as_bytes()))
 }
 
 pub fn ui_create_world(
     mut ctx: EguiContexts,
-    mut cli: ResMut<ClientInfo>,
+    mut cli: EthertiaClient,
     mut tx_world_name: Local<String>,
     mut tx_world_seed: Local<String>,
-    mut _difficulty: Local<Difficulty>,
+    mut game_mode: Local<GameMode>,
 ) {
     new_egui_window("New World").show(ctx.ctx_mut().unwrap(), |ui| {
         // ui_lr_panel(ui, true, |ui| {
@@ -352,37 +540,28 @@ pub fn ui_create_world(
 
         ui.label("Gamemode:");
         ui.horizontal(|ui| {
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Peace, "Survival"));
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Normal, "Creative"));
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Hard, "Spectator"));
-        });
-        ui.add_space(space);
-
-        ui.label("Difficulty:");
-        ui.horizontal(|ui| {
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Peace, "Peace"));
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Normal, "Normal"));
-            sfx_play(ui.radio_value(&mut *_difficulty, Difficulty::Hard, "Hard"));
+            for mode in GameMode::ALL {
+                sfx_play(ui.radio_value(&mut *game_mode, mode, mode.name()));
+            }
         });
         ui.add_space(space);
 
-        ui.label("Difficulty:");
-        egui::ComboBox::from_id_source("Difficulty")
-            .selected_text(format!("{:?}", *_difficulty))
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut *_difficulty, Difficulty::Peace, "Peace");
-                ui.selectable_value(&mut *_difficulty, Difficulty::Normal, "Normal");
-                ui.selectable_value(&mut *_difficulty, Difficulty::Hard, "Hard");
-            });
-
-        ui.add_space(space);
-
         ui.add_space(22.);
 
-        sfx_play(ui.add_sized([290., 26.], egui::Button::new("Create World").fill(Color32::DARK_GREEN))).clicked();
+        if sfx_play(ui.add_sized([290., 26.], egui::Button::new("Create World").fill(Color32::DARK_GREEN))).clicked() {
+            let name = if tx_world_name.trim().is_empty() { "New World" } else { tx_world_name.trim() };
+            match LocalWorld::create(name, parse_seed(&tx_world_seed), *game_mode) {
+                Ok(world) => {
+                    tx_world_name.clear();
+                    tx_world_seed.clear();
+                    cli.open_local_world(world.dir, false);
+                }
+                Err(err) => error!("Failed to create world {}: {err}", name),
+            }
+        }
         ui.add_space(4.);
         if sfx_play(ui.add_sized([290., 20.], egui::Button::new("Cancel"))).clicked() {
-            cli.curr_ui = CurrentUI::LocalWorldList;
+            cli.data().curr_ui = CurrentUI::LocalWorldList;
         }
         // });
     });
@@ -123,6 +123,11 @@ pub fn ui_settings(
                         ui_setting_line(ui, "Chunk Load Distance X", egui::Slider::new(&mut cfg.chunks_load_distance.x, -1..=25));
                         ui_setting_line(ui, "Chunk Load Distance Y", egui::Slider::new(&mut cfg.chunks_load_distance.y, -1..=25));
 
+                        ui.label("Network:");
+
+                        ui_setting_line(ui, "Interpolation Delay (ms)", egui::Slider::new(&mut cfg.interp_delay_ms, 0..=500));
+                        ui_setting_line(ui, "Extrapolation Limit (ms)", egui::Slider::new(&mut cfg.extrap_limit_ms, 0..=1000));
+
                         ui.label("Voxel Brush:");
 
                         ui_setting_line(ui, "Size", egui::Slider::new(&mut vox_brush.size, 0.0..=20.0));
@@ -1,24 +1,34 @@
+use bevy::platform::collections::HashMap;
+
 use crate::util::registry::{RegId, Registry};
 
+pub mod recipe;
+pub use recipe::{Recipe, Recipes};
+
 use crate::prelude::*;
 
-// pub struct Item {
-//     // tab_category
-//     max_stacksize: u32,
+/// ItemStack::item_id, the RegId + 1. 0 is empty.
+pub type ItemId = u16;
 
-//     max_damage: u32,
-//     // name
-// }
-
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ItemStack {
     pub count: u8,
-    pub item_id: u8,
-    // pub durability
+    pub item_id: ItemId,
+    // remaining uses of a tool. see ItemDef::durability
+    #[serde(default)]
+    pub durability: u16,
+    // item specific data. e.g. a variant
+    #[serde(default)]
+    pub meta: Option<u32>,
 }
 impl ItemStack {
-    pub fn new(count: u8, item: u8) -> Self {
-        Self { count, item_id: item }
+    /// a stack without durability. see `Items::new_stack` for tools.
+    pub fn new(count: u8, item: ItemId) -> Self {
+        Self {
+            count,
+            item_id: item,
+            ..default()
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -34,20 +44,243 @@ impl ItemStack {
         *a = *b;
         *b = tmp;
     }
+
+    /// same item, durability and meta. only such stacks merge.
+    pub fn stacks_with(&self, other: &ItemStack) -> bool {
+        self.item_id == other.item_id && self.durability == other.durability && self.meta == other.meta
+    }
+
+    /// merge `other` into self as much as possible (up to `max_stack`), if they stack. returns true if anything merged.
+    pub fn merge(&mut self, other: &mut ItemStack, max_stack: u8) -> bool {
+        if other.is_empty() || (!self.is_empty() && !self.stacks_with(other)) {
+            return false;
+        }
+        if self.is_empty() {
+            *self = ItemStack { count: 0, ..*other };
+        }
+        let n = other.count.min(max_stack.saturating_sub(self.count));
+        self.count += n;
+        other.count -= n;
+        if other.count == 0 {
+            other.clear();
+        }
+        n > 0
+    }
+
+    /// use up `amount` durability of a tool. the stack is cleared when it breaks, returns true then.
+    /// items without durability are not affected.
+    pub fn damage(&mut self, amount: u16) -> bool {
+        if self.is_empty() || self.durability == 0 {
+            return false;
+        }
+        self.durability = self.durability.saturating_sub(amount);
+        if self.durability == 0 {
+            self.clear();
+            return true;
+        }
+        false
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Inventory {
     pub items: Vec<ItemStack>,
 }
 
 impl Inventory {
+    /// slots of a player inventory, the first HOTBAR_SLOTS are the hotbar.
+    pub const PLAYER_SLOTS: usize = 36;
+    pub const HOTBAR_SLOTS: usize = 9;
+
     pub fn new(len: usize) -> Self {
         let mut items = Vec::new();
         items.resize(len, ItemStack::default());
 
         Self { items }
     }
+
+    pub fn clear(&mut self) {
+        self.items.iter_mut().for_each(ItemStack::clear);
+    }
+
+    /// put the stack into the inventory, fill same items first then empty slots. the remaining stays in `stack`.
+    pub fn insert(&mut self, stack: &mut ItemStack, items: &Items) {
+        self.insert_range(stack, 0..self.items.len(), items);
+    }
+
+    fn insert_range(&mut self, stack: &mut ItemStack, range: std::ops::Range<usize>, items: &Items) {
+        let max_stack = items.max_stack(stack.item_id);
+        let slots = &mut self.items[range];
+        for slot in slots.iter_mut().filter(|s| !s.is_empty()) {
+            slot.merge(stack, max_stack);
+        }
+        for slot in slots.iter_mut().filter(|s| s.is_empty()) {
+            slot.merge(stack, max_stack);
+        }
+    }
+
+    /// click a slot with the `cursor` stack (the one held by the mouse). returns false if the slot doesn't exist.
+    /// - primary: put the cursor stack into the slot, merge if they stack, otherwise swap.
+    /// - secondary: with an empty cursor take half of the slot, otherwise put one item.
+    pub fn click(&mut self, slot: usize, secondary: bool, cursor: &mut ItemStack, items: &Items) -> bool {
+        let Some(stack) = self.items.get_mut(slot) else {
+            return false;
+        };
+        let max_stack = items.max_stack(cursor.item_id);
+        let same_item = !stack.is_empty() && stack.stacks_with(cursor);
+
+        if !secondary {
+            if !same_item || !stack.merge(cursor, max_stack) {
+                ItemStack::swap(stack, cursor);
+            }
+        } else if cursor.is_empty() {
+            let n = stack.count - stack.count / 2;
+            *cursor = ItemStack { count: n, ..*stack };
+            stack.count -= n;
+            if stack.count == 0 {
+                stack.clear();
+            }
+        } else if stack.is_empty() || same_item {
+            let mut one = ItemStack { count: 1, ..*cursor };
+            if stack.merge(&mut one, max_stack) {
+                cursor.count -= 1;
+                if cursor.count == 0 {
+                    cursor.clear();
+                }
+            }
+        } else {
+            ItemStack::swap(stack, cursor);
+        }
+        true
+    }
+
+    /// quick move (shift-click) the slot's stack between the hotbar and the rest. returns false if the slot doesn't exist.
+    pub fn transfer(&mut self, slot: usize, items: &Items) -> bool {
+        let Some(mut stack) = self.items.get(slot).copied() else {
+            return false;
+        };
+        let range = if slot < Self::HOTBAR_SLOTS {
+            Self::HOTBAR_SLOTS.min(self.items.len())..self.items.len()
+        } else {
+            0..Self::HOTBAR_SLOTS.min(self.items.len())
+        };
+        self.insert_range(&mut stack, range, items);
+        self.items[slot] = stack;
+        true
+    }
+
+    /// ItemId -> total count in the inventory.
+    pub fn item_counts(&self) -> HashMap<ItemId, u32> {
+        let mut counts = HashMap::default();
+        for stack in self.items.iter().filter(|s| !s.is_empty()) {
+            *counts.entry(stack.item_id).or_default() += stack.count as u32;
+        }
+        counts
+    }
+
+    /// take `count` of the item out of the inventory, from the last slots. returns the number actually removed.
+    pub fn remove_item(&mut self, item_id: ItemId, count: u32) -> u32 {
+        let mut removed = 0;
+        for stack in self.items.iter_mut().rev().filter(|s| !s.is_empty() && s.item_id == item_id) {
+            let n = (count - removed).min(stack.count as u32);
+            stack.count -= n as u8;
+            if stack.count == 0 {
+                stack.clear();
+            }
+            removed += n;
+            if removed == count {
+                break;
+            }
+        }
+        removed
+    }
+
+    /// slots differ from `before`. (slot, new stack)
+    pub fn changed_slots(&self, before: &Inventory) -> Vec<(u16, ItemStack)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(i, stack)| before.items.get(*i) != Some(*stack))
+            .map(|(i, stack)| (i as u16, *stack))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToolType {
+    Pickaxe,
+    Axe,
+    Shovel,
+    Shears,
+}
+
+/// Properties of an item type.
+#[derive(Debug, Clone)]
+pub struct ItemDef {
+    pub max_stack: u8,
+    // uses of a new tool. 0: no durability
+    pub durability: u16,
+    pub tool: Option<ToolType>,
+    // the voxel (VoxTex) placed by using the item
+    pub place_block: Option<u16>,
+    // the shape of the placed voxel. plants (VoxShape::Grass) need dirt or grass below, see `can_place_on`
+    pub place_shape: VoxShape,
+}
+
+impl Default for ItemDef {
+    fn default() -> Self {
+        Self {
+            max_stack: 64,
+            durability: 0,
+            tool: None,
+            place_block: None,
+            place_shape: VoxShape::Cube,
+        }
+    }
+}
+
+impl ItemDef {
+    pub fn tool(tool: ToolType, durability: u16) -> Self {
+        Self {
+            max_stack: 1,
+            durability,
+            tool: Some(tool),
+            ..default()
+        }
+    }
+
+    /// an item placing the voxel (VoxTex) when used.
+    pub fn block(tex_id: u16) -> Self {
+        Self {
+            place_block: Some(tex_id),
+            ..default()
+        }
+    }
+
+    /// an item placing the voxel (VoxTex) as a plant, e.g. a sapling.
+    pub fn plant(tex_id: u16) -> Self {
+        Self {
+            place_shape: VoxShape::Grass,
+            ..Self::block(tex_id)
+        }
+    }
+
+    /// whether the placed voxel can stand on `below`. plants need dirt or grass.
+    pub fn can_place_on(&self, below: Option<&Vox>) -> bool {
+        self.place_shape != VoxShape::Grass || below.is_some_and(Vox::supports_plant)
+    }
+
+    pub fn max_stack(mut self, max_stack: u8) -> Self {
+        self.max_stack = max_stack;
+        self
+    }
+}
+
+/// where the icon of an item is, see `Items::icon`.
+pub struct ItemIcon {
+    pub atlas: Handle<Image>,
+    pub atlas_egui: bevy_egui::egui::TextureId,
+    pub uv: Rect,
 }
 
 pub struct ItemPlugin;
@@ -55,6 +288,7 @@ pub struct ItemPlugin;
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Items::default());
+        app.insert_resource(Recipes::default());
         // app.insert_resource(Registry::default());
 
         app.add_systems(Startup, setup_items);
@@ -66,8 +300,20 @@ impl Plugin for ItemPlugin {
 #[derive(Resource, Default)]
 pub struct Items {
     pub reg: Registry,
+    // by RegId
+    pub defs: Vec<ItemDef>,
     pub atlas: Handle<Image>,
     pub atlas_egui: bevy_egui::egui::TextureId,
+    // the voxel textures, the icons of block items
+    pub atlas_blocks: Handle<Image>,
+    pub atlas_blocks_egui: bevy_egui::egui::TextureId,
+    // the plant textures, the icons of plant items (e.g. sapling)
+    pub atlas_foliage: Handle<Image>,
+    pub atlas_foliage_egui: bevy_egui::egui::TextureId,
+
+    // Client: the server's ItemId -> local ItemId, 0 for unknown. empty if not connected, then ids are the same.
+    // set by SPacket::ItemRegistry at login.
+    server_ids: Vec<ItemId>,
 
     pub apple: RegId,
 
@@ -83,6 +329,140 @@ pub struct Items {
     pub iron_ingot: RegId,
 }
 
+impl Items {
+    pub fn register_all(&mut self) {
+        let mut defs: HashMap<&str, ItemDef> = HashMap::default();
+        // Food
+        defs.insert("apple", ItemDef::default());
+        defs.insert("avocado", ItemDef::default()); // tmp
+
+        // Material
+        defs.insert("coal", ItemDef::default());
+        defs.insert("stick", ItemDef::default());
+        defs.insert("iron_ingot", ItemDef::default());
+
+        // Block
+        defs.insert("dirt", ItemDef::block(VoxTex::Dirt));
+        defs.insert("sand", ItemDef::block(VoxTex::Sand));
+        defs.insert("sapling", ItemDef::plant(VoxTex::Leaves));
+
+        // Object
+        defs.insert("frame", ItemDef::default().max_stack(16));
+        defs.insert("lantern", ItemDef::default().max_stack(16));
+        // torch
+
+        // Tool
+        defs.insert("pickaxe", ItemDef::tool(ToolType::Pickaxe, 250));
+        // shovel
+        defs.insert("shears", ItemDef::tool(ToolType::Shears, 238));
+        defs.insert("grapple", ItemDef::default().max_stack(1));
+
+        // Build NumId Table. sorted by str id, so the ids are stable regardless of the registration order.
+        let reg = &mut self.reg;
+        for str_id in defs.keys() {
+            reg.insert(str_id);
+        }
+        reg.build_num_id();
+        self.defs = reg.vec.iter().map(|str_id| defs[str_id.as_str()].clone()).collect();
+
+        let id = |str_id: &str| reg.get(str_id).unwrap();
+        self.apple = id("apple");
+        self.coal = id("coal");
+        self.stick = id("stick");
+        self.frame = id("frame");
+        self.lantern = id("lantern");
+        self.pickaxe = id("pickaxe");
+        self.shears = id("shears");
+        self.grapple = id("grapple");
+        self.iron_ingot = id("iron_ingot");
+
+        info!("Registered {} items: {:?}", reg.len(), reg.vec);
+    }
+
+    /// ItemStack::item_id of the item. 0 is empty, so it's RegId + 1.
+    pub fn id_of(&self, str_id: &str) -> Option<ItemId> {
+        Some(self.reg.get(str_id)? + 1)
+    }
+
+    pub fn str_id(&self, item_id: ItemId) -> Option<&String> {
+        self.reg.at(item_id.checked_sub(1)?)
+    }
+
+    pub fn def(&self, item_id: ItemId) -> Option<&ItemDef> {
+        self.defs.get(item_id.checked_sub(1)? as usize)
+    }
+
+    /// the index of the item's icon in the items atlas, and the number of icons there.
+    /// block items (`ItemDef::place_block`) have no icon in it, they show the voxel texture.
+    pub fn icon_index(&self, item_id: ItemId) -> (usize, usize) {
+        let num_icons = |defs: &[ItemDef]| defs.iter().filter(|def| def.place_block.is_none()).count();
+        let idx = (item_id as usize).saturating_sub(1).min(self.defs.len());
+        (num_icons(&self.defs[..idx]), num_icons(&self.defs))
+    }
+
+    /// the icon of the item: block items show their voxel texture in `atlas_blocks` (`atlas_foliage` for plants), the others are in `atlas`.
+    pub fn icon(&self, item_id: ItemId) -> ItemIcon {
+        if let Some((def, tex_id)) = self.def(item_id).and_then(|def| Some((def, def.place_block?))) {
+            let (atlas, atlas_egui) = if def.place_shape == VoxShape::Grass {
+                (&self.atlas_foliage, self.atlas_foliage_egui)
+            } else {
+                (&self.atlas_blocks, self.atlas_blocks_egui)
+            };
+            return ItemIcon {
+                atlas: atlas.clone(),
+                atlas_egui,
+                uv: Rect::from_corners(VoxTex::map_uv(Vec2::ZERO, tex_id), VoxTex::map_uv(Vec2::ONE, tex_id)),
+            };
+        }
+        let (idx, num_icons) = self.icon_index(item_id);
+        let uv_siz = 1. / num_icons.max(1) as f32;
+        ItemIcon {
+            atlas: self.atlas.clone(),
+            atlas_egui: self.atlas_egui,
+            uv: Rect::new(uv_siz * idx as f32, 0., uv_siz * (idx + 1) as f32, 1.),
+        }
+    }
+
+    pub fn max_stack(&self, item_id: ItemId) -> u8 {
+        self.def(item_id).map_or(64, |def| def.max_stack)
+    }
+
+    /// a new stack of the item, tools with full durability.
+    pub fn new_stack(&self, item_id: ItemId, count: u8) -> ItemStack {
+        ItemStack {
+            count,
+            item_id,
+            durability: self.def(item_id).map_or(0, |def| def.durability),
+            meta: None,
+        }
+    }
+
+    /// Client: set the server's item registry, str ids in the server's num id order.
+    pub fn set_server_registry(&mut self, str_ids: &[String]) {
+        self.server_ids = str_ids.iter().map(|str_id| self.id_of(str_id).unwrap_or(0)).collect();
+        let unknown: Vec<&String> = str_ids.iter().filter(|str_id| self.id_of(str_id).is_none()).collect();
+        if !unknown.is_empty() {
+            warn!("Unknown items from the server: {:?}", unknown);
+        }
+    }
+
+    pub fn clear_server_registry(&mut self) {
+        self.server_ids.clear();
+    }
+
+    /// Client: map a stack received from the server to local ids. unknown items become empty.
+    pub fn from_server(&self, mut stack: ItemStack) -> ItemStack {
+        if self.server_ids.is_empty() || stack.is_empty() {
+            return stack;
+        }
+        stack.item_id = self.server_ids.get(stack.item_id as usize - 1).copied().unwrap_or(0);
+        if stack.item_id == 0 {
+            stack.clear();
+        }
+        stack
+    }
+}
+
 // pub static mut _UI_ITEMS_ATLAS: bevy_egui::egui::TextureId = bevy_egui::egui::TextureId::Managed(0);
 pub static mut _ITEMS_REG: *const Items = std::ptr::null();
 
@@ -91,37 +471,21 @@ fn setup_items(
     // mut reg: ResMut<Registry>,
     asset_server: Res<AssetServer>,
     mut egui_ctx: bevy_egui::EguiContexts,
+    mut cmds: Commands,
 ) {
-    let reg = crate::util::as_mut(&items.reg);
     let items = crate::util::as_mut(&*items);
-    // Food
-    items.apple = reg.insert("apple");
-    reg.insert("avocado"); // tmp
-
-    // Material
-    items.coal = reg.insert("coal");
-    items.stick = reg.insert("stick");
-
-    // Object
-    items.frame = reg.insert("frame");
-    items.lantern = reg.insert("lantern");
-    // torch
-
-    // Tool
-    items.pickaxe = reg.insert("pickaxe");
-    // shovel
-    items.shears = reg.insert("shears");
-    items.grapple = reg.insert("grapple");
-    items.iron_ingot = reg.insert("iron_ingot");
+    items.register_all();
 
-    // below are temporary. Build should defer to PostStartup stage.:
+    cmds.insert_resource(Recipes::load(recipe::RECIPES_DIR, items));
 
-    // Build NumId Table
-    reg.build_num_id();
-    info!("Registered {} items: {:?}", reg.len(), reg.vec);
+    // below are temporary. Build should defer to PostStartup stage.:
 
     items.atlas = asset_server.load("baked/items.png");
     items.atlas_egui = egui_ctx.add_image(items.atlas.clone());
+    items.atlas_blocks = asset_server.load("baked/atlas_diff.png");
+    items.atlas_blocks_egui = egui_ctx.add_image(items.atlas_blocks.clone());
+    items.atlas_foliage = asset_server.load("baked/atlas_diff_foli.png");
+    items.atlas_foliage_egui = egui_ctx.add_image(items.atlas_foliage.clone());
 
     unsafe {
         _ITEMS_REG = std::ptr::from_ref(items);
@@ -0,0 +1,244 @@
+//! Crafting Recipes. loaded from `assets/recipes/*.json`, the file stem is the recipe id.
+//!
+//! ```json
+//! { "type": "shaped", "pattern": ["III", " S ", " S "], "key": { "I": "iron_ingot", "S": "stick" }, "result": { "item": "pickaxe" } }
+//! { "type": "shapeless", "ingredients": ["iron_ingot", "stick", "stick"], "result": { "item": "grapple", "count": 1 } }
+//! ```
+//! Shaped patterns match anywhere in the crafting grid, the empty rows/columns around are trimmed.
+
+use std::path::Path;
+
+use bevy::platform::collections::HashMap;
+
+use super::{Inventory, ItemId, ItemStack, Items};
+use crate::prelude::*;
+
+pub const RECIPES_DIR: &str = "assets/recipes";
+
+/// the crafting grid is CRAFTING_GRID x CRAFTING_GRID.
+pub const CRAFTING_GRID: usize = 3;
+
+#[derive(Deserialize, Debug)]
+struct RecipeResultDef {
+    item: String,
+    #[serde(default = "default_count")]
+    count: u8,
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RecipeDef {
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, String>,
+        result: RecipeResultDef,
+    },
+    Shapeless {
+        ingredients: Vec<String>,
+        result: RecipeResultDef,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipeShape {
+    // row-major, 0 for empty
+    Shaped { width: usize, height: usize, cells: Vec<ItemId> },
+    Shapeless { ingredients: Vec<ItemId> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub id: String,
+    pub shape: RecipeShape,
+    pub result: ItemStack,
+}
+
+impl Recipe {
+    fn from_def(id: String, def: RecipeDef, items: &Items) -> Result<Self, String> {
+        let item = |str_id: &str| items.id_of(str_id).ok_or_else(|| format!("unknown item {str_id}"));
+        let (shape, result) = match def {
+            RecipeDef::Shaped { pattern, key, result } => {
+                let height = pattern.len();
+                let width = pattern.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+                if height == 0 || width == 0 || height > CRAFTING_GRID || width > CRAFTING_GRID {
+                    return Err(format!("invalid pattern size {width}x{height}"));
+                }
+                let mut cells = vec![0; width * height];
+                for (y, row) in pattern.iter().enumerate() {
+                    for (x, c) in row.chars().enumerate() {
+                        if c == ' ' {
+                            continue;
+                        }
+                        let str_id = key.get(&c).ok_or_else(|| format!("pattern key '{c}' undefined"))?;
+                        cells[y * width + x] = item(str_id)?;
+                    }
+                }
+                // trim the empty rows/columns around, as the grid is when matching.
+                let Some((min, max)) = cell_bounds(&cells, width) else {
+                    return Err("empty pattern".into());
+                };
+                let (trimmed_width, trimmed_height) = (max.0 - min.0 + 1, max.1 - min.1 + 1);
+                let cells = (0..trimmed_height)
+                    .flat_map(|y| (0..trimmed_width).map(move |x| (min.0 + x, min.1 + y)))
+                    .map(|(x, y)| cells[y * width + x])
+                    .collect();
+                let (width, height) = (trimmed_width, trimmed_height);
+                (RecipeShape::Shaped { width, height, cells }, result)
+            }
+            RecipeDef::Shapeless { ingredients, result } => {
+                if ingredients.is_empty() || ingredients.len() > CRAFTING_GRID * CRAFTING_GRID {
+                    return Err(format!("invalid number of ingredients {}", ingredients.len()));
+                }
+                let mut ids = ingredients.iter().map(|s| item(s)).collect::<Result<Vec<_>, _>>()?;
+                ids.sort_unstable();
+                (RecipeShape::Shapeless { ingredients: ids }, result)
+            }
+        };
+        let result = items.new_stack(item(&result.item)?, result.count.max(1));
+        Ok(Self { id, shape, result })
+    }
+
+    /// ItemId -> count, of all ingredients.
+    pub fn ingredients(&self) -> HashMap<ItemId, u32> {
+        let ids = match &self.shape {
+            RecipeShape::Shaped { cells, .. } => cells,
+            RecipeShape::Shapeless { ingredients } => ingredients,
+        };
+        let mut counts = HashMap::default();
+        for &id in ids.iter().filter(|&&id| id != 0) {
+            *counts.entry(id).or_default() += 1;
+        }
+        counts
+    }
+
+    /// does the crafting grid (CRAFTING_GRID x CRAFTING_GRID item ids, row-major) match the recipe.
+    pub fn matches(&self, grid: &[ItemId]) -> bool {
+        match &self.shape {
+            RecipeShape::Shaped { width, height, cells } => {
+                let Some((min, max)) = cell_bounds(grid, CRAFTING_GRID) else {
+                    return false;
+                };
+                if max.0 - min.0 + 1 != *width || max.1 - min.1 + 1 != *height {
+                    return false;
+                }
+                (0..*height).all(|y| (0..*width).all(|x| grid[(min.1 + y) * CRAFTING_GRID + min.0 + x] == cells[y * width + x]))
+            }
+            RecipeShape::Shapeless { ingredients } => {
+                let mut ids: Vec<ItemId> = grid.iter().copied().filter(|&id| id != 0).collect();
+                ids.sort_unstable();
+                ids == *ingredients
+            }
+        }
+    }
+}
+
+/// the bounding box ((min x, min y), (max x, max y)) of the non-empty cells, row-major by `width`.
+fn cell_bounds(cells: &[ItemId], width: usize) -> Option<((usize, usize), (usize, usize))> {
+    let mut bounds: Option<((usize, usize), (usize, usize))> = None;
+    for (i, _) in cells.iter().enumerate().filter(|(_, &id)| id != 0) {
+        let (x, y) = (i % width, i / width);
+        bounds = Some(match bounds {
+            None => ((x, y), (x, y)),
+            Some((min, max)) => ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y))),
+        });
+    }
+    bounds
+}
+
+#[derive(Resource, Default)]
+pub struct Recipes {
+    // sorted by id
+    pub recipes: Vec<Recipe>,
+}
+
+impl Recipes {
+    /// load all recipe files of the directory. invalid ones are skipped with an error.
+    pub fn load(dir: impl AsRef<Path>, items: &Items) -> Self {
+        let mut recipes = Vec::new();
+        let entries = match std::fs::read_dir(dir.as_ref()) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Failed to read recipes directory {}: {err}", dir.as_ref().display());
+                return Self::default();
+            }
+        };
+        for path in entries.filter_map(|e| Some(e.ok()?.path())).filter(|p| p.extension().is_some_and(|ext| ext == "json")) {
+            let id = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let result = std::fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|str| serde_json::from_str::<RecipeDef>(&str).map_err(|err| err.to_string()))
+                .and_then(|def| Recipe::from_def(id, def, items));
+            match result {
+                Ok(recipe) => recipes.push(recipe),
+                Err(err) => error!("Invalid recipe {}: {err}", path.display()),
+            }
+        }
+        recipes.sort_by(|a, b| a.id.cmp(&b.id));
+        info!("Loaded {} recipes", recipes.len());
+        Self { recipes }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| r.id == id)
+    }
+
+    /// the recipe the crafting grid matches.
+    pub fn find(&self, grid: &Inventory) -> Option<&Recipe> {
+        let ids: Vec<ItemId> = grid.items.iter().map(|s| if s.is_empty() { 0 } else { s.item_id }).collect();
+        self.recipes.iter().find(|r| r.matches(&ids))
+    }
+
+    /// Recipe Book: recipes that can be crafted with the items in the inventory.
+    pub fn craftable<'a>(&'a self, inventory: &Inventory) -> Vec<&'a Recipe> {
+        let counts = inventory.item_counts();
+        self.recipes
+            .iter()
+            .filter(|r| r.ingredients().iter().all(|(id, n)| counts.get(id).is_some_and(|c| c >= n)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shaped(items: &Items, pattern: &[&str], key: &[(char, &str)], result: &str) -> Recipe {
+        let def = RecipeDef::Shaped {
+            pattern: pattern.iter().map(|s| s.to_string()).collect(),
+            key: key.iter().map(|(c, s)| (*c, s.to_string())).collect(),
+            result: RecipeResultDef { item: result.into(), count: 1 },
+        };
+        Recipe::from_def(result.into(), def, items).unwrap()
+    }
+
+    #[test]
+    fn shaped_matches_anywhere_in_grid() {
+        let mut items = Items::default();
+        items.register_all();
+        let (i, s) = (items.id_of("iron_ingot").unwrap(), items.id_of("stick").unwrap());
+        let shears = shaped(&items, &[" I", "I "], &[('I', "iron_ingot")], "shears");
+
+        assert!(shears.matches(&[0, i, 0, i, 0, 0, 0, 0, 0]));
+        assert!(shears.matches(&[0, 0, 0, 0, 0, i, 0, i, 0]));
+        assert!(!shears.matches(&[i, 0, 0, 0, i, 0, 0, 0, 0])); // mirrored
+        assert!(!shears.matches(&[0, i, 0, i, 0, 0, 0, 0, s])); // extra item
+        assert_eq!(shears.result.durability, items.def(shears.result.item_id).unwrap().durability);
+    }
+
+    #[test]
+    fn shaped_pattern_is_trimmed() {
+        let mut items = Items::default();
+        items.register_all();
+        let s = items.id_of("stick").unwrap();
+        let padded = shaped(&items, &["   ", " S ", " S "], &[('S', "stick")], "stick");
+
+        assert_eq!(padded.shape, RecipeShape::Shaped { width: 1, height: 2, cells: vec![s, s] });
+        assert!(padded.matches(&[s, 0, 0, s, 0, 0, 0, 0, 0]));
+        assert!(padded.matches(&[0, 0, 0, 0, 0, s, 0, 0, s]));
+        assert!(!padded.matches(&[s, 0, 0, 0, 0, 0, 0, 0, 0]));
+    }
+}
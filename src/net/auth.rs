@@ -0,0 +1,137 @@
+//! Netcode ConnectToken Authentication.
+//!
+//! In `AuthMode::Secure` the server only accepts connections with a ConnectToken signed by its private key
+//! (`ServerSettings::auth_private_key`). The token is bound to a user id (the netcode client id) and carries the username in its user data.
+//! Tokens are issued by a local auth service (RCON endpoint `/auth`), or written as a token file for offline testing (`bin/issue_token`).
+//! `AuthMode::Unsecure` trusts whatever the client claims, only for LAN play.
+
+use std::{
+    io::Cursor,
+    net::SocketAddr,
+    path::Path,
+};
+
+use bevy_renet::netcode::{
+    generate_random_bytes, ClientAuthentication, ConnectToken, TokenGenerationError, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES,
+};
+use serde::{Deserialize, Serialize};
+
+use super::PROTOCOL_ID;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMode {
+    /// Verify signed ConnectTokens.
+    #[default]
+    Secure,
+    /// Trust the client. explicit opt-in for LAN play.
+    Unsecure,
+}
+
+/// seconds a token can be used to connect after issued.
+pub const TOKEN_EXPIRE_SECS: u64 = 300;
+/// seconds of no packets before the connection timeout.
+pub const TOKEN_TIMEOUT_SECS: i32 = 15;
+
+/// the stable user id of the username. it names the persisted player data, so the hash must never change.
+pub fn user_id_of(username: &str) -> u64 {
+    crate::util::fnv1a_64(username.as_bytes())
+}
+
+pub fn generate_private_key() -> String {
+    to_hex(&generate_random_bytes::<NETCODE_KEY_BYTES>())
+}
+
+pub fn parse_private_key(hex: &str) -> Option<[u8; NETCODE_KEY_BYTES]> {
+    let hex = hex.trim();
+    if hex.len() != NETCODE_KEY_BYTES * 2 {
+        return None;
+    }
+    let mut key = [0u8; NETCODE_KEY_BYTES];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(key)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn encode_user_data(username: &str) -> [u8; NETCODE_USER_DATA_BYTES] {
+    let mut data = [0u8; NETCODE_USER_DATA_BYTES];
+    let bytes = username.as_bytes();
+    let n = bytes.len().min(NETCODE_USER_DATA_BYTES);
+    data[..n].copy_from_slice(&bytes[..n]);
+    data
+}
+
+pub fn decode_user_data(data: &[u8; NETCODE_USER_DATA_BYTES]) -> String {
+    let n = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..n]).into_owned()
+}
+
+/// Issue a ConnectToken for the user, signed by the server's private key.
+/// `server_addrs` must be the server's public addresses, the client will connect to them.
+pub fn issue_connect_token(
+    private_key: &[u8; NETCODE_KEY_BYTES],
+    server_addrs: Vec<SocketAddr>,
+    username: &str,
+) -> Result<ConnectToken, TokenGenerationError> {
+    ConnectToken::generate(
+        crate::util::current_timestamp(),
+        PROTOCOL_ID,
+        TOKEN_EXPIRE_SECS,
+        user_id_of(username),
+        TOKEN_TIMEOUT_SECS,
+        server_addrs,
+        Some(&encode_user_data(username)),
+        private_key,
+    )
+}
+
+pub fn token_to_bytes(token: &ConnectToken) -> Vec<u8> {
+    let mut buf = Vec::new();
+    token.write(&mut buf).unwrap();
+    buf
+}
+
+pub fn token_from_bytes(bytes: &[u8]) -> anyhow::Result<ConnectToken> {
+    ConnectToken::read(&mut Cursor::new(bytes)).map_err(|err| anyhow::anyhow!("Invalid ConnectToken: {}", err))
+}
+
+pub fn write_token_file(path: impl AsRef<Path>, token: &ConnectToken) -> std::io::Result<()> {
+    std::fs::write(path, token_to_bytes(token))
+}
+
+pub fn read_token_file(path: impl AsRef<Path>) -> anyhow::Result<ConnectToken> {
+    token_from_bytes(&std::fs::read(path)?)
+}
+
+/// Acquire the client authentication for connecting to the server.
+/// Priority: token file, then the auth service. if neither is configured, fallback to Unsecure (LAN).
+/// the Unsecure client id only identifies the connection, the user is identified by `user_id_of` the username.
+pub fn client_authentication(
+    token_file: &str,
+    auth_service_url: &str,
+    server_addr: SocketAddr,
+    username: &str,
+) -> anyhow::Result<ClientAuthentication> {
+    let connect_token = if !token_file.is_empty() {
+        read_token_file(token_file)?
+    } else if !auth_service_url.is_empty() {
+        let url = reqwest::Url::parse_with_params(auth_service_url, &[("username", username.to_string()), ("server", server_addr.to_string())])?;
+        token_from_bytes(&crate::util::http_get_bytes(url.as_str())?)?
+    } else {
+        return Ok(ClientAuthentication::Unsecure {
+            protocol_id: PROTOCOL_ID,
+            client_id: crate::util::current_timestamp_millis(),
+            server_addr,
+            user_data: Some(encode_user_data(username)),
+        });
+    };
+
+    if connect_token.protocol_id != PROTOCOL_ID {
+        anyhow::bail!("ConnectToken protocol mismatch. token {}, client {}", connect_token.protocol_id, PROTOCOL_ID);
+    }
+    Ok(ClientAuthentication::Secure { connect_token })
+}
@@ -0,0 +1,115 @@
+//! Packet Encoding/Decoding.
+//!
+//! All packets go through here instead of raw `bincode::serialize/deserialize`. The decoding is size limited,
+//! so malformed or out-of-version input from the remote returns an Err instead of panic or huge allocations.
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Max bytes of a single encoded packet. a full ChunkNew is about 40 KB.
+pub const MAX_PACKET_SIZE: u64 = 1024 * 1024;
+
+/// Bad packets a connection may send before it gets disconnected.
+pub const MAX_BAD_PACKETS: u32 = 3;
+
+// same wire format as bincode::serialize (fixint, little endian), plus the size limit and rejects trailing bytes.
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding().with_limit(MAX_PACKET_SIZE)
+}
+
+pub fn encode<P: Serialize>(packet: &P) -> Vec<u8> {
+    options().serialize(packet).expect("Failed to encode packet")
+}
+
+pub fn decode<P: DeserializeOwned>(bytes: &[u8]) -> Result<P, bincode::Error> {
+    if bytes.len() as u64 > MAX_PACKET_SIZE {
+        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+    }
+    options().deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec3, Vec3};
+    use rand::{Rng, RngCore, SeedableRng};
+
+    use super::*;
+    use crate::net::{CPacket, CellData, ConnectionState, SPacket};
+
+    fn random_bytes(rng: &mut impl RngCore, max_len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; rng.random_range(0..max_len)];
+        rng.fill_bytes(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn fuzz_decode_random_bytes() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5EED);
+        for _ in 0..20_000 {
+            let bytes = random_bytes(&mut rng, 256);
+            let _ = decode::<CPacket>(&bytes);
+            let _ = decode::<SPacket>(&bytes);
+        }
+    }
+
+    #[test]
+    fn fuzz_decode_mutated_packets() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0xF022);
+        let samples = [
+            encode(&CPacket::Handshake {
+                protocol_version: 18,
+                next_state: ConnectionState::Login,
+            }),
+            encode(&CPacket::Login {
+                uuid: 1,
+                access_token: 2,
+                username: "Steven".into(),
+            }),
+            encode(&CPacket::ChunkModify {
+                chunkpos: IVec3::new(16, 0, -16),
+                voxel: vec![CellData::default(); 8],
+            }),
+            encode(&SPacket::EntityPos {
+                entity_id: crate::net::EntityId(7),
+                position: Vec3::ONE,
+                yaw: 0.5,
+                seq: 42,
+                tick: 1000,
+            }),
+        ];
+        for _ in 0..20_000 {
+            let mut bytes = samples[rng.random_range(0..samples.len())].clone();
+            // flip, truncate or extend
+            match rng.random_range(0..3) {
+                0 => {
+                    let i = rng.random_range(0..bytes.len());
+                    bytes[i] = rng.random();
+                }
+                1 => bytes.truncate(rng.random_range(0..bytes.len())),
+                _ => bytes.extend(random_bytes(&mut rng, 16)),
+            }
+            let _ = decode::<CPacket>(&bytes);
+            let _ = decode::<SPacket>(&bytes);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_huge_length_prefix() {
+        // Login with username length u64::MAX
+        let mut bytes = encode(&CPacket::Login {
+            uuid: 0,
+            access_token: 0,
+            username: String::new(),
+        });
+        let n = bytes.len();
+        bytes[n - 8..].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(decode::<CPacket>(&bytes).is_err());
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let bytes = encode(&CPacket::ChatMessage { message: "Hello".into() });
+        assert_eq!(bytes, bincode::serialize(&CPacket::ChatMessage { message: "Hello".into() }).unwrap());
+        assert!(matches!(decode(&bytes), Ok(CPacket::ChatMessage { message }) if message == "Hello"));
+    }
+}
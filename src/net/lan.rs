@@ -0,0 +1,196 @@
+//! LAN Discovery.
+//!
+//! A server opened to LAN (see `IntegratedServer`) broadcasts an announcement every ANNOUNCE_INTERVAL
+//! to LAN_DISCOVERY_PORT. Clients listen on that port while the server list is shown, and list the announced games.
+//! Announcement: LAN_MAGIC + bincode(LanAnnouncement). the game address is the sender ip + `game_port`.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::{codec, PROTOCOL_VERSION};
+use crate::server::prelude::{ServerInfo, ServerSettings};
+
+pub const LAN_MAGIC: &[u8; 4] = b"ETLN";
+pub const LAN_DISCOVERY_PORT: u16 = 4445;
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// a discovered game is removed if not announced again in this time.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanAnnouncement {
+    pub game_port: u16,
+    pub motd: String,
+    pub num_players_online: u32,
+    pub num_players_limit: u32,
+    pub protocol_version: u64,
+}
+
+impl LanAnnouncement {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = LAN_MAGIC.to_vec();
+        data.extend(codec::encode(self));
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        codec::decode(data.strip_prefix(LAN_MAGIC)?).ok()
+    }
+}
+
+/// the ip of this machine in the local network. the interface of the default route, no packet is sent.
+pub fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_unspecified())
+}
+
+/// Server: announces the game to the LAN.
+#[derive(Resource)]
+pub struct LanAnnouncer {
+    socket: UdpSocket,
+    target: SocketAddr,
+    last_sent: Option<Instant>,
+}
+
+impl LanAnnouncer {
+    /// broadcast to the discovery port of the local network.
+    pub fn broadcast() -> std::io::Result<Self> {
+        Self::new(SocketAddr::new(Ipv4Addr::BROADCAST.into(), LAN_DISCOVERY_PORT))
+    }
+
+    pub fn new(target: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        Ok(Self { socket, target, last_sent: None })
+    }
+
+    pub fn announce(&self, announcement: &LanAnnouncement) -> std::io::Result<()> {
+        self.socket.send_to(&announcement.encode(), self.target).map(|_| ())
+    }
+}
+
+pub fn lan_announce_sys(mut announcer: ResMut<LanAnnouncer>, serverinfo: Res<ServerInfo>, cfg: Res<ServerSettings>) {
+    if announcer.last_sent.is_some_and(|t| t.elapsed() < ANNOUNCE_INTERVAL) {
+        return;
+    }
+    announcer.last_sent = Some(Instant::now());
+
+    let announcement = LanAnnouncement {
+        game_port: cfg.port,
+        motd: cfg.motd.clone(),
+        num_players_online: serverinfo.online_players.len() as u32,
+        num_players_limit: cfg.num_player_limit,
+        protocol_version: PROTOCOL_VERSION,
+    };
+    if let Err(err) = announcer.announce(&announcement) {
+        warn!("Failed to announce the LAN game: {}", err);
+    }
+}
+
+pub struct LanGame {
+    pub game_addr: SocketAddr,
+    pub announcement: LanAnnouncement,
+    pub last_seen: Instant,
+}
+
+/// Client: listens for the announced LAN games.
+pub struct LanDiscovery {
+    socket: UdpSocket,
+    pub games: Vec<LanGame>,
+}
+
+impl LanDiscovery {
+    /// listen on the discovery port.
+    pub fn bind() -> std::io::Result<Self> {
+        Self::bind_addr(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), LAN_DISCOVERY_PORT))
+    }
+
+    /// bound with address reuse, so several clients on the machine can listen for the announcements.
+    pub fn bind_addr(addr: SocketAddr) -> std::io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        socket.bind(&addr.into())?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket: socket.into(),
+            games: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// receive the pending announcements, and forget the games not announced for a while.
+    pub fn poll(&mut self) {
+        let mut buf = [0u8; 1024];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buf) {
+            let Some(announcement) = LanAnnouncement::decode(&buf[..len]) else {
+                continue;
+            };
+            let game_addr = SocketAddr::new(from.ip(), announcement.game_port);
+            let last_seen = Instant::now();
+            match self.games.iter_mut().find(|g| g.game_addr == game_addr) {
+                Some(game) => {
+                    game.announcement = announcement;
+                    game.last_seen = last_seen;
+                }
+                None => self.games.push(LanGame {
+                    game_addr,
+                    announcement,
+                    last_seen,
+                }),
+            }
+        }
+        self.games.retain(|g| g.last_seen.elapsed() < DISCOVERY_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announce_discover_loopback() {
+        let mut discovery = LanDiscovery::bind_addr("127.0.0.1:0".parse().unwrap()).unwrap();
+        let announcer = LanAnnouncer::new(discovery.local_addr().unwrap()).unwrap();
+
+        let announcement = LanAnnouncement {
+            game_port: 6123,
+            motd: "My World".into(),
+            num_players_online: 1,
+            num_players_limit: 8,
+            protocol_version: PROTOCOL_VERSION,
+        };
+        announcer.announce(&announcement).unwrap();
+        announcer.announce(&announcement).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while discovery.games.is_empty() && Instant::now() < deadline {
+            discovery.poll();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        discovery.poll();
+
+        // announced twice, listed once.
+        assert_eq!(discovery.games.len(), 1);
+        assert_eq!(discovery.games[0].game_addr, "127.0.0.1:6123".parse().unwrap());
+        assert_eq!(discovery.games[0].announcement, announcement);
+    }
+
+    #[test]
+    fn ignore_foreign_datagrams() {
+        assert_eq!(LanAnnouncement::decode(b"ETQY1234"), None);
+        assert_eq!(LanAnnouncement::decode(LAN_MAGIC), None);
+    }
+}
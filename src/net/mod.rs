@@ -1,79 +1,91 @@
 use serde::{Deserialize, Serialize};
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    net::{SocketAddr, UdpSocket},
     time::Duration,
 };
 
 use bevy::prelude::*;
 use bevy_renet::renet::{
-    ChannelConfig, ClientId, DefaultChannel, RenetClient, RenetServer, SendType,
-};
-use bevy_renet::netcode::{
-    ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport, ServerAuthentication, ServerConfig, NETCODE_USER_DATA_BYTES,
+    ChannelConfig, ClientId, ConnectionConfig, RenetClient, RenetServer, SendType,
 };
+use bevy_renet::netcode::{ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport, ServerAuthentication, ServerConfig};
 
+pub mod auth;
+pub mod codec;
+pub mod lan;
 pub mod netproc_client;
 mod netproc_server;
 mod packet;
+pub mod query;
+pub mod replication;
 
 use crate::util::current_timestamp;
 pub use netproc_client::ClientNetworkPlugin;
 pub use netproc_server::ServerNetworkPlugin;
-pub use packet::{CPacket, CellData, SPacket};
+pub use packet::{seq_newer, CPacket, CellData, ConnectionState, MiningAction, NetChannel, Packet, SPacket, ServerStatus};
 
+/// netcode protocol id. connections with a different id are silently dropped by netcode, so it should rarely change.
 const PROTOCOL_ID: u64 = 1;
 
-pub fn new_netcode_server_transport(public_addr_port: u16, max_clients: usize) -> NetcodeServerTransport {
-    let public_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), public_addr_port);
-    let socket = UdpSocket::bind(public_addr).unwrap();
+/// Game protocol version, negotiated in the Handshake. a mismatch is reported to the client as Disconnect reason.
+pub const PROTOCOL_VERSION: u64 = 18;
+
+/// height of the eyes above the player position. the server checks drowning and placing by it, the client puts the camera there.
+pub const EYE_HEIGHT: f32 = 0.8;
+
+/// number of crack stages of a mined voxel, the range of the `SPacket::BreakProgress` stage.
+pub const BREAK_STAGES: u64 = 10;
+
+/// `public_addresses`: addresses the clients connect to, they must match the server addresses in ConnectTokens.
+pub fn new_netcode_server_transport(
+    bind_addr: SocketAddr,
+    max_clients: usize,
+    authentication: ServerAuthentication,
+    public_addresses: Vec<SocketAddr>,
+) -> NetcodeServerTransport {
+    let socket = UdpSocket::bind(bind_addr).unwrap();
     let server_config = ServerConfig {
         current_time: current_timestamp(),
         max_clients,
         protocol_id: PROTOCOL_ID,
-        public_addresses: vec![public_addr],
-        authentication: ServerAuthentication::Unsecure,
+        public_addresses,
+        authentication,
     };
     NetcodeServerTransport::new(server_config, socket).unwrap()
 }
 
-pub fn new_netcode_client_transport(server_addr: SocketAddr, user_data: Option<Vec<u8>>) -> NetcodeClientTransport {
+pub fn new_netcode_client_transport(authentication: ClientAuthentication) -> NetcodeClientTransport {
     // let server_addr = "127.0.0.1:5000".parse().unwrap();
     let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-    let current_time = current_timestamp();
-    let client_id = current_time.as_millis() as u64;
-
-    let user_data = user_data.map(|vec| {
-        let mut data = [0u8; NETCODE_USER_DATA_BYTES];
-        assert!(vec.len() <= NETCODE_USER_DATA_BYTES);
-        data[..vec.len()].copy_from_slice(&vec[..]);
-        data
-    });
+    NetcodeClientTransport::new(current_timestamp(), authentication, socket).unwrap()
+}
 
-    let authentication = ClientAuthentication::Unsecure {
-        protocol_id: PROTOCOL_ID,
-        client_id,
-        server_addr,
-        user_data,
-    };
-    NetcodeClientTransport::new(current_time, authentication, socket).unwrap()
+/// Shared by the server and the client, both sides must have the same channels.
+pub fn connection_config() -> ConnectionConfig {
+    ConnectionConfig {
+        server_channels_config: net_channel_config(20 * 1024 * 1024),
+        client_channels_config: net_channel_config(5 * 1024 * 1024),
+        ..default()
+    }
 }
 
+/// Channels of `Packet::channel`. see `NetChannel`
 fn net_channel_config(max_memory_usage_bytes: usize) -> Vec<ChannelConfig> {
     vec![
         ChannelConfig {
-            channel_id: 0,
+            channel_id: NetChannel::Unreliable.into(),
             max_memory_usage_bytes,
             send_type: SendType::Unreliable,
         },
         ChannelConfig {
-            channel_id: 1,
+            channel_id: NetChannel::Chunk.into(),
             max_memory_usage_bytes,
-            send_type: SendType::ReliableUnordered {
+            send_type: SendType::ReliableOrdered {
                 resend_time: Duration::from_millis(300),
             },
         },
         ChannelConfig {
-            channel_id: 2,
+            channel_id: NetChannel::ReliableOrdered.into(),
             max_memory_usage_bytes,
             send_type: SendType::ReliableOrdered {
                 resend_time: Duration::from_millis(300),
@@ -82,21 +94,21 @@ fn net_channel_config(max_memory_usage_bytes: usize) -> Vec<ChannelConfig> {
     ]
 }
 
-// An unique id shared in Server and Client. in client with a big offset to avoid id collision.
+// An unique id of a server entity, shared in Server and Client. clients map it to their own entities by `replication::EntityMap`.
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct EntityId(u32);
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(u64);
 
 impl EntityId {
     pub fn from_server(entity: Entity) -> EntityId {
-        EntityId(entity.index())
+        EntityId(entity.to_bits())
     }
 
-    pub fn client_entity(&self) -> Entity {
-        Entity::from_raw(10_000 + self.0)
+    pub fn server_entity(&self) -> Entity {
+        Entity::from_bits(self.0)
     }
 
-    pub fn raw(&self) -> u32 {
+    pub fn raw(&self) -> u64 {
         self.0
     }
 }
@@ -164,22 +176,25 @@ impl EntityId {
 //     });
 // }
 
+/// Channels to receive from, in order of the draining.
+pub const RECV_CHANNELS: [NetChannel; 3] = [NetChannel::ReliableOrdered, NetChannel::Chunk, NetChannel::Unreliable];
+
 pub trait RenetServerHelper {
-    fn send_packet<P: Serialize>(&mut self, client_id: ClientId, packet: &P);
+    fn send_packet<P: Packet>(&mut self, client_id: ClientId, packet: &P);
 
     fn send_packet_disconnect(&mut self, client_id: ClientId, reason: String);
 
     fn send_packet_chat(&mut self, client_id: ClientId, message: String);
 
-    fn broadcast_packet<P: Serialize>(&mut self, packet: &P);
+    fn broadcast_packet<P: Packet>(&mut self, packet: &P);
 
-    fn broadcast_packet_except<P: Serialize>(&mut self, except_id: ClientId, packet: &P);
+    fn broadcast_packet_except<P: Packet>(&mut self, except_id: ClientId, packet: &P);
 
     fn broadcast_packet_chat(&mut self, message: String);
 }
 impl RenetServerHelper for RenetServer {
-    fn send_packet<P: Serialize>(&mut self, client_id: ClientId, packet: &P) {
-        self.send_message(client_id, DefaultChannel::ReliableOrdered, bincode::serialize(packet).unwrap());
+    fn send_packet<P: Packet>(&mut self, client_id: ClientId, packet: &P) {
+        self.send_message(client_id, packet.channel(), codec::encode(packet));
     }
     fn send_packet_disconnect(&mut self, client_id: ClientId, reason: String) {
         self.send_packet(client_id, &SPacket::Disconnect { reason });
@@ -187,11 +202,11 @@ impl RenetServerHelper for RenetServer {
     fn send_packet_chat(&mut self, client_id: ClientId, message: String) {
         self.send_packet(client_id, &SPacket::Chat { message });
     }
-    fn broadcast_packet<P: Serialize>(&mut self, packet: &P) {
-        self.broadcast_message(DefaultChannel::ReliableOrdered, bincode::serialize(packet).unwrap());
+    fn broadcast_packet<P: Packet>(&mut self, packet: &P) {
+        self.broadcast_message(packet.channel(), codec::encode(packet));
     }
-    fn broadcast_packet_except<P: Serialize>(&mut self, except_id: ClientId, packet: &P) {
-        self.broadcast_message_except(except_id, DefaultChannel::ReliableOrdered, bincode::serialize(packet).unwrap());
+    fn broadcast_packet_except<P: Packet>(&mut self, except_id: ClientId, packet: &P) {
+        self.broadcast_message_except(except_id, packet.channel(), codec::encode(packet));
     }
     fn broadcast_packet_chat(&mut self, message: String) {
         info!("[BroadcastChat] {}", &message);
@@ -200,10 +215,10 @@ impl RenetServerHelper for RenetServer {
 }
 
 pub trait RenetClientHelper {
-    fn send_packet<P: Serialize>(&mut self, packet: &P);
+    fn send_packet<P: Packet>(&mut self, packet: &P);
 }
 impl RenetClientHelper for RenetClient {
-    fn send_packet<P: Serialize>(&mut self, packet: &P) {
-        self.send_message(DefaultChannel::ReliableOrdered, bincode::serialize(packet).unwrap());
+    fn send_packet<P: Packet>(&mut self, packet: &P) {
+        self.send_message(packet.channel(), codec::encode(packet));
     }
 }
@@ -2,7 +2,7 @@
 
 use bevy::{ecs::system::EntityCommands, prelude::*, platform::collections::HashMap};
 use bevy_renet::{
-    renet::{DefaultChannel, DisconnectReason, RenetClient},
+    renet::{DisconnectReason, RenetClient},
     netcode::NetcodeClientPlugin,
     RenetClientPlugin,
 };
@@ -11,12 +11,19 @@ use avian3d::prelude::*;
 use crate::{
     client::prelude::*,
     client::ui::CurrentUI,
+    item::Items,
+    server::prelude::IntegratedServer,
     util::{current_timestamp_millis, AsMutRef},
-    voxel::{Chunk, ChunkSystem, ClientChunkSystem},
+    voxel::{Chunk, ChunkSystem, ClientChunkSystem, RemoteMining},
     util::BevyEcsCommandsExt,
 };
 
-use super::{packet::CellData, SPacket};
+use super::{
+    codec,
+    packet::CellData,
+    replication::{ComponentData, EntityMap, EntityType, NetEntity},
+    ConnectionState, SPacket,
+};
 
 pub struct ClientNetworkPlugin;
 
@@ -25,6 +32,8 @@ impl Plugin for ClientNetworkPlugin {
         app.add_plugins(RenetClientPlugin);
         app.add_plugins(NetcodeClientPlugin);
 
+        app.insert_resource(EntityMap::default());
+
         // 待考证: resource_exists::<RenetClient> 之前会造成 获取未加载的ChunkSystemClient
         app.add_systems(Update, client_sys.run_if(condition::in_world));
 
@@ -49,6 +58,11 @@ pub fn client_sys(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
     // entity_s2c: Local<HashMap<Entity, Entity>>,
+    mut entity_snapshots: ResMut<EntitySnapshots>,
+    mut entity_map: ResMut<EntityMap>,
+    mut player: ResMut<ClientPlayerInfo>,
+    mut items: ResMut<Items>,
+    time: Res<Time>,
 ) {
     if *last_connected != 1 && net_client.is_connecting() {
         *last_connected = 1;
@@ -56,12 +70,17 @@ pub fn client_sys(
         *last_connected = 2;
     } else if *last_connected != 0 && net_client.is_disconnected() {
         *last_connected = 0;
+        cli.conn_state = ConnectionState::Handshake;
+        entity_snapshots.reset(0);
+        entity_map.s2c.clear(); // the entities are despawned with the world.
+        items.clear_server_registry();
 
         if cli.disconnected_reason.is_empty() {
             cli.disconnected_reason = net_client.disconnect_reason().unwrap().to_string();
         }
 
         cmds.remove_resource::<WorldInfo>(); // todo: cli.close_world();
+        cmds.remove_resource::<IntegratedServer>(); // the local world is saved and closed too.
         if net_client.disconnect_reason().unwrap() != DisconnectReason::DisconnectedByClient {
             cli.curr_ui = CurrentUI::DisconnectedReason;
         }
@@ -69,132 +88,286 @@ pub fn client_sys(
         info!("Disconnected. {}", cli.disconnected_reason);
     }
 
-    while let Some(bytes) = net_client.receive_message(DefaultChannel::ReliableOrdered) {
-        // info!("CLI Recv PACKET: {}", String::from_utf8_lossy(&bytes));
-        let packet: SPacket = bincode::deserialize(&bytes[..]).unwrap();
-        match &packet {
-            SPacket::Disconnect { reason } => {
-                info!("DisconnectedPacket: {}", reason);
-                cli.disconnected_reason.clone_from(reason);
-                net_client.disconnect_due_to_transport();
-            }
-            // SPacket::ServerInfo {
-            //     motd,
-            //     num_players_limit,
-            //     num_players_online,
-            //     protocol_version,
-            //     favicon,
-            // } => {
-            //     info!("ServerInfo: {:?}", &packet);
-            // }
-            SPacket::Pong { client_time, server_time } => {
-                let curr = current_timestamp_millis();
-
-                cli.ping = (
-                    curr - *client_time,
-                    *server_time as i64 - *client_time as i64,
-                    curr as i64 - *server_time as i64,
-                    *client_time,
-                );
-                // info!("Ping: rtt {}ms = c2s {} + s2c {}", cli.ping.0, cli.ping.1, cli.ping.2);
-            }
-            SPacket::LoginSuccess { player_entity } => {
-                info!("Login Success!");
-
-                cli.curr_ui = CurrentUI::None;
-
-                spawn_player(
-                    &mut cmds.get_or_spawn(player_entity.client_entity()), // 为什么在这生成 因为要指定id，以及其他player也是在这生成
-                    true,
-                    &cfg.username,
-                    &asset_server,
-                    &mut meshes,
-                    &mut materials,
-                );
+    'recv: for channel in super::RECV_CHANNELS {
+        while let Some(bytes) = net_client.receive_message(channel) {
+            // info!("CLI Recv PACKET: {}", String::from_utf8_lossy(&bytes));
+            let packet: SPacket = match codec::decode(&bytes) {
+                Ok(packet) => packet,
+                Err(err) => {
+                    error!("Bad packet from server: {}", err);
+                    cli.disconnected_reason = format!("Bad packet from server: {}", err);
+                    net_client.disconnect_due_to_transport();
+                    break 'recv;
+                }
+            };
 
-                // cmds.insert_resource(WorldInfo::default());  // moved to Click Connect. 要在用之前初始化，如果现在标记 那么就来不及初始化 随后就有ChunkNew数据包 要用到资源
-            }
-            SPacket::Chat { message } => {
-                info!("[Chat]: {}", message);
-                chats.scrollback.push(message.clone());
+            // e.g. broadcasted play packets before login success. just ignore them.
+            if !packet.is_valid_in(cli.conn_state) {
+                debug!("Ignored packet in {:?} state", cli.conn_state);
+                continue;
             }
-            SPacket::EntityNew { entity_id, name } => {
-                info!("Spawn EntityNew {}", entity_id.raw());
-
-                // 客户端此时可能的确存在这个entity 因为内置的broadcast EntityPos 会发给所有客户端，包括没登录的
-                // assert!(cmds.get_entity(entity_id.client_entity()).is_none(), "The EntityId already occupied in client.");
-
-                spawn_player(
-                    &mut cmds.get_or_spawn(entity_id.client_entity()),
-                    false,
-                    name,
-                    &asset_server,
-                    &mut meshes,
-                    &mut materials,
-                );
-            }
-            SPacket::EntityPos { entity_id, position } => {
-                info!("EntityPos {} -> {}", entity_id.raw(), position);
 
-                cmds.get_or_spawn(entity_id.client_entity())
-                    .insert(Transform::from_translation(*position));
-            }
-            SPacket::EntityDel { entity_id } => {
-                info!("DeSpawn EntityDel {}", entity_id.raw());
+            match &packet {
+                SPacket::Disconnect { reason } => {
+                    info!("DisconnectedPacket: {}", reason);
+                    cli.disconnected_reason.clone_from(reason);
+                    net_client.disconnect_due_to_transport();
+                }
+                SPacket::ServerInfo(status) => {
+                    info!("ServerInfo: {} {}/{} (protocol {})", status.motd, status.num_players_online, status.num_players_limit, status.protocol_version);
+                }
+                SPacket::Pong { client_time, server_time } => {
+                    let curr = current_timestamp_millis();
+
+                    cli.ping = (
+                        curr - *client_time,
+                        *server_time as i64 - *client_time as i64,
+                        curr as i64 - *server_time as i64,
+                        *client_time,
+                    );
+                    // info!("Ping: rtt {}ms = c2s {} + s2c {}", cli.ping.0, cli.ping.1, cli.ping.2);
+                }
+                SPacket::LoginSuccess {
+                    player_entity,
+                    tick_rate,
+                    position,
+                    seed,
+                } => {
+                    info!("Login Success!");
+                    cli.conn_state = ConnectionState::Play;
+                    entity_snapshots.reset(*tick_rate);
+                    worldinfo.seed = *seed;
+
+                    cli.curr_ui = CurrentUI::None;
+
+                    let mut ec = cmds.spawn(NetEntity {
+                        entity_id: *player_entity,
+                        entity_type: EntityType::Player,
+                    });
+                    entity_map.s2c.insert(*player_entity, ec.id());
+                    spawn_player(
+                        &mut ec,
+                        true,
+                        &cfg.username,
+                        &asset_server,
+                        &mut meshes,
+                        &mut materials,
+                    );
+                    // moved once the player entity is spawned.
+                    player.pending_teleport = Some(*position);
+
+                    // cmds.insert_resource(WorldInfo::default());  // moved to Click Connect. 要在用之前初始化，如果现在标记 那么就来不及初始化 随后就有ChunkNew数据包 要用到资源
+                }
+                SPacket::Chat { message } => {
+                    info!("[Chat]: {}", message);
+                    chats.scrollback.push(message.clone());
+                }
+                SPacket::EntityNew {
+                    entity_id,
+                    entity_type,
+                    position,
+                    yaw,
+                    components,
+                } => {
+                    info!("Spawn EntityNew {} {:?}", entity_id.raw(), entity_type);
+
+                    if let Some(old) = entity_map.s2c.remove(entity_id) {
+                        warn!("EntityNew {} already exists, respawn", entity_id.raw());
+                        cmds.entity(old).despawn_recursive();
+                    }
+                    entity_snapshots.remove(*entity_id);
+
+                    let name = components.iter().find_map(|c| match c {
+                        ComponentData::Name(name) => Some(name.clone()),
+                        _ => None,
+                    });
+
+                    let mut ec = cmds.spawn(NetEntity {
+                        entity_id: *entity_id,
+                        entity_type: *entity_type,
+                    });
+                    entity_map.s2c.insert(*entity_id, ec.id());
+                    match entity_type {
+                        EntityType::Player => {
+                            spawn_player(&mut ec, false, &name.clone().unwrap_or_default(), &asset_server, &mut meshes, &mut materials);
+                        }
+                        EntityType::ItemDrop => {
+                            // the visual is added with the ItemDropVisual component
+                            ec.insert((Transform::default(), Visibility::default(), DespawnOnWorldUnload));
+                        }
+                        _ => {
+                            spawn_entity_placeholder(&mut ec, &mut meshes, &mut materials);
+                        }
+                    }
+                    ec.insert(Transform::from_translation(*position).with_rotation(Quat::from_rotation_y(*yaw)));
+                    apply_components(&mut ec, components, &items);
+                }
+                SPacket::EntityUpdate { entity_id, components } => {
+                    if let Some(entity) = entity_map.get(*entity_id) {
+                        apply_components(&mut cmds.entity(entity), components, &items);
+                    }
+                }
+                SPacket::EntityPos {
+                    entity_id,
+                    position,
+                    yaw,
+                    seq,
+                    tick,
+                } => {
+                    if entity_map.get(*entity_id).is_none() {
+                        continue; // not yet spawned, or already despawned. EntityNew carries the position.
+                    }
+                    let snapshot = Snapshot {
+                        tick: *tick,
+                        position: *position,
+                        yaw: *yaw,
+                    };
+                    if !entity_snapshots.receive(*entity_id, *seq, snapshot, time.elapsed_secs_f64()) {
+                        continue; // outdated, reordered by the unreliable channel.
+                    }
+                    debug!("EntityPos {} -> {} @{}", entity_id.raw(), position, tick);
+                    // applied to the Transform by the interpolation.
+                }
+                SPacket::EntityDel { entity_id } => {
+                    info!("DeSpawn EntityDel {}", entity_id.raw());
+                    entity_snapshots.remove(*entity_id);
 
-                cmds.get_entity(entity_id.client_entity()).unwrap().despawn_recursive();
-            }
-            SPacket::PlayerList { playerlist } => {
-                cli.playerlist.clone_from(playerlist); // should move?
-            }
-            SPacket::WorldTime { daytime } => {
-                worldinfo.daytime = *daytime;
-            }
-            SPacket::ChunkNew { chunkpos, voxel } => {
-                let mut chunk = Chunk::new(*chunkpos);
+                    if let Some(entity) = entity_map.s2c.remove(entity_id) {
+                        cmds.entity(entity).despawn_recursive();
+                    }
+                }
+                SPacket::BreakProgress { entity_id, pos, stage } => {
+                    let Some(entity) = entity_map.get(*entity_id) else {
+                        continue;
+                    };
+                    if *stage < 0 {
+                        cmds.entity(entity).remove::<RemoteMining>();
+                    } else {
+                        cmds.entity(entity).insert(RemoteMining { pos: *pos, stage: *stage });
+                    }
+                }
+                SPacket::PickupItem { entity_id, stack } => {
+                    info!("Pickup {:?} from {}", stack, entity_id.raw());
+                }
+                SPacket::PlayerHealth { health, health_max } => {
+                    player.health = *health;
+                    player.health_max = *health_max;
+                    if *health == 0 {
+                        cli.curr_ui = CurrentUI::Respawn;
+                    } else if cli.curr_ui == CurrentUI::Respawn {
+                        cli.curr_ui = CurrentUI::None;
+                    }
+                }
+                SPacket::PlayerRespawn { position } | SPacket::PlayerCorrection { position } => {
+                    player.pending_teleport = Some(*position);
+                }
+                SPacket::PlayerGameMode { game_mode } => {
+                    info!("GameMode: {:?}", game_mode);
+                    player.game_mode = *game_mode;
+                }
+                SPacket::ItemRegistry { items: str_ids } => {
+                    items.set_server_registry(str_ids);
+                }
+                SPacket::InventoryContent {
+                    items: stacks,
+                    cursor,
+                    hotbar_index,
+                } => {
+                    player.inventory.items = stacks.iter().map(|stack| items.from_server(*stack)).collect();
+                    player.cursor = items.from_server(*cursor);
+                    player.hotbar_index = *hotbar_index as u32;
+                }
+                SPacket::InventorySlots { slots, cursor } => {
+                    for (slot, stack) in slots {
+                        if let Some(s) = player.inventory.items.get_mut(*slot as usize) {
+                            *s = items.from_server(*stack);
+                        }
+                    }
+                    player.cursor = items.from_server(*cursor);
+                }
+                SPacket::CraftingContent { grid, result } => {
+                    player.crafting_grid.items = grid.iter().map(|stack| items.from_server(*stack)).collect();
+                    player.crafting_result = items.from_server(*result);
+                }
+                SPacket::PlayerList { playerlist } => {
+                    cli.playerlist.clone_from(playerlist); // should move?
+                }
+                SPacket::WorldTime { daytime, daytime_length } => {
+                    worldinfo.daytime = *daytime;
+                    worldinfo.daytime_length = *daytime_length;
+                }
+                SPacket::ChunkNew { chunkpos, voxel } => {
+                    let mut chunk = Chunk::new(*chunkpos);
 
-                CellData::to_chunk(voxel, &mut chunk);
+                    CellData::to_chunk(voxel, &mut chunk);
 
-                chunk_sys.spawn_chunk(chunk, &mut cmds, &mut *meshes);
+                    chunk_sys.spawn_chunk(chunk, &mut cmds, &mut *meshes);
 
-                // info!("ChunkNew: {} ({})", chunkpos, chunk_sys.num_chunks());
-            }
-            SPacket::ChunkDel { chunkpos } => {
-                error!("ChunkDel: {} ({})", chunkpos, chunk_sys.num_chunks());
-
-                //     if let Some(chunkptr) = chunk_sys.despawn_chunk(*chunkpos) {
-                //         let entity = chunkptr.entity;
-
-                //         // bug crash: "Attempting to create an EntityCommands for entity 9649v15, which doesn't exist."
-                //         // why the entity may not exists even if it in the chunk_sys?
-                //         if let Some(cmds) = cmds.get_entity(entity) {
-                //             cmds.despawn_recursive();
-                //         }
-                //     }
-            }
-            SPacket::ChunkModify { chunkpos, voxel } => {
-                info!("ChunkModify: {}", chunkpos);
+                    // info!("ChunkNew: {} ({})", chunkpos, chunk_sys.num_chunks());
+                }
+                SPacket::ChunkDel { chunkpos } => {
+                    error!("ChunkDel: {} ({})", chunkpos, chunk_sys.num_chunks());
+
+                    //     if let Some(chunkptr) = chunk_sys.despawn_chunk(*chunkpos) {
+                    //         let entity = chunkptr.entity;
+
+                    //         // bug crash: "Attempting to create an EntityCommands for entity 9649v15, which doesn't exist."
+                    //         // why the entity may not exists even if it in the chunk_sys?
+                    //         if let Some(cmds) = cmds.get_entity(entity) {
+                    //             cmds.despawn_recursive();
+                    //         }
+                    //     }
+                }
+                SPacket::ChunkModify { chunkpos, voxel } => {
+                    info!("ChunkModify: {}", chunkpos);
+
+                    chunk_sys.mark_chunk_remesh(*chunkpos);
+
+                    // 这不全面。如果修改了edge 那么应该更新3个区块。然而这里只会更新一个区块
+                    for data in voxel {
+                        let lp = Chunk::local_idx_pos(data.local_idx as i32);
+                        let neib = Chunk::at_boundary_naive(lp);
+                        if neib != -1 {
+                            chunk_sys.mark_chunk_remesh(*chunkpos + Chunk::NEIGHBOR_DIR[neib as usize] * Chunk::LEN);
+                        }
+                    }
 
-                chunk_sys.mark_chunk_remesh(*chunkpos);
+                    // todo: NonLock
+                    let Some(chunk) = chunk_sys.get_chunk(*chunkpos) else {
+                        warn!("ChunkModify on a missing chunk {}", chunkpos);
+                        continue;
+                    };
 
-                // 这不全面。如果修改了edge 那么应该更新3个区块。然而这里只会更新一个区块
-                for data in voxel {
-                    let lp = Chunk::local_idx_pos(data.local_idx as i32);
-                    let neib = Chunk::at_boundary_naive(lp);
-                    if neib != -1 {
-                        chunk_sys.mark_chunk_remesh(*chunkpos + Chunk::NEIGHBOR_DIR[neib as usize] * Chunk::LEN);
-                    }
+                    CellData::to_chunk(voxel, chunk.as_mut());
                 }
+            }
+        }
+    }
+}
 
-                // todo: NonLock
-                let chunk = chunk_sys.get_chunk(*chunkpos).unwrap();
-
-                CellData::to_chunk(voxel, chunk.as_mut());
+fn apply_components(ec: &mut EntityCommands, components: &[ComponentData], items: &Items) {
+    for component in components {
+        match component {
+            ComponentData::Name(name) => {
+                ec.insert(Name::new(name.clone()));
+            }
+            ComponentData::Item(stack) => {
+                ec.insert(ItemDropVisual(items.from_server(*stack)));
             }
         }
     }
 }
 
+/// a simple box for entity types without their own visuals yet.
+fn spawn_entity_placeholder(ec: &mut EntityCommands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>) {
+    ec.insert((
+        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
+        MeshMaterial3d(materials.add(Color::srgb(0.6, 0.6, 0.7))),
+        Transform::default(),
+        DespawnOnWorldUnload,
+    ));
+}
+
 pub fn spawn_player(
     ec: &mut EntityCommands,
     is_theplayer: bool,
@@ -1,17 +1,23 @@
-use std::time::Duration;
 
 use bevy::{prelude::*, platform::collections::HashSet};
 use bevy_renet::{
-    renet::{ConnectionConfig, DefaultChannel, RenetServer, ServerEvent},
-    netcode::{NetcodeServerTransport, NetcodeServerPlugin},
+    renet::{RenetServer, ServerEvent},
+    netcode::{NetcodeServerTransport, NetcodeServerPlugin, ServerAuthentication, NETCODE_KEY_BYTES},
     RenetServerPlugin,
 };
 
 use crate::{
-    net::{packet::CellData, CPacket, EntityId, RenetServerHelper, SPacket, PROTOCOL_ID},
-    server::prelude::*,
+    net::{
+        auth::{self, AuthMode},
+        codec,
+        packet::{seq_newer, CellData},
+        replication::{EntityType, Replicated},
+        CPacket, ConnectionState, EntityId, NetChannel, RenetServerHelper, SPacket, PROTOCOL_VERSION,
+    },
+    item::{recipe::CRAFTING_GRID, Inventory, ItemStack, Items, Recipes},
+    server::{crafting, health, inventory, mining::MiningEvent, movement, prelude::*},
     util::{current_timestamp_millis, AsMutRef},
-    voxel::{ChunkSystem, ServerChunkSystem},
+    voxel::{self, ChunkSystem, ServerChunkSystem, VoxelBreakEvent, VoxelPlaceEvent},
 };
 
 pub struct ServerNetworkPlugin;
@@ -21,21 +27,53 @@ impl Plugin for ServerNetworkPlugin {
         app.add_plugins(RenetServerPlugin);
         app.add_plugins(NetcodeServerPlugin);
 
-        app.insert_resource(RenetServer::new(ConnectionConfig {
-            server_channels_config: super::net_channel_config(20 * 1024 * 1024),
-            ..default()
-        }));
+        app.insert_resource(RenetServer::new(super::connection_config()));
 
         app.add_systems(Startup, bind_server_endpoint);
-        app.add_systems(Update, server_sys);
+        app.add_systems(Update, (server_sys, super::query::query_sys));
+        app.add_systems(
+            FixedUpdate,
+            (super::replication::sync_player_transforms, super::replication::replicate_entities)
+                .chain()
+                .in_set(ServerTickSet),
+        );
 
         // app.add_systems(Update, ui_server_net);
     }
 }
 
 fn bind_server_endpoint(mut cmds: Commands, cfg: Res<ServerSettings>) {
-    cmds.insert_resource(super::new_netcode_server_transport(cfg.port, 64));
-    info!("Server bind endpoint at port {}", cfg.port);
+    // a few extra netcode slots beyond num_player_limit, so that rejected logins still can receive the Disconnect reason.
+    let max_clients = (cfg.num_player_limit as usize + 4).min(1024); // 1024: netcode max clients
+
+    let authentication = match cfg.auth_mode {
+        AuthMode::Secure => {
+            let Some(private_key) = auth::parse_private_key(&cfg.auth_private_key) else {
+                panic!("Invalid auth_private_key in server settings, expected {} hex chars", NETCODE_KEY_BYTES * 2);
+            };
+            ServerAuthentication::Secure { private_key }
+        }
+        AuthMode::Unsecure => {
+            warn!("Server is running in Unsecure auth mode. any client can login as anyone, only use it for LAN play.");
+            ServerAuthentication::Unsecure
+        }
+    };
+
+    cmds.insert_resource(super::new_netcode_server_transport(
+        std::net::SocketAddr::new(cfg.bind_addr, cfg.port),
+        max_clients,
+        authentication,
+        cfg.connect_addresses(),
+    ));
+    info!("Server bind endpoint at {}:{} ({:?})", cfg.bind_addr, cfg.port, cfg.auth_mode);
+
+    match super::query::bind_query_socket(cfg.bind_addr, cfg.port) {
+        Ok(socket) => {
+            info!("Server status query at udp port {}", super::query::query_port(cfg.port));
+            cmds.insert_resource(socket);
+        }
+        Err(err) => warn!("Failed to bind status query socket: {}", err),
+    }
 }
 
 pub fn server_sys(
@@ -44,190 +82,341 @@ pub fn server_sys(
     transport: Res<NetcodeServerTransport>,
 
     mut serverinfo: ResMut<ServerInfo>,
+    cfg: Res<ServerSettings>,
+    lists: Res<AccessLists>,
+    mut cmd_events: EventWriter<ServerCommand>,
+    server_tick: Res<ServerTick>,
+    level: Res<LevelData>,
+    (mut voxel_break_events, mut voxel_place_events): (EventWriter<VoxelBreakEvent>, EventWriter<VoxelPlaceEvent>),
+    mut mining_events: EventWriter<MiningEvent>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
     // mut worldinfo: ResMut<WorldInfo>,
-    // chunk_sys: ResMut<ServerChunkSystem>,
+    chunk_sys: Res<ServerChunkSystem>,
     mut cmds: Commands,
 ) {
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                let result_string: String = transport
-                    .user_data(*client_id)
-                    .unwrap_or([0; bevy_renet::netcode::NETCODE_USER_DATA_BYTES])
-                    .iter()
-                    .map(|&byte| byte as char)
-                    .collect();
-
-                info!("Cli Connected {} {}", client_id, result_string);
+                let username = transport.user_data(*client_id).map(|data| auth::decode_user_data(&data)).unwrap_or_default();
+
+                info!("Cli Connected {} {}", client_id, username);
+                serverinfo.connections.insert(*client_id, ClientConnection::default());
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 info!("Cli Disconnected {} {}", client_id, reason);
+                serverinfo.connections.remove(client_id);
+
+                if let Some(mut player) = serverinfo.online_players.remove(client_id) {
+                    server.broadcast_packet_chat(format!(
+                        "Player {} left. ({}/{})",
+                        player.username,
+                        serverinfo.online_players.len(),
+                        cfg.num_player_limit
+                    ));
 
-                if let Some(player) = serverinfo.online_players.remove(client_id) {
-                    server.broadcast_packet_chat(format!("Player {} left. ({}/N)", player.username, serverinfo.online_players.len()));
+                    // EntityDel is sent by the replication.
+                    cmds.entity(player.entity_id.server_entity()).despawn();
 
-                    server.broadcast_packet(&SPacket::EntityDel { entity_id: player.entity_id });
+                    crafting::return_held_items(&mut player, &items, &mut cmds);
+                    PlayerData::from_player(&player, &items).save(&cfg.world_dir, player.user_id);
                 }
             }
         }
     }
 
+    // Force disconnect kicked clients, after the grace time for the Disconnect packet.
+    let now = current_timestamp_millis();
+    for (client_id, conn) in serverinfo.connections.iter() {
+        if conn.disconnect_at.is_some_and(|t| now >= t) && server.is_connected(*client_id) {
+            server.disconnect(*client_id);
+        }
+    }
+
     // Receive message from all clients
     for client_id in server.clients_id() {
-        while let Some(bytes) = server.receive_message(client_id, DefaultChannel::ReliableOrdered) {
-            // info!("Server Received: {}", String::from_utf8_lossy(&bytes));
-            let packet: CPacket = bincode::deserialize(&bytes[..]).unwrap();
-            match packet {
-                CPacket::Handshake { protocol_version } => {
-                    if protocol_version < PROTOCOL_ID {
-                        server.send_packet_disconnect(client_id, "Client outdated.".into());
-                    } else if protocol_version > PROTOCOL_ID {
-                        server.send_packet_disconnect(client_id, "Server outdated.".into());
-                    }
+        for channel in super::RECV_CHANNELS {
+            while let Some(bytes) = server.receive_message(client_id, channel) {
+                // info!("Server Received: {}", String::from_utf8_lossy(&bytes));
+                let conn = serverinfo.connections.entry(client_id).or_default();
+                if conn.disconnect_at.is_some() {
+                    continue; // being kicked, drop remaining packets.
                 }
-                // CPacket::ServerQuery {} => {
-                //     server.send_packet(
-                //         client_id,
-                //         &SPacket::ServerInfo {
-                //             motd: "Motd".into(),
-                //             num_players_limit: 64,
-                //             num_players_online: 0,
-                //             protocol_version: PROTOCOL_ID,
-                //             favicon: "None".into(),
-                //         },
-                //     );
-                // }
-                CPacket::Login {
-                    uuid,
-                    access_token,
-                    username,
-                } => {
-                    info!("Login Requested: {} {} {}", uuid, access_token, username);
-
-                    if serverinfo.online_players.values().any(|v| &v.username == &username) {
-                        server.send_packet_disconnect(client_id, format!("Player {} already logged in", &username));
+                let packet: CPacket = match codec::decode(&bytes) {
+                    Ok(packet) => packet,
+                    Err(err) => {
+                        conn.num_bad_packets += 1;
+                        warn!("Bad packet from client {} ({}/{}): {}", client_id, conn.num_bad_packets, codec::MAX_BAD_PACKETS, err);
+
+                        if conn.num_bad_packets >= codec::MAX_BAD_PACKETS {
+                            serverinfo.kick(&mut server, client_id, format!("Bad packet: {}", err));
+                        }
                         continue;
                     }
-                    // 模拟登录验证
-                    std::thread::sleep(Duration::from_millis(800));
-
-                    let entity_id = EntityId::from_server(cmds.spawn(Transform::default()).id());
+                };
 
-                    // Login Success
-                    server.send_packet(client_id, &SPacket::LoginSuccess { player_entity: entity_id });
-
-                    server.broadcast_packet_chat(format!("Player {} joined. ({}/N)", &username, serverinfo.online_players.len() + 1));
+                let state = conn.state;
+                if !packet.is_valid_in(state) {
+                    if channel == NetChannel::Unreliable {
+                        continue; // may be late arrivals across the state change.
+                    }
+                    warn!("Illegal packet in {:?} state from client {}", state, client_id);
+                    serverinfo.kick(&mut server, client_id, format!("Illegal packet in {:?} state", state));
+                    continue;
+                }
 
-                    server.broadcast_packet_except(
-                        client_id,
-                        &SPacket::EntityNew {
-                            entity_id,
-                            name: username.clone(),
-                        },
-                    );
+                match packet {
+                    CPacket::Handshake { protocol_version, next_state } => {
+                        if protocol_version != PROTOCOL_VERSION {
+                            let outdated = if protocol_version < PROTOCOL_VERSION { "client" } else { "server" };
+                            serverinfo.kick(
+                                &mut server,
+                                client_id,
+                                format!(
+                                    "Outdated {}! Server protocol version {} ({}), client {}",
+                                    outdated,
+                                    PROTOCOL_VERSION,
+                                    crate::VERSION_NAME,
+                                    protocol_version
+                                ),
+                            );
+                            continue;
+                        }
+                        if !matches!(next_state, ConnectionState::Status | ConnectionState::Login) {
+                            serverinfo.kick(&mut server, client_id, format!("Illegal handshake next state {:?}", next_state));
+                            continue;
+                        }
+                        serverinfo.connections.entry(client_id).or_default().state = next_state;
+                    }
+                    CPacket::ServerQuery {} => {
+                        server.send_packet(client_id, &SPacket::ServerInfo(serverinfo.status(&cfg)));
+                    }
+                    // Status & Play
+                    CPacket::Ping { client_time, last_rtt } => {
+                        if let Some(player) = serverinfo.online_players.get_mut(&client_id) {
+                            player.ping_rtt = last_rtt;
+                        }
 
-                    // Send Server Players to the client. Note: Before insert of online_players
-                    for player in serverinfo.online_players.values() {
-                        server.send_packet(
-                            client_id,
-                            &SPacket::EntityNew {
-                                entity_id: player.entity_id,
-                                name: player.username.clone(),
-                            },
-                        );
                         server.send_packet(
                             client_id,
-                            &SPacket::EntityPos {
-                                entity_id: player.entity_id,
-                                position: player.position,
+                            &SPacket::Pong {
+                                client_time,
+                                server_time: current_timestamp_millis(),
                             },
                         );
                     }
+                    CPacket::Login {
+                        uuid,
+                        access_token,
+                        username,
+                    } => {
+                        info!("Login Requested: {} {} {}", uuid, access_token, username);
 
-                    serverinfo.online_players.insert(
-                        client_id,
-                        PlayerInfo {
-                            username,
-                            user_id: uuid,
-                            client_id,
-                            entity_id,
-                            position: Vec3::ZERO,
-                            chunks_loaded: HashSet::default(),
-                            chunks_load_distance: IVec2::new(-1, -1), // 4 2
-                            ping_rtt: 0,
-                        },
-                    );
-                }
-                // Play Stage:
-                _ => {
-                    // Requires Logged in.
-                    // 这几行应该有语法糖简化..
-                    let player = serverinfo.online_players.get_mut(&client_id);
-                    if player.is_none() {
-                        server.send_packet_disconnect(client_id, "illegal play-stage packet. you have not login yet".into());
-                        continue;
-                    }
-                    let player = player.unwrap();
-
-                    match packet {
-                        CPacket::ChatMessage { message } => {
-                            if message.starts_with('/') {
-                                let args = shlex::split(&message[1..]).unwrap();
-
-                                if args[0] == "time" {
-                                    if args[1] == "set" {
-                                        let daytime = args[2].parse::<f32>().unwrap();
-                                        server.broadcast_packet(&SPacket::WorldTime { daytime });
-                                    } else {
-                                        server.send_packet_chat(client_id, "Current time is ".into());
-                                    }
-                                }
-                                info!("[CMD]: {:?}", args);
-                            } else {
-                                server.broadcast_packet_chat(format!("<{}>: {}", player.username, message.clone()));
+                        // the identity is bound to the ConnectToken in secure mode. client_id is the user id, and user_data carries the username.
+                        if cfg.auth_mode == AuthMode::Secure {
+                            let token_username = transport.user_data(client_id).map(|data| auth::decode_user_data(&data));
+                            if uuid != client_id || token_username.as_ref() != Some(&username) {
+                                warn!("Login Rejected: {} session mismatch. token user {:?}", username, token_username);
+                                serverinfo.kick(&mut server, client_id, "Invalid session, please re-login.".into());
+                                continue;
                             }
                         }
-                        CPacket::LoadDistance { load_distance } => {
-                            player.chunks_load_distance = load_distance;
+                        // Unsecure: the client id is per connection, the player data is keyed by the username.
+                        let user_id = match cfg.auth_mode {
+                            AuthMode::Secure => uuid,
+                            AuthMode::Unsecure => auth::user_id_of(&username),
+                        };
+
+                        if serverinfo.online_players.values().any(|v| &v.username == &username) {
+                            serverinfo.kick(&mut server, client_id, format!("Player {} already logged in", &username));
+                            continue;
+                        }
+                        let client_ip = transport.client_addr(client_id).map(|addr| addr.ip());
+                        if let Err(reason) = lists.check_login(&username, client_ip, cfg.whitelist_enabled) {
+                            info!("Login Rejected: {} {}", username, reason);
+                            serverinfo.kick(&mut server, client_id, reason);
+                            continue;
                         }
-                        CPacket::PlayerPos { position } => {
-                            // todo: check diff, skip the same
+                        if serverinfo.online_players.len() >= cfg.num_player_limit as usize {
+                            serverinfo.kick(&mut server, client_id, format!("The server is full. ({}/{})", serverinfo.online_players.len(), cfg.num_player_limit));
+                            continue;
+                        }
+                        let data = PlayerData::load(&cfg.world_dir, user_id);
+                        let position = data.position.unwrap_or(level.spawn_point);
 
-                            player.position = position;
+                        let entity_id = EntityId::from_server(
+                            cmds.spawn((
+                                Transform::from_translation(position),
+                                Replicated::new(EntityType::Player),
+                                Name::new(username.clone()),
+                            ))
+                            .id(),
+                        );
 
-                            server.broadcast_packet_except(
-                                client_id,
-                                &SPacket::EntityPos {
-                                    entity_id: player.entity_id,
-                                    position,
-                                },
-                            );
-                        }
-                        CPacket::Ping { client_time, last_rtt } => {
-                            player.ping_rtt = last_rtt;
+                        // Login Success
+                        server.send_packet(
+                            client_id,
+                            &SPacket::LoginSuccess {
+                                player_entity: entity_id,
+                                tick_rate: server_tick.tick_rate(),
+                                position,
+                                seed: level.seed,
+                            },
+                        );
+
+                        server.broadcast_packet_chat(format!(
+                            "Player {} joined. ({}/{})",
+                            &username,
+                            serverinfo.online_players.len() + 1,
+                            cfg.num_player_limit
+                        ));
+
+                        // other entities are replicated by interest, see replication::replicate_entities
 
-                            server.send_packet(
+                        server.send_packet(client_id, &level.world_time_packet());
+
+                        // the item num ids of the server. clients map them to their own.
+                        server.send_packet(client_id, &SPacket::ItemRegistry { items: items.reg.vec.clone() });
+
+                        serverinfo.connections.entry(client_id).or_default().state = ConnectionState::Play;
+                        serverinfo.online_players.insert(
+                            client_id,
+                            PlayerInfo {
+                                username,
+                                user_id,
                                 client_id,
-                                &SPacket::Pong {
-                                    client_time,
-                                    server_time: current_timestamp_millis(),
-                                },
-                            );
+                                entity_id,
+                                position,
+                                yaw: 0.,
+                                last_move_time: now,
+                                move_budget: movement::MoveBudget::default(),
+                                // until the client is moved to the position of LoginSuccess.
+                                correction: Some(movement::PendingCorrection { position, sent_at: now }),
+                                move_violations: 0,
+                                chunks_loaded: HashSet::default(),
+                                chunks_load_distance: IVec2::NEG_ONE, // no chunks until the client sends its LoadDistance
+                                ping_rtt: 0,
+                                last_pos_seq: 0,
+                                entities_visible: HashSet::default(),
+                                inventory: data.inventory(&items),
+                                cursor: ItemStack::default(),
+                                hotbar_index: data.hotbar_index,
+                                crafting_grid: Inventory::new(CRAFTING_GRID * CRAFTING_GRID),
+                                mining: None,
+                                game_mode: data.game_mode.unwrap_or(level.game_mode),
+                                health: data.health.unwrap_or(health::MAX_HEALTH).min(health::MAX_HEALTH),
+                                air: health::MAX_AIR,
+                            },
+                        );
+                        let player = &serverinfo.online_players[&client_id];
+                        server.send_packet(client_id, &inventory::content_packet(player));
+                        server.send_packet(client_id, &SPacket::PlayerGameMode { game_mode: player.game_mode });
+                        server.send_packet(client_id, &health::health_packet(player));
+                    }
+                    // Play Stage:
+                    _ => {
+                        // Requires Logged in.
+                        // 这几行应该有语法糖简化..
+                        let player = serverinfo.online_players.get_mut(&client_id);
+                        if player.is_none() {
+                            serverinfo.kick(&mut server, client_id, "illegal play-stage packet. you have not login yet".into());
+                            continue;
                         }
-                        CPacket::PlayerList => {
-                            let playerlist = serverinfo.online_players.iter().map(|e| (e.1.username.clone(), e.1.ping_rtt)).collect();
-                            server.send_packet(client_id, &SPacket::PlayerList { playerlist });
+                        let player = player.unwrap();
+
+                        // the dead can only respawn. their moves are dropped, the inventory actions resynced.
+                        if !player.is_alive() {
+                            match packet {
+                                CPacket::PlayerPos { .. } | CPacket::PlayerLand { .. } => continue,
+                                CPacket::InventoryClick { .. }
+                                | CPacket::InventoryTransfer { .. }
+                                | CPacket::CraftingClick { .. }
+                                | CPacket::CraftingTakeResult { .. }
+                                | CPacket::CraftRecipe { .. }
+                                | CPacket::HotbarSelect { .. } => {
+                                    server.send_packet(client_id, &inventory::content_packet(player));
+                                    continue;
+                                }
+                                _ => {}
+                            }
                         }
-                        // CPacket::ChunkModify { chunkpos, voxel } => {
-                        // todo: NonLock
-                        // let chunk = chunk_sys.get_chunk(chunkpos).unwrap();
 
-                        // CellData::to_chunk(&voxel, chunk.as_ref_mut());
+                        match packet {
+                            CPacket::ChatMessage { message } => {
+                                if let Some(cmdline) = message.strip_prefix('/') {
+                                    match shlex::split(cmdline) {
+                                        Some(args) if !args.is_empty() => {
+                                            info!("[CMD] {}: {:?}", player.username, args);
+                                            cmd_events.write(ServerCommand { client_id, args });
+                                        }
+                                        _ => {
+                                            server.send_packet_chat(client_id, "Invalid command syntax.".into());
+                                        }
+                                    }
+                                } else {
+                                    server.broadcast_packet_chat(format!("<{}>: {}", player.username, message.clone()));
+                                }
+                            }
+                            CPacket::LoadDistance { load_distance } => {
+                                // -1: no chunks
+                                let load_distance = load_distance.clamp(IVec2::NEG_ONE, cfg.max_chunks_load_distance);
+                                voxel::set_load_distance(&mut server, player, load_distance);
+                            }
+                            CPacket::PlayerPos { position, yaw, seq } => {
+                                if !seq_newer(seq, player.last_pos_seq) {
+                                    continue; // outdated, reordered by the unreliable channel.
+                                }
+                                player.last_pos_seq = seq;
+                                movement::on_player_pos(&mut server, player, position, yaw, &chunk_sys);
+                            }
+                            CPacket::BreakVoxels { positions } => {
+                                voxel_break_events.write(VoxelBreakEvent { client_id, positions });
+                            }
+                            CPacket::PlaceVoxel { pos } => {
+                                voxel_place_events.write(VoxelPlaceEvent { client_id, pos });
+                            }
+                            CPacket::Mining(action) => {
+                                mining_events.write(MiningEvent { client_id, action });
+                            }
+                            CPacket::InventoryClick { slot, secondary } => {
+                                inventory::on_click(&mut server, player, slot, secondary, &items);
+                            }
+                            CPacket::InventoryTransfer { slot } => {
+                                inventory::on_transfer(&mut server, player, slot, &items);
+                            }
+                            CPacket::CraftingClick { slot, secondary } => {
+                                crafting::on_grid_click(&mut server, player, slot, secondary, &items, &recipes);
+                            }
+                            CPacket::CraftingTakeResult { all } => {
+                                crafting::on_take_result(&mut server, player, all, &items, &recipes);
+                            }
+                            CPacket::CraftRecipe { recipe } => {
+                                crafting::on_craft_recipe(&mut server, player, &recipe, &items, &recipes);
+                            }
+                            CPacket::HotbarSelect { index } => {
+                                inventory::on_hotbar_select(&mut server, player, index);
+                            }
+                            CPacket::PlayerLand { fall_velocity } => {
+                                health::on_land(&mut server, player, fall_velocity, &chunk_sys, &mut cmds);
+                            }
+                            CPacket::Respawn => {
+                                health::on_respawn(&mut server, player, &level);
+                            }
+                            CPacket::PlayerList => {
+                                let playerlist = serverinfo.online_players.iter().map(|e| (e.1.username.clone(), e.1.ping_rtt)).collect();
+                                server.send_packet(client_id, &SPacket::PlayerList { playerlist });
+                            }
+                            // CPacket::ChunkModify { chunkpos, voxel } => {
+                            // todo: NonLock
+                            // let chunk = chunk_sys.get_chunk(chunkpos).unwrap();
 
-                        // server.broadcast_packet(&SPacket::ChunkModify { chunkpos, voxel });
-                        // }
-                        _ => {
-                            warn!("Unknown Packet {:?}", packet);
+                            // CellData::to_chunk(&voxel, chunk.as_ref_mut());
+
+                            // server.broadcast_packet(&SPacket::ChunkModify { chunkpos, voxel });
+                            // }
+                            _ => {
+                                warn!("Unknown Packet {:?}", packet);
+                            }
                         }
                     }
                 }
@@ -1,9 +1,16 @@
 use bevy::math::{IVec2, IVec3, Vec3};
 use serde::{Deserialize, Serialize};
 
-use crate::voxel::{Chunk, Vox, VoxShape};
+use crate::{
+    item::ItemStack,
+    server::game_mode::GameMode,
+    voxel::{Chunk, Vox, VoxShape},
+};
 
-use super::EntityId;
+use super::{
+    replication::{ComponentData, EntityType},
+    EntityId,
+};
 
 // Compressed Cell data.
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
@@ -45,48 +52,176 @@ impl CellData {
     }
 }
 
+/// Connection State Machine. Handshake → (Status | Login) → Play
+/// each state accepts its own packet set, see `CPacket::is_valid_in` and `SPacket::is_valid_in`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    #[default]
+    Handshake,
+    Status,
+    Login,
+    Play,
+}
+
+/// Server Status. response of the ServerQuery, via netcode or the UDP query socket.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub motd: String,
+    pub num_players_limit: u32,
+    pub num_players_online: u32,
+    // online_players: Vec<(u64 uuid, String name)>
+    pub protocol_version: u64,
+    pub version_name: String,
+    /// PNG image bytes. empty if the server has no icon.
+    pub favicon: Vec<u8>,
+}
+
+/// hold-to-mine a voxel: Start when targeted, Abort when released or the target changed,
+/// Finish when the client-side progress completes. the server validates the time elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MiningAction {
+    Start { pos: IVec3 },
+    Abort,
+    Finish { pos: IVec3 },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CPacket {
-    // Handshake & Server Query & Login
-    Handshake { protocol_version: u64 },
+    // Handshake
+    Handshake { protocol_version: u64, next_state: ConnectionState },
+
+    // Status
     ServerQuery {},
     Ping { client_time: u64, last_rtt: u32 }, // last_rtt is a temporary solution to let server know the client's ping
 
+    // Login
     Login { uuid: u64, access_token: u64, username: String },
 
     // Play
     ChatMessage { message: String },
 
-    PlayerPos { position: Vec3 },
+    // Unreliable. seq: increasing sequence number, the server drops outdated (reordered) ones.
+    PlayerPos { position: Vec3, yaw: f32, seq: u32 },
 
     PlayerList, // RequestPlayerList
 
     ChunkModify { chunkpos: IVec3, voxel: Vec<CellData> },
 
     LoadDistance { load_distance: IVec2 },
+
+    // voxels broken instantly by the player (creative). the server drops their items.
+    BreakVoxels { positions: Vec<IVec3> },
+    // hold-to-mine. see server::mining
+    Mining(MiningAction),
+    // place the block of the held item at the voxel. the server takes one from the stack.
+    PlaceVoxel { pos: IVec3 },
+
+    // Inventory. the server applies and answers with InventorySlots.
+    // click a slot with the cursor stack. see `Inventory::click`
+    InventoryClick { slot: u16, secondary: bool },
+    // quick move the slot between the hotbar and the rest. see `Inventory::transfer`
+    InventoryTransfer { slot: u16 },
+    HotbarSelect { index: u8 },
+
+    // Crafting. see server::crafting
+    // click a slot of the crafting grid with the cursor stack.
+    CraftingClick { slot: u16, secondary: bool },
+    // take the crafting result into the cursor. all: craft as many as possible into the inventory.
+    CraftingTakeResult { all: bool },
+    // recipe book. craft directly from the inventory.
+    CraftRecipe { recipe: String },
+
+    // Health. see server::health
+    // landed on the ground, with the vertical speed (m/s, positive downwards). for fall damage.
+    PlayerLand { fall_velocity: f32 },
+    // respawn after death.
+    Respawn,
+}
+
+/// The channels of packets. the ids of `net_channel_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetChannel {
+    /// high frequency states, e.g. movement. with sequence numbers.
+    Unreliable = 0,
+    /// chunk data: ChunkNew, ChunkModify and ChunkDel, ordered among themselves.
+    /// a channel of its own, the bulk data won't block the others.
+    Chunk = 1,
+    /// the others, e.g. login, chat, events.
+    ReliableOrdered = 2,
+}
+
+impl From<NetChannel> for u8 {
+    fn from(channel: NetChannel) -> u8 {
+        channel as u8
+    }
+}
+
+/// A packet, routed to a channel by its type. see `NetChannel`
+pub trait Packet: Serialize {
+    fn channel(&self) -> NetChannel;
+}
+
+impl Packet for CPacket {
+    fn channel(&self) -> NetChannel {
+        match self {
+            CPacket::PlayerPos { .. } => NetChannel::Unreliable,
+            _ => NetChannel::ReliableOrdered,
+        }
+    }
+}
+
+impl Packet for SPacket {
+    fn channel(&self) -> NetChannel {
+        match self {
+            SPacket::EntityPos { .. } => NetChannel::Unreliable,
+            // a ChunkModify/ChunkDel must not overtake the ChunkNew before it.
+            SPacket::ChunkNew { .. } | SPacket::ChunkModify { .. } | SPacket::ChunkDel { .. } => NetChannel::Chunk,
+            _ => NetChannel::ReliableOrdered,
+        }
+    }
+}
+
+/// is sequence number `a` newer than `b`. wrapping.
+pub fn seq_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+impl CPacket {
+    pub fn is_valid_in(&self, state: ConnectionState) -> bool {
+        use ConnectionState::*;
+        match self {
+            CPacket::Handshake { .. } => state == Handshake,
+            CPacket::ServerQuery {} => state == Status,
+            CPacket::Ping { .. } => state == Status || state == Play,
+            CPacket::Login { .. } => state == Login,
+            _ => state == Play,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SPacket {
-    // Handshake & Server Query & Login
+    // Any State
     Disconnect {
         reason: String,
     },
-    // ServerInfo {
-    //     motd: String,
-    //     num_players_limit: u32,
-    //     num_players_online: u32,
-    //     // online_players: Vec<(u64 uuid, String name)>
-    //     protocol_version: u64,
-    //     favicon: String,
-    // },
+
+    // Status
+    ServerInfo(ServerStatus),
     Pong {
         client_time: u64,
         server_time: u64,
     },
+
+    // Login
     LoginSuccess {
         // uuid, username
         player_entity: EntityId,
+        tick_rate: u32, // server ticks per second. for the EntityPos tick clock
+        // the saved position of the player, or the world spawn point
+        position: Vec3,
+        // the world seed, for the client-side terrain generation
+        seed: u64,
     },
 
     // Play
@@ -96,15 +231,26 @@ pub enum SPacket {
 
     EntityNew {
         entity_id: EntityId,
-        name: String, // temporary way.
-                      // type: {Player}
+        entity_type: EntityType,
+        position: Vec3,
+        yaw: f32,
+        components: Vec<ComponentData>, // replicated components of the entity type
+    },
+    EntityUpdate {
+        entity_id: EntityId,
+        components: Vec<ComponentData>,
     },
     EntityDel {
         entity_id: EntityId,
     },
+    // Unreliable. seq: increasing sequence number, the client drops outdated (reordered) ones.
+    // tick: the server tick of this state, for the client snapshot interpolation.
     EntityPos {
         entity_id: EntityId,
         position: Vec3,
+        yaw: f32,
+        seq: u32,
+        tick: u64,
     },
 
     PlayerList {
@@ -124,7 +270,75 @@ pub enum SPacket {
         voxel: Vec<CellData>,
     },
 
+    // crack stage [0, BREAK_STAGES) of the voxel the entity (player) is mining. -1: stopped.
+    BreakProgress {
+        entity_id: EntityId,
+        pos: IVec3,
+        stage: i8,
+    },
+
+    // picked up an item drop into the inventory. the inventory itself is synced by InventorySlots.
+    PickupItem {
+        entity_id: EntityId,
+        stack: ItemStack,
+    },
+
+    // str ids of the server items, in the num id order. sent before any ItemStack, clients map the ids to their own.
+    ItemRegistry {
+        items: Vec<String>,
+    },
+
+    // the whole player inventory. on login, or resync after an invalid action.
+    InventoryContent {
+        items: Vec<ItemStack>,
+        cursor: ItemStack,
+        hotbar_index: u8,
+    },
+    // changed slots (slot, stack), and the cursor stack.
+    InventorySlots {
+        slots: Vec<(u16, ItemStack)>,
+        cursor: ItemStack,
+    },
+    // the crafting grid, and the result of the matching recipe.
+    CraftingContent {
+        grid: Vec<ItemStack>,
+        result: ItemStack,
+    },
+
     WorldTime {
         daytime: f32,
+        daytime_length: f32, // 0: the daytime doesn't advance
+    },
+
+    // the game mode of the player. on login and on change.
+    PlayerGameMode {
+        game_mode: GameMode,
+    },
+
+    // the health of the player. on login and on change. 0: dead, the client shows the respawn screen.
+    PlayerHealth {
+        health: u32,
+        health_max: u32,
     },
+    // respawned at the position.
+    PlayerRespawn {
+        position: Vec3,
+    },
+    // the move was rejected by the server, teleport back to the position. see server::movement
+    PlayerCorrection {
+        position: Vec3,
+    },
+}
+
+impl SPacket {
+    pub fn is_valid_in(&self, state: ConnectionState) -> bool {
+        use ConnectionState::*;
+        match self {
+            SPacket::Disconnect { .. } => true,
+            SPacket::ServerInfo(_) => state == Status,
+            SPacket::Pong { .. } => state == Status || state == Play,
+            SPacket::LoginSuccess { .. } => state == Login,
+            _ => state == Play,
+        }
+    }
 }
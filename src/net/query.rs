@@ -0,0 +1,156 @@
+//! UDP Server Status Query.
+//!
+//! Query the server status (motd, player counts, protocol version, favicon) without a netcode connection,
+//! for refreshing the server list. The query socket binds on the game port + QUERY_PORT_OFFSET.
+//! Request: QUERY_MAGIC + cookie u64 + bincode(CPacket::ServerQuery).
+//! Response: QUERY_MAGIC + RESPONSE_STATUS + bincode(SPacket::ServerInfo) if the cookie is valid for the sender address,
+//! otherwise QUERY_MAGIC + RESPONSE_CHALLENGE + cookie, and the client queries again with it.
+//! The challenge round trip proves the sender address isn't spoofed before the large status is sent,
+//! and the challenge is smaller than the request, so the server can't be used for UDP reflection/amplification.
+
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use super::{codec, CPacket, SPacket, ServerStatus};
+use crate::{
+    server::prelude::{ServerInfo, ServerSettings},
+    util::current_timestamp,
+};
+
+pub const QUERY_MAGIC: &[u8; 4] = b"ETQY";
+pub const QUERY_PORT_OFFSET: u16 = 1;
+
+/// UDP payload limit. the favicon is dropped from the response if it doesn't fit.
+const MAX_DATAGRAM: usize = 60 * 1024;
+
+const RESPONSE_CHALLENGE: u8 = 0;
+const RESPONSE_STATUS: u8 = 1;
+
+/// a cookie is valid in its time window and the next one.
+const COOKIE_WINDOW_SECS: u64 = 30;
+
+pub fn query_port(game_port: u16) -> u16 {
+    game_port.wrapping_add(QUERY_PORT_OFFSET)
+}
+
+#[derive(Resource)]
+pub struct QuerySocket {
+    socket: UdpSocket,
+    // random key of the cookies (SipHash), per server start.
+    cookie_key: RandomState,
+}
+
+impl QuerySocket {
+    fn cookie(&self, addr: SocketAddr, window: u64) -> u64 {
+        self.cookie_key.hash_one((addr, window))
+    }
+
+    fn is_valid_cookie(&self, addr: SocketAddr, cookie: u64) -> bool {
+        let window = current_timestamp().as_secs() / COOKIE_WINDOW_SECS;
+        cookie == self.cookie(addr, window) || cookie == self.cookie(addr, window.saturating_sub(1))
+    }
+}
+
+pub fn bind_query_socket(bind_addr: IpAddr, game_port: u16) -> std::io::Result<QuerySocket> {
+    let socket = UdpSocket::bind(SocketAddr::new(bind_addr, query_port(game_port)))?;
+    socket.set_nonblocking(true)?;
+    Ok(QuerySocket {
+        socket,
+        cookie_key: RandomState::new(),
+    })
+}
+
+/// Server: respond status queries.
+pub fn query_sys(socket: Option<Res<QuerySocket>>, serverinfo: Res<ServerInfo>, cfg: Res<ServerSettings>) {
+    let Some(socket) = socket else {
+        return;
+    };
+    let mut buf = [0u8; 512];
+    while let Ok((len, addr)) = socket.socket.recv_from(&mut buf) {
+        let Some((cookie, payload)) = buf[..len].strip_prefix(QUERY_MAGIC).and_then(|p| p.split_first_chunk::<8>()) else {
+            continue;
+        };
+        if !matches!(codec::decode(payload), Ok(CPacket::ServerQuery {})) {
+            continue;
+        }
+
+        let data = if socket.is_valid_cookie(addr, u64::from_le_bytes(*cookie)) {
+            let mut status = serverinfo.status(&cfg);
+            let mut data = encode_response(&status);
+            if data.len() > MAX_DATAGRAM {
+                status.favicon.clear();
+                data = encode_response(&status);
+            }
+            data
+        } else {
+            let window = current_timestamp().as_secs() / COOKIE_WINDOW_SECS;
+            let mut data = QUERY_MAGIC.to_vec();
+            data.push(RESPONSE_CHALLENGE);
+            data.extend(socket.cookie(addr, window).to_le_bytes());
+            data
+        };
+        if let Err(err) = socket.socket.send_to(&data, addr) {
+            warn!("Failed to respond status query to {}: {}", addr, err);
+        }
+    }
+}
+
+fn encode_response(status: &ServerStatus) -> Vec<u8> {
+    let mut data = QUERY_MAGIC.to_vec();
+    data.push(RESPONSE_STATUS);
+    data.extend(codec::encode(&SPacket::ServerInfo(status.clone())));
+    data
+}
+
+fn encode_request(cookie: u64) -> Vec<u8> {
+    let mut req = QUERY_MAGIC.to_vec();
+    req.extend(cookie.to_le_bytes());
+    req.extend(codec::encode(&CPacket::ServerQuery {}));
+    req
+}
+
+/// Client: query the server status of the game address. blocking, returns the status and the round trip time.
+pub fn query_server(game_addr: SocketAddr, timeout: Duration) -> anyhow::Result<(ServerStatus, Duration)> {
+    let query_addr = SocketAddr::new(game_addr.ip(), query_port(game_addr.port()));
+
+    let socket = UdpSocket::bind(if query_addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.set_read_timeout(Some(timeout))?;
+
+    // the first request gets the challenge cookie, the second the status.
+    let mut cookie = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    for _ in 0..2 {
+        let time_sent = Instant::now();
+        socket.send_to(&encode_request(cookie), query_addr)?;
+
+        let len = loop {
+            let (len, from) = socket.recv_from(&mut buf)?;
+            if from == query_addr {
+                break len;
+            }
+        };
+        let (&tag, payload) = buf[..len]
+            .strip_prefix(QUERY_MAGIC)
+            .and_then(|p| p.split_first())
+            .ok_or(anyhow::anyhow!("Invalid query response"))?;
+        match tag {
+            RESPONSE_CHALLENGE => {
+                let challenge = payload.first_chunk::<8>().ok_or(anyhow::anyhow!("Invalid query challenge"))?;
+                cookie = u64::from_le_bytes(*challenge);
+            }
+            RESPONSE_STATUS => {
+                return match codec::decode(payload)? {
+                    SPacket::ServerInfo(status) => Ok((status, time_sent.elapsed())),
+                    _ => Err(anyhow::anyhow!("Invalid query response")),
+                };
+            }
+            _ => return Err(anyhow::anyhow!("Invalid query response")),
+        }
+    }
+    Err(anyhow::anyhow!("Query challenge failed"))
+}
@@ -0,0 +1,200 @@
+//! Entity Replication.
+//!
+//! Server entities with the `Replicated` component are synced to the players who have the entity's chunk loaded (interest management).
+//! Which components sync is described per `EntityType` by `EntityType::replicated_components`.
+//! - EntityNew: the entity enters the player's interest. carries the type, transform and the replicated components.
+//! - EntityUpdate: replicated components changed.
+//! - EntityPos: transform changed. unreliable, interpolated on the client.
+//! - EntityDel: the entity left the player's interest, or despawned.
+//!
+//! Clients map the server EntityIds to their own entities by `EntityMap`.
+
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use super::{EntityId, RenetServerHelper, SPacket};
+use crate::{item::ItemStack, server::{item_drop::DroppedItem, prelude::*}, voxel::Chunk};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityType {
+    Player,
+    ItemDrop,
+    Mob,
+    Projectile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentKind {
+    Transform,
+    Name,
+    Item,
+}
+
+/// Data of a replicated component. except Transform, which is sent by EntityNew/EntityPos.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ComponentData {
+    Name(String),
+    Item(ItemStack),
+}
+
+impl EntityType {
+    /// the replication registry. components of the entity type that sync to clients.
+    pub fn replicated_components(&self) -> &'static [ComponentKind] {
+        use ComponentKind::*;
+        match self {
+            EntityType::Player => &[Transform, Name],
+            EntityType::ItemDrop => &[Transform, Item],
+            EntityType::Mob => &[Transform, Name],
+            EntityType::Projectile => &[Transform],
+        }
+    }
+
+    pub fn replicates(&self, kind: ComponentKind) -> bool {
+        self.replicated_components().contains(&kind)
+    }
+}
+
+/// Server: marks an entity replicated to clients.
+#[derive(Component, Debug)]
+pub struct Replicated {
+    pub entity_type: EntityType,
+
+    // EntityPos sequence number
+    seq: u32,
+}
+
+impl Replicated {
+    pub fn new(entity_type: EntityType) -> Self {
+        Self { entity_type, seq: 0 }
+    }
+}
+
+/// Client: server EntityId -> client Entity.
+#[derive(Resource, Default)]
+pub struct EntityMap {
+    pub s2c: HashMap<EntityId, Entity>,
+}
+
+impl EntityMap {
+    pub fn get(&self, entity_id: EntityId) -> Option<Entity> {
+        self.s2c.get(&entity_id).copied()
+    }
+}
+
+/// Client: the server entity of a client entity.
+#[derive(Component, Debug)]
+pub struct NetEntity {
+    pub entity_id: EntityId,
+    pub entity_type: EntityType,
+}
+
+fn collect_components(entity_type: EntityType, name: Option<&Name>, item: Option<&DroppedItem>) -> Vec<ComponentData> {
+    let mut components = Vec::new();
+    if let Some(name) = name.filter(|_| entity_type.replicates(ComponentKind::Name)) {
+        components.push(ComponentData::Name(name.to_string()));
+    }
+    if let Some(item) = item.filter(|_| entity_type.replicates(ComponentKind::Item)) {
+        components.push(ComponentData::Item(item.0));
+    }
+    components
+}
+
+/// Server: apply the PlayerPos of players to their entities.
+pub(super) fn sync_player_transforms(serverinfo: Res<ServerInfo>, mut query: Query<&mut Transform, With<Replicated>>) {
+    for player in serverinfo.online_players.values() {
+        if let Ok(mut trans) = query.get_mut(player.entity_id.server_entity()) {
+            let rotation = Quat::from_rotation_y(player.yaw);
+            // only set when differs, not to trigger the change detection.
+            if trans.translation != player.position || trans.rotation != rotation {
+                trans.translation = player.position;
+                trans.rotation = rotation;
+            }
+        }
+    }
+}
+
+/// Server: replicate entities to the interested players. once per tick.
+pub(super) fn replicate_entities(
+    mut server: ResMut<RenetServer>,
+    mut serverinfo: ResMut<ServerInfo>,
+    tick: Res<ServerTick>,
+    mut query: Query<(Entity, &mut Replicated, Ref<Transform>, Option<Ref<Name>>, Option<Ref<DroppedItem>>)>,
+) {
+    let mut existing = HashSet::new();
+    for (entity, mut replicated, trans, name, item) in query.iter_mut() {
+        let entity_id = EntityId::from_server(entity);
+        existing.insert(entity_id);
+
+        let chunkpos = Chunk::as_chunkpos(trans.translation.as_ivec3());
+        let yaw = trans.rotation.to_euler(EulerRot::YXZ).0;
+        let moved = trans.is_changed();
+        let components_changed = name.as_ref().is_some_and(|n| n.is_changed()) || item.as_ref().is_some_and(|i| i.is_changed());
+        if moved {
+            replicated.seq = replicated.seq.wrapping_add(1);
+        }
+        let entity_type = replicated.entity_type;
+
+        for player in serverinfo.online_players.values_mut() {
+            if player.entity_id == entity_id {
+                continue;
+            }
+            let interested = player.chunks_loaded.contains(&chunkpos);
+            let visible = player.entities_visible.contains(&entity_id);
+
+            if interested && !visible {
+                player.entities_visible.insert(entity_id);
+                server.send_packet(
+                    player.client_id,
+                    &SPacket::EntityNew {
+                        entity_id,
+                        entity_type,
+                        position: trans.translation,
+                        yaw,
+                        components: collect_components(entity_type, name.as_deref(), item.as_deref()),
+                    },
+                );
+            } else if !interested && visible {
+                player.entities_visible.remove(&entity_id);
+                server.send_packet(player.client_id, &SPacket::EntityDel { entity_id });
+            } else if interested {
+                if components_changed {
+                    server.send_packet(
+                        player.client_id,
+                        &SPacket::EntityUpdate {
+                            entity_id,
+                            components: collect_components(entity_type, name.as_deref(), item.as_deref()),
+                        },
+                    );
+                }
+                if moved {
+                    server.send_packet(
+                        player.client_id,
+                        &SPacket::EntityPos {
+                            entity_id,
+                            position: trans.translation,
+                            yaw,
+                            seq: replicated.seq,
+                            tick: tick.tick,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    // despawned entities
+    for player in serverinfo.online_players.values_mut() {
+        let client_id = player.client_id;
+        player.entities_visible.retain(|entity_id| {
+            let keep = existing.contains(entity_id);
+            if !keep {
+                server.send_packet(client_id, &SPacket::EntityDel { entity_id: *entity_id });
+            }
+            keep
+        });
+    }
+}
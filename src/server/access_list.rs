@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+// Persisted next to server.settings.json on a dedicated server, in the world directory of an integrated server.
+const WHITELIST_FILE: &str = "whitelist.json";
+const BANNED_PLAYERS_FILE: &str = "banned-players.json";
+const BANNED_IPS_FILE: &str = "banned-ips.json";
+const OPS_FILE: &str = "ops.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BanEntry {
+    pub reason: String,
+    // unix timestamp seconds
+    pub created: u64,
+}
+
+/// Whitelist, Banned Usernames, Banned IPs and Operators of the Server.
+/// usernames are stored in lowercase.
+#[derive(Resource, Default)]
+pub struct AccessLists {
+    pub whitelist: BTreeSet<String>,
+    pub banned_players: BTreeMap<String, BanEntry>,
+    pub banned_ips: BTreeMap<IpAddr, BanEntry>,
+
+    /// Players allowed to run admin commands (/ban, /whitelist, ..). nobody if empty.
+    /// seeded by the local RCON endpoint `/op` on a dedicated server, the host is the op of an integrated server.
+    pub ops: BTreeSet<String>,
+
+    // where the lists are saved
+    dir: PathBuf,
+}
+
+impl AccessLists {
+    /// load the lists from the directory, and save them there.
+    pub fn load(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        Self {
+            whitelist: load_json(&dir.join(WHITELIST_FILE)),
+            banned_players: load_json(&dir.join(BANNED_PLAYERS_FILE)),
+            banned_ips: load_json(&dir.join(BANNED_IPS_FILE)),
+            ops: load_json(&dir.join(OPS_FILE)),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn save(&self) {
+        save_json(&self.dir.join(WHITELIST_FILE), &self.whitelist);
+        save_json(&self.dir.join(BANNED_PLAYERS_FILE), &self.banned_players);
+        save_json(&self.dir.join(BANNED_IPS_FILE), &self.banned_ips);
+        save_json(&self.dir.join(OPS_FILE), &self.ops);
+    }
+
+    pub fn is_whitelisted(&self, username: &str) -> bool {
+        self.whitelist.contains(&username.to_lowercase())
+    }
+
+    pub fn is_op(&self, username: &str) -> bool {
+        self.ops.contains(&username.to_lowercase())
+    }
+
+    pub fn get_ban(&self, username: &str) -> Option<&BanEntry> {
+        self.banned_players.get(&username.to_lowercase())
+    }
+
+    pub fn get_ban_ip(&self, ip: &IpAddr) -> Option<&BanEntry> {
+        self.banned_ips.get(ip)
+    }
+
+    /// Check if the Login should be rejected. returns the Disconnect reason.
+    pub fn check_login(&self, username: &str, ip: Option<IpAddr>, whitelist_enabled: bool) -> Result<(), String> {
+        if let Some(ban) = self.get_ban(username) {
+            return Err(format!("You are banned from this server. Reason: {}", ban.reason));
+        }
+        if let Some(ban) = ip.and_then(|ip| self.get_ban_ip(&ip)) {
+            return Err(format!("Your IP address is banned from this server. Reason: {}", ban.reason));
+        }
+        if whitelist_enabled && !self.is_whitelisted(username) {
+            return Err("You are not whitelisted on this server.".into());
+        }
+        Ok(())
+    }
+}
+
+impl BanEntry {
+    pub fn new(reason: String) -> Self {
+        Self {
+            reason,
+            created: crate::util::current_timestamp().as_secs(),
+        }
+    }
+}
+
+fn load_json<T: DeserializeOwned + Default>(file: &Path) -> T {
+    match std::fs::read_to_string(file) {
+        Ok(str) => serde_json::from_str(&str).unwrap_or_else(|err| {
+            error!("Failed to parse {}: {err}", file.display());
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+fn save_json<T: Serialize>(file: &Path, val: &T) {
+    if let Err(err) = std::fs::write(file, serde_json::to_string_pretty(val).unwrap()) {
+        error!("Failed to save {}: {err}", file.display());
+    }
+}
@@ -0,0 +1,276 @@
+//! Chunk Ticking: random ticks and scheduled block updates.
+//!
+//! Every server tick, `GameRules::random_tick_speed` random voxels of each loaded chunk get a random tick.
+//! A voxel can also be updated at a later tick by `ScheduledUpdates::schedule_update`, e.g. when a neighbor changed.
+//! What a voxel does on either is registered per block type (`VoxTex`) in `BlockBehaviors`.
+//! Voxels changed by the behaviors are sent by `SPacket::ChunkModify` at the end of the tick, to the players that have the chunk loaded.
+
+use std::collections::BTreeMap;
+
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use bevy_renet::renet::RenetServer;
+use rand::Rng;
+
+use super::prelude::*;
+use crate::{
+    net::CellData,
+    util::{iter, AsMutRef},
+    voxel::{
+        send_chunk_modify,
+        worldgen::{tree_voxels, TreePart},
+        Chunk, ChunkSystem, ServerChunkSystem, Vox, VoxShape, VoxTex,
+    },
+};
+
+/// scheduled updates processed per tick at most, the rest are delayed to the next tick.
+const MAX_UPDATES_PER_TICK: usize = 4096;
+
+/// leaves without a log in this distance decay.
+const LEAVES_DECAY_DISTANCE: i32 = 6;
+
+/// chance of a sapling to grow into a tree on a random tick.
+const SAPLING_GROW_CHANCE: f64 = 0.15;
+/// the size of the trees grown from saplings, see `worldgen::tree_voxels`.
+const SAPLING_TREE_SIZE: std::ops::Range<f32> = 0.0..0.4;
+
+const NEIGHBORS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+pub type BlockTickFn = fn(&mut BlockTickCtx, IVec3);
+
+#[derive(Default, Clone, Copy)]
+pub struct BlockBehavior {
+    pub random_tick: Option<BlockTickFn>,
+    pub scheduled_update: Option<BlockTickFn>,
+}
+
+/// the behaviors of block types, by the tex id.
+#[derive(Resource, Default)]
+pub struct BlockBehaviors(HashMap<u16, BlockBehavior>);
+
+impl BlockBehaviors {
+    pub fn on_random_tick(&mut self, tex_id: u16, f: BlockTickFn) {
+        self.0.entry(tex_id).or_default().random_tick = Some(f);
+    }
+
+    pub fn on_scheduled_update(&mut self, tex_id: u16, f: BlockTickFn) {
+        self.0.entry(tex_id).or_default().scheduled_update = Some(f);
+    }
+
+    pub fn get(&self, tex_id: u16) -> Option<&BlockBehavior> {
+        self.0.get(&tex_id)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ScheduledUpdates {
+    // the current server tick
+    tick: u64,
+    // due tick -> positions
+    queue: BTreeMap<u64, Vec<IVec3>>,
+    // a voxel is scheduled once at most
+    scheduled: HashSet<IVec3>,
+}
+
+impl ScheduledUpdates {
+    /// the current server tick.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// update the voxel after `delay` ticks (at least 1). ignored if it's already scheduled.
+    pub fn schedule_update(&mut self, pos: IVec3, delay: u64) {
+        if self.scheduled.insert(pos) {
+            self.queue.entry(self.tick + delay.max(1)).or_default().push(pos);
+        }
+    }
+
+    /// the voxel changed: update it and its 6 neighbors on the next tick.
+    pub fn notify_changed(&mut self, pos: IVec3) {
+        self.schedule_update(pos, 1);
+        for n in NEIGHBORS {
+            self.schedule_update(pos + n, 1);
+        }
+    }
+
+    fn take_due(&mut self, max: usize) -> Vec<IVec3> {
+        let mut due = Vec::new();
+        while due.len() < max {
+            let Some(mut entry) = self.queue.first_entry() else {
+                break;
+            };
+            if *entry.key() > self.tick {
+                break;
+            }
+            let positions = entry.get_mut();
+            let n = positions.len().min(max - due.len());
+            due.extend(positions.drain(..n));
+            if positions.is_empty() {
+                entry.remove();
+            }
+        }
+        // may be scheduled again by its update.
+        for p in &due {
+            self.scheduled.remove(p);
+        }
+        due
+    }
+}
+
+/// what the behaviors access: the voxels and the scheduled updates.
+pub struct BlockTickCtx<'a> {
+    pub chunk_sys: &'a ServerChunkSystem,
+    pub updates: &'a mut ScheduledUpdates,
+    // changed cells to send, by chunkpos
+    modified: HashMap<IVec3, Vec<CellData>>,
+}
+
+impl BlockTickCtx<'_> {
+    /// None if the chunk isn't loaded.
+    pub fn get(&self, p: IVec3) -> Option<Vox> {
+        self.chunk_sys.get_voxel(p).copied()
+    }
+
+    /// change the voxel: the chunk is saved, sent to the clients, and the neighbors are updated. false if not loaded.
+    pub fn set(&mut self, p: IVec3, visitor: impl FnOnce(&mut Vox)) -> bool {
+        let Some(chunkptr) = self.chunk_sys.get_chunk(Chunk::as_chunkpos(p)) else {
+            return false;
+        };
+        let localpos = Chunk::as_localpos(p);
+        let vox = chunkptr.at_voxel_mut(localpos);
+        visitor(vox);
+        chunkptr.as_mut().is_modified = true;
+
+        let cell = CellData::from_cell(Chunk::local_idx(localpos) as u16, vox);
+        self.modified.entry(chunkptr.chunkpos).or_default().push(cell);
+        self.updates.notify_changed(p);
+        true
+    }
+}
+
+pub fn init(app: &mut App) {
+    let mut behaviors = BlockBehaviors::default();
+    behaviors.on_random_tick(VoxTex::Grass, grass_random_tick);
+    behaviors.on_random_tick(VoxTex::Leaves, leaves_random_tick);
+    behaviors.on_scheduled_update(VoxTex::Sand, sand_update);
+    app.insert_resource(behaviors);
+    app.insert_resource(ScheduledUpdates::default());
+
+    app.add_systems(FixedUpdate, tick_chunks.in_set(ServerTickSet));
+}
+
+fn tick_chunks(
+    chunk_sys: Res<ServerChunkSystem>,
+    mut updates: ResMut<ScheduledUpdates>,
+    behaviors: Res<BlockBehaviors>,
+    level: Res<LevelData>,
+    tick: Res<ServerTick>,
+    serverinfo: Res<ServerInfo>,
+    mut net_server: ResMut<RenetServer>,
+) {
+    updates.tick = tick.tick;
+    let mut ctx = BlockTickCtx {
+        chunk_sys: &chunk_sys,
+        updates: &mut updates,
+        modified: HashMap::default(),
+    };
+
+    // Scheduled Updates
+    for p in ctx.updates.take_due(MAX_UPDATES_PER_TICK) {
+        let update = ctx.get(p).and_then(|v| behaviors.get(v.tex_id)).and_then(|b| b.scheduled_update);
+        if let Some(update) = update {
+            update(&mut ctx, p);
+        }
+    }
+
+    // Random Ticks
+    let mut rng = rand::thread_rng();
+    for &chunkpos in chunk_sys.get_chunks().keys() {
+        for _ in 0..level.game_rules.random_tick_speed {
+            let p = chunkpos + IVec3::new(rng.gen_range(0..Chunk::LEN), rng.gen_range(0..Chunk::LEN), rng.gen_range(0..Chunk::LEN));
+            let random_tick = ctx.get(p).and_then(|v| behaviors.get(v.tex_id)).and_then(|b| b.random_tick);
+            if let Some(random_tick) = random_tick {
+                random_tick(&mut ctx, p);
+            }
+        }
+    }
+
+    for (chunkpos, voxel) in ctx.modified {
+        send_chunk_modify(&mut net_server, &serverinfo, chunkpos, voxel, None);
+    }
+}
+
+/// covered voxels don't grow grass. plants, leaves and water don't cover.
+fn is_covered(ctx: &BlockTickCtx, p: IVec3) -> bool {
+    ctx.get(p + IVec3::Y)
+        .is_some_and(|v| !v.is_nil() && v.tex_id != VoxTex::Water && !matches!(v.shape_id, VoxShape::Grass | VoxShape::Leaves))
+}
+
+/// covered grass dies into dirt, otherwise spreads to a nearby uncovered dirt.
+fn grass_random_tick(ctx: &mut BlockTickCtx, p: IVec3) {
+    if is_covered(ctx, p) {
+        ctx.set(p, |v| v.tex_id = VoxTex::Dirt);
+        return;
+    }
+    let mut rng = rand::thread_rng();
+    let target = p + IVec3::new(rng.gen_range(-1..=1), rng.gen_range(-1..=1), rng.gen_range(-1..=1));
+    if ctx.get(target).is_some_and(|v| v.tex_id == VoxTex::Dirt) && !is_covered(ctx, target) {
+        ctx.set(target, |v| v.tex_id = VoxTex::Grass);
+    }
+}
+
+/// leaves decay when no log is nearby. saplings share the tex id, they grow instead.
+fn leaves_random_tick(ctx: &mut BlockTickCtx, p: IVec3) {
+    if ctx.get(p).is_some_and(|v| v.is_sapling()) {
+        sapling_random_tick(ctx, p);
+        return;
+    }
+    let mut has_log = false;
+    iter::iter_aabb(LEAVES_DECAY_DISTANCE, LEAVES_DECAY_DISTANCE, |rp| {
+        // the short grass shares the tex id with the log, but not the shape.
+        has_log = has_log || ctx.get(p + rp).is_some_and(|v| v.tex_id == VoxTex::Log && v.shape_id != VoxShape::Grass);
+    });
+    if !has_log {
+        ctx.set(p, |v| v.tex_id = VoxTex::Nil);
+    }
+}
+
+/// a sapling on dirt or grass grows into a tree by chance. the leaves only fill empty voxels,
+/// the trunk replaces the sapling and the tree's own leaves.
+fn sapling_random_tick(ctx: &mut BlockTickCtx, p: IVec3) {
+    let mut rng = rand::thread_rng();
+    if !rng.gen_bool(SAPLING_GROW_CHANCE) || !ctx.get(p - IVec3::Y).is_some_and(|v| v.supports_plant()) {
+        return;
+    }
+    tree_voxels(rng.gen_range(SAPLING_TREE_SIZE), |rp, part| {
+        let replaceable = |v: Vox| match part {
+            TreePart::Leaves => v.is_nil(),
+            TreePart::Trunk { .. } => v.is_nil() || v.tex_id == VoxTex::Leaves,
+        };
+        if ctx.get(p + rp).is_some_and(replaceable) {
+            ctx.set(p + rp, |v| part.apply(v));
+        }
+    });
+}
+
+/// sand falls when nothing is below. it falls one voxel per tick, by updating itself again.
+fn sand_update(ctx: &mut BlockTickCtx, p: IVec3) {
+    let below = p - IVec3::Y;
+    if !ctx.get(below).is_some_and(|v| v.is_nil()) {
+        return;
+    }
+    let Some(sand) = ctx.get(p) else {
+        return;
+    };
+    ctx.set(below, |v| {
+        v.tex_id = sand.tex_id;
+        v.shape_id = sand.shape_id;
+        v.set_isovalue(sand.isovalue().max(0.5));
+    });
+    ctx.set(p, |v| {
+        v.tex_id = VoxTex::Nil;
+        v.set_isovalue(v.isovalue().min(0.));
+    });
+}
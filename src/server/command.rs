@@ -0,0 +1,165 @@
+use std::net::IpAddr;
+
+use bevy::prelude::*;
+use bevy_renet::{
+    netcode::NetcodeServerTransport,
+    renet::{ClientId, RenetServer},
+};
+
+use super::{
+    access_list::{AccessLists, BanEntry},
+    prelude::*,
+};
+use crate::net::RenetServerHelper;
+
+/// A chat message with leading '/' sent by a logged-in player. args are split by shlex, args[0] is the command name.
+#[derive(Event, Debug)]
+pub struct ServerCommand {
+    pub client_id: ClientId,
+    pub args: Vec<String>,
+}
+
+impl ServerCommand {
+    pub fn name(&self) -> &str {
+        &self.args[0]
+    }
+
+    pub fn arg(&self, idx: usize) -> Option<&str> {
+        self.args.get(idx).map(String::as_str)
+    }
+
+    /// join args[idx..] with space. e.g. for ban reason.
+    pub fn args_joined(&self, idx: usize) -> Option<String> {
+        self.args.get(idx..).filter(|a| !a.is_empty()).map(|a| a.join(" "))
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_event::<ServerCommand>();
+
+    app.add_systems(Update, cmd_access_lists);
+}
+
+fn cmd_access_lists(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    transport: Res<NetcodeServerTransport>,
+    mut serverinfo: ResMut<ServerInfo>,
+    mut cfg: ResMut<ServerSettings>,
+    mut lists: ResMut<AccessLists>,
+) {
+    for cmd in cmds.read() {
+        if !matches!(cmd.name(), "whitelist" | "ban" | "ban-ip" | "pardon" | "op" | "deop") {
+            continue;
+        }
+        let Some(sender) = serverinfo.online_players.get(&cmd.client_id) else {
+            continue;
+        };
+        if !lists.is_op(&sender.username) {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+
+        // players to be kicked after the list changed. (client_id, reason)
+        let mut kicks: Vec<(ClientId, String)> = Vec::new();
+
+        let reply = match (cmd.name(), cmd.arg(1)) {
+            ("whitelist", Some("on")) => {
+                cfg.whitelist_enabled = true;
+                cfg.save();
+                "Whitelist is now turned on".to_string()
+            }
+            ("whitelist", Some("off")) => {
+                cfg.whitelist_enabled = false;
+                cfg.save();
+                "Whitelist is now turned off".to_string()
+            }
+            ("whitelist", Some("list")) => {
+                format!("There are {} whitelisted players: {}", lists.whitelist.len(), Vec::from_iter(lists.whitelist.iter().cloned()).join(", "))
+            }
+            ("whitelist", Some("add")) if cmd.arg(2).is_some() => {
+                let name = cmd.arg(2).unwrap();
+                lists.whitelist.insert(name.to_lowercase());
+                lists.save();
+                format!("Added {name} to the whitelist")
+            }
+            ("whitelist", Some("remove")) if cmd.arg(2).is_some() => {
+                let name = cmd.arg(2).unwrap();
+                if lists.whitelist.remove(&name.to_lowercase()) {
+                    lists.save();
+                    format!("Removed {name} from the whitelist")
+                } else {
+                    format!("Player {name} is not whitelisted")
+                }
+            }
+            ("whitelist", _) => "Usage: /whitelist <on|off|list|add|remove> [player]".to_string(),
+
+            ("ban", Some(name)) => {
+                let reason = cmd.args_joined(2).unwrap_or("Banned by an operator.".into());
+                lists.banned_players.insert(name.to_lowercase(), BanEntry::new(reason.clone()));
+                lists.save();
+
+                for player in serverinfo.online_players.values().filter(|p| p.username.eq_ignore_ascii_case(name)) {
+                    kicks.push((player.client_id, format!("You are banned from this server. Reason: {reason}")));
+                }
+                format!("Banned player {name}: {reason}")
+            }
+            ("ban-ip", Some(target)) => {
+                // an IP address, or the name of an online player
+                let ip = target.parse::<IpAddr>().ok().or_else(|| {
+                    let player = serverinfo.online_players.values().find(|p| p.username.eq_ignore_ascii_case(target))?;
+                    Some(transport.client_addr(player.client_id)?.ip())
+                });
+                if let Some(ip) = ip {
+                    let reason = cmd.args_joined(2).unwrap_or("Banned by an operator.".into());
+                    lists.banned_ips.insert(ip, BanEntry::new(reason.clone()));
+                    lists.save();
+
+                    for player in serverinfo.online_players.values() {
+                        if transport.client_addr(player.client_id).is_some_and(|addr| addr.ip() == ip) {
+                            kicks.push((player.client_id, format!("Your IP address is banned from this server. Reason: {reason}")));
+                        }
+                    }
+                    format!("Banned IP {ip}: {reason}")
+                } else {
+                    format!("Invalid IP address or unknown player: {target}")
+                }
+            }
+            ("pardon", Some(target)) => {
+                let removed = if let Ok(ip) = target.parse::<IpAddr>() {
+                    lists.banned_ips.remove(&ip).is_some()
+                } else {
+                    lists.banned_players.remove(&target.to_lowercase()).is_some()
+                };
+                if removed {
+                    lists.save();
+                    format!("Unbanned {target}")
+                } else {
+                    format!("{target} is not banned")
+                }
+            }
+            ("op", Some(name)) => {
+                lists.ops.insert(name.to_lowercase());
+                lists.save();
+                format!("Made {name} a server operator")
+            }
+            ("deop", Some(name)) => {
+                if lists.ops.remove(&name.to_lowercase()) {
+                    lists.save();
+                    format!("Made {name} no longer a server operator")
+                } else {
+                    format!("Player {name} is not an operator")
+                }
+            }
+            ("op" | "deop", None) => format!("Usage: /{} <player>", cmd.name()),
+            _ => format!("Usage: /{} <player|ip> [reason]", cmd.name()),
+        };
+
+        info!("[CMD] {} ran {:?}: {}", sender.username, cmd.args, reply);
+        server.send_packet_chat(cmd.client_id, reply);
+
+        for (client_id, reason) in kicks {
+            serverinfo.kick(&mut server, client_id, reason);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Server-side Crafting.
+//!
+//! Each player has a crafting grid (`PlayerInfo::crafting_grid`). Clients click grid slots with the cursor stack like the inventory,
+//! and take the result of the matching recipe. The recipe book crafts a recipe directly from the inventory.
+//! Grid changes are answered by `SPacket::CraftingContent`, the inventory/cursor changes by `SPacket::InventorySlots`.
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+
+use super::{inventory, prelude::*};
+use crate::{
+    item::{ItemStack, Items, Recipes},
+    net::{RenetServerHelper, SPacket},
+};
+
+fn send_grid(server: &mut RenetServer, player: &PlayerInfo, recipes: &Recipes) {
+    let result = recipes.find(&player.crafting_grid).map_or(ItemStack::default(), |r| r.result);
+    server.send_packet(
+        player.client_id,
+        &SPacket::CraftingContent {
+            grid: player.crafting_grid.items.clone(),
+            result,
+        },
+    );
+}
+
+/// click a slot of the crafting grid with the cursor stack. see `Inventory::click`
+pub fn on_grid_click(server: &mut RenetServer, player: &mut PlayerInfo, slot: u16, secondary: bool, items: &Items, recipes: &Recipes) {
+    let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+    if !player.crafting_grid.click(slot as usize, secondary, &mut player.cursor, items) {
+        warn!("Invalid crafting grid slot {} from {}", slot, player.username);
+    }
+    send_grid(server, player, recipes);
+    inventory::send_changes(server, player, &before, cursor_before);
+}
+
+/// take the crafting result into the cursor. `all`: craft as many as possible into the inventory.
+pub fn on_take_result(server: &mut RenetServer, player: &mut PlayerInfo, all: bool, items: &Items, recipes: &Recipes) {
+    let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+
+    let mut crafted = 0;
+    while let Some(recipe) = recipes.find(&player.crafting_grid) {
+        let mut result = recipe.result;
+        if all {
+            let mut inv = player.inventory.clone();
+            inv.insert(&mut result, items);
+            if !result.is_empty() {
+                break; // inventory full
+            }
+            player.inventory = inv;
+        } else {
+            let max_stack = items.max_stack(result.item_id);
+            let fits = player.cursor.is_empty() || (player.cursor.stacks_with(&result) && player.cursor.count + result.count <= max_stack);
+            if !fits {
+                break;
+            }
+            player.cursor.merge(&mut result, max_stack);
+        }
+
+        // consume one of each ingredient
+        for stack in player.crafting_grid.items.iter_mut().filter(|s| !s.is_empty()) {
+            stack.count -= 1;
+            if stack.count == 0 {
+                stack.clear();
+            }
+        }
+        crafted += 1;
+        if !all {
+            break;
+        }
+    }
+    if crafted > 0 {
+        debug!("{} crafted {} times", player.username, crafted);
+    }
+    send_grid(server, player, recipes);
+    inventory::send_changes(server, player, &before, cursor_before);
+}
+
+/// Recipe Book: craft the recipe with the ingredients in the inventory, the result goes into the inventory.
+pub fn on_craft_recipe(server: &mut RenetServer, player: &mut PlayerInfo, recipe_id: &str, items: &Items, recipes: &Recipes) {
+    let Some(recipe) = recipes.get(recipe_id) else {
+        warn!("Unknown recipe {} from {}", recipe_id, player.username);
+        return;
+    };
+    let mut inv = player.inventory.clone();
+    for (item_id, count) in recipe.ingredients() {
+        if inv.remove_item(item_id, count) != count {
+            server.send_packet_chat(player.client_id, "Missing ingredients.".into());
+            return;
+        }
+    }
+    let mut result = recipe.result;
+    inv.insert(&mut result, items);
+    if !result.is_empty() {
+        server.send_packet_chat(player.client_id, "Inventory is full.".into());
+        return;
+    }
+
+    let (before, cursor_before) = (std::mem::replace(&mut player.inventory, inv), player.cursor);
+    inventory::send_changes(server, player, &before, cursor_before);
+}
+
+/// move the cursor stack and the items left in the crafting grid back to the inventory, e.g. on logout.
+/// what doesn't fit drops at the player.
+pub fn return_held_items(player: &mut PlayerInfo, items: &Items, cmds: &mut Commands) {
+    let mut held = vec![std::mem::take(&mut player.cursor)];
+    held.extend(player.crafting_grid.items.iter_mut().map(std::mem::take));
+
+    for mut stack in held.into_iter().filter(|s| !s.is_empty()) {
+        player.inventory.insert(&mut stack, items);
+        if !stack.is_empty() {
+            super::item_drop::spawn_item_drop(cmds, player.position, stack);
+        }
+    }
+}
@@ -2,11 +2,17 @@ use bevy::{
     prelude::*,
     platform::collections::{HashMap, HashSet},
 };
-use bevy_renet::renet::ClientId;
+use bevy_renet::renet::{ClientId, RenetServer};
 
+use super::{
+    access_list::AccessLists, game_mode::GameMode, level::LevelData, mining::MiningState, movement::{MoveBudget, PendingCorrection}, player_data::PlayerData,
+    tick::ServerTick,
+};
 use crate::{
-    net::{EntityId, ServerNetworkPlugin},
-    voxel::ServerVoxelPlugin,
+    item::{recipe, Inventory, ItemStack, Items, Recipes},
+    net::{auth::{self, AuthMode}, ConnectionState, EntityId, RenetServerHelper, ServerNetworkPlugin, ServerStatus, PROTOCOL_VERSION},
+    util::current_timestamp_millis,
+    voxel::{ChunkLoader, ServerChunkSystem, ServerVoxelPlugin},
 };
 
 pub struct DedicatedServerPlugin;
@@ -15,6 +21,29 @@ impl Plugin for DedicatedServerPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ServerInfo::default());
         app.insert_resource(ServerSettings::default());
+        app.insert_resource(AccessLists::default());
+        app.insert_resource(LevelData::default());
+
+        let mut items = Items::default();
+        items.register_all();
+        app.insert_resource(Recipes::load(recipe::RECIPES_DIR, &items));
+        app.insert_resource(items);
+
+        // Commands
+        super::command::init(app);
+
+        // Tick
+        super::tick::init(app);
+        super::level::init(app); // World Time
+        super::item_drop::init(app);
+        super::inventory::init(app);
+        super::mining::init(app);
+        super::game_mode::init(app);
+        super::health::init(app);
+        super::player_data::init(app);
+        super::movement::init(app);
+        super::chunk_tick::init(app);
+        super::fluid::init(app);
 
         // Network
         app.add_plugins(ServerNetworkPlugin);
@@ -37,23 +66,73 @@ impl Plugin for DedicatedServerPlugin {
 }
 
 const SERVER_SETTINGS_FILE: &str = "server.settings.json";
+const SERVER_ICON_FILE: &str = "server-icon.png";
 
-fn on_init(mut cfg: ResMut<ServerSettings>) {
+fn on_init(
+    mut cfg: ResMut<ServerSettings>,
+    mut lists: ResMut<AccessLists>,
+    mut serverinfo: ResMut<ServerInfo>,
+    mut time_fixed: ResMut<Time<Fixed>>,
+    mut level: ResMut<LevelData>,
+) {
     info!("Loading server settings from {SERVER_SETTINGS_FILE}");
 
-    if let Ok(c) = serde_json::from_str(&SERVER_SETTINGS_FILE) {
-        *cfg = c;
+    if let Ok(str) = std::fs::read_to_string(SERVER_SETTINGS_FILE) {
+        if let Ok(c) = serde_json::from_str(&str) {
+            *cfg = c;
+        }
+    }
+    cfg.settings_file = Some(SERVER_SETTINGS_FILE.into());
+
+    if cfg.auth_mode == AuthMode::Secure && cfg.auth_private_key.is_empty() {
+        info!("Generating new auth private key, saving to {SERVER_SETTINGS_FILE}");
+        cfg.auth_private_key = auth::generate_private_key();
+        cfg.save();
+    }
+
+    time_fixed.set_timestep_hz(cfg.tps.max(1.) as f64);
+    info!("Server tick rate {} TPS", cfg.tps);
+
+    if let Ok(favicon) = std::fs::read(SERVER_ICON_FILE) {
+        serverinfo.favicon = favicon;
+    }
+
+    info!("Loading world level from {}", cfg.world_dir);
+    *level = LevelData::load(&cfg.world_dir);
+
+    *lists = AccessLists::load(".");
+    if lists.ops.is_empty() {
+        warn!("No operators in ops.json, nobody can use admin commands. Add one by the local RCON endpoint /op?username=<name>");
     }
 }
 
-fn on_exit(mut exit_events: EventReader<bevy::app::AppExit>, cfg: Res<ServerSettings>) {
+fn on_exit(
+    mut exit_events: EventReader<bevy::app::AppExit>,
+    cfg: Res<ServerSettings>,
+    mut level: ResMut<LevelData>,
+    serverinfo: Res<ServerInfo>,
+    items: Res<Items>,
+    chunk_sys: Res<ServerChunkSystem>,
+) {
     for _ in exit_events.read() {
         info!("Saving server settings to {SERVER_SETTINGS_FILE}");
+        cfg.save();
 
-        std::fs::write(SERVER_SETTINGS_FILE, serde_json::to_string_pretty(&*cfg).unwrap()).unwrap();
+        save_world(&cfg.world_dir, &mut level, &serverinfo, &items, &chunk_sys);
     }
 }
 
+/// save the level, the online players and the modified chunks into the world directory.
+pub fn save_world(world_dir: &str, level: &mut LevelData, serverinfo: &ServerInfo, items: &Items, chunk_sys: &ServerChunkSystem) {
+    info!("Saving world level to {}", world_dir);
+    level.save(world_dir);
+
+    for player in serverinfo.online_players.values() {
+        PlayerData::from_player(player, items).save(world_dir, player.user_id);
+    }
+    chunk_sys.save_modified(&ChunkLoader::new(world_dir));
+}
+
 pub mod rcon {
     use super::*;
 
@@ -65,6 +144,8 @@ pub mod rcon {
         pub num_player_limit: u32,
         pub protocol_version: u64,
         pub favicon_url: String,
+        pub tps: f32,
+        pub mspt: f32,
     }
 
     #[derive(Resource)]
@@ -72,16 +153,63 @@ pub mod rcon {
         pub server: tiny_http::Server,
     }
 
-    pub fn on_http_recv(http: Res<HttpServer>, serv: Res<ServerInfo>, cfg: Res<ServerSettings>) {
+    pub fn on_http_recv(
+        http: Res<HttpServer>,
+        serv: Res<ServerInfo>,
+        cfg: Res<ServerSettings>,
+        tick: Res<ServerTick>,
+        mut lists: ResMut<AccessLists>,
+    ) {
         if let Ok(Some(req)) = http.server.try_recv() {
             info!("Req URL: {}", req.url());
+
+            // Make a player operator. only for requests from the same machine, e.g. the server owner seeding ops.json.
+            if req.url().starts_with("/op?") {
+                let is_local = req.remote_addr().is_some_and(|addr| addr.ip().is_loopback());
+                let username = crate::util::query_param(req.url(), "username");
+
+                let resp = match (is_local, username) {
+                    (true, Some(username)) if !username.is_empty() => {
+                        lists.ops.insert(username.to_lowercase());
+                        lists.save();
+                        info!("Made {} a server operator", username);
+                        tiny_http::Response::from_string(format!("Made {username} a server operator"))
+                    }
+                    (false, _) => tiny_http::Response::from_string("Forbidden").with_status_code(403),
+                    _ => tiny_http::Response::from_string("Bad Request").with_status_code(400),
+                };
+                req.respond(resp).unwrap();
+                return;
+            }
+
+            // Local Auth Service: issue ConnectTokens. only for requests from the same machine.
+            if req.url().starts_with("/auth?") {
+                let is_local = req.remote_addr().is_some_and(|addr| addr.ip().is_loopback());
+                let username = crate::util::query_param(req.url(), "username");
+
+                let resp = match (is_local, username, auth::parse_private_key(&cfg.auth_private_key)) {
+                    (true, Some(username), Some(private_key)) if !username.is_empty() => {
+                        match auth::issue_connect_token(&private_key, cfg.connect_addresses(), &username) {
+                            Ok(token) => tiny_http::Response::from_data(auth::token_to_bytes(&token)),
+                            Err(err) => tiny_http::Response::from_string(err.to_string()).with_status_code(500),
+                        }
+                    }
+                    (false, ..) => tiny_http::Response::from_string("Forbidden").with_status_code(403),
+                    _ => tiny_http::Response::from_string("Bad Request").with_status_code(400),
+                };
+                req.respond(resp).unwrap();
+                return;
+            }
+
             let motd = Motd {
                 motd: cfg.motd.clone(),
                 num_player_limit: cfg.num_player_limit,
                 num_player_online: serv.online_players.len() as u32,
-                protocol_version: 0,
+                protocol_version: PROTOCOL_VERSION,
                 favicon_url: "".into(),
                 game_addr: format!(":{}", cfg.port),
+                tps: tick.measured_tps,
+                mspt: tick.mspt,
             };
             req.respond(tiny_http::Response::from_string(serde_json::to_string(&motd).unwrap()))
                 .unwrap();
@@ -90,18 +218,72 @@ pub mod rcon {
 }
 
 #[derive(Resource, serde::Deserialize, serde::Serialize, Asset, TypePath, Clone)]
+#[serde(default)]
 pub struct ServerSettings {
     pub port: u16,
+    // the interface to bind. 0.0.0.0 for all, 127.0.0.1 for the local machine only
+    pub bind_addr: std::net::IpAddr,
     pub num_player_limit: u32,
     pub motd: String,
+
+    // only players in whitelist.json can join
+    pub whitelist_enabled: bool,
+
+    pub auth_mode: AuthMode,
+    // hex of the 32 bytes netcode private key. generated on first start.
+    pub auth_private_key: String,
+    // addresses clients connect to, must match the ConnectTokens. empty for local (127.0.0.1:port)
+    pub public_addresses: Vec<std::net::SocketAddr>,
+    // the chunks load distance requested by clients is capped to this
+    pub max_chunks_load_distance: IVec2,
+
+    // server simulation ticks per second
+    pub tps: f32,
+
+    // directory of the world save
+    pub world_dir: String,
+
+    // where the settings are saved, see `save`. None for the integrated server, its settings are not persisted.
+    #[serde(skip)]
+    pub settings_file: Option<std::path::PathBuf>,
 }
 
 impl Default for ServerSettings {
     fn default() -> Self {
         Self {
             port: 4060,
+            bind_addr: std::net::Ipv4Addr::UNSPECIFIED.into(),
             num_player_limit: 80,
             motd: "An Ethertum Server".into(),
+            whitelist_enabled: false,
+            auth_mode: AuthMode::Secure,
+            auth_private_key: String::new(),
+            public_addresses: Vec::new(),
+            max_chunks_load_distance: IVec2::new(12, 8),
+            tps: 20.,
+            world_dir: "world".into(),
+            settings_file: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// write the settings into `settings_file`, if any. e.g. when changed by a command.
+    pub fn save(&self) {
+        let Some(file) = &self.settings_file else {
+            return;
+        };
+        if let Err(err) = std::fs::write(file, serde_json::to_string_pretty(self).unwrap()) {
+            error!("Failed to save {}: {err}", file.display());
+        }
+    }
+
+    /// the addresses ConnectTokens are issued for, clients connect to them.
+    pub fn connect_addresses(&self) -> Vec<std::net::SocketAddr> {
+        if self.public_addresses.is_empty() {
+            vec![std::net::SocketAddr::from(([127, 0, 0, 1], self.port))]
+        } else {
+            self.public_addresses.clone()
         }
     }
 }
@@ -110,6 +292,52 @@ impl Default for ServerSettings {
 pub struct ServerInfo {
     // PlayerList
     pub online_players: HashMap<ClientId, PlayerInfo>,
+
+    // all connected clients, include not logged in.
+    pub connections: HashMap<ClientId, ClientConnection>,
+
+    // PNG bytes of server-icon.png
+    pub favicon: Vec<u8>,
+}
+
+impl ServerInfo {
+    pub fn status(&self, cfg: &ServerSettings) -> ServerStatus {
+        ServerStatus {
+            motd: cfg.motd.clone(),
+            num_players_limit: cfg.num_player_limit,
+            num_players_online: self.online_players.len() as u32,
+            protocol_version: PROTOCOL_VERSION,
+            version_name: crate::VERSION_NAME.into(),
+            favicon: self.favicon.clone(),
+        }
+    }
+
+    /// send the Disconnect reason, and force the disconnect after the grace time in case the client ignores it.
+    /// all kicks go through here. the packets of the client are dropped meanwhile.
+    pub fn kick(&mut self, server: &mut RenetServer, client_id: ClientId, reason: String) {
+        let conn = self.connections.entry(client_id).or_default();
+        if conn.disconnect_at.is_some() {
+            return;
+        }
+        conn.disconnect_at = Some(current_timestamp_millis() + ClientConnection::DISCONNECT_GRACE_MILLIS);
+        server.send_packet_disconnect(client_id, reason);
+    }
+}
+
+#[derive(Default)]
+pub struct ClientConnection {
+    pub state: ConnectionState,
+
+    // malformed/undecodable packets received. disconnect when exceeds codec::MAX_BAD_PACKETS
+    pub num_bad_packets: u32,
+
+    // timestamp millis. force disconnect after, in case the client ignores the Disconnect packet.
+    pub disconnect_at: Option<u64>,
+}
+
+impl ClientConnection {
+    /// grace time for the Disconnect packet to be delivered before the force disconnect.
+    pub const DISCONNECT_GRACE_MILLIS: u64 = 1000;
 }
 
 pub struct PlayerInfo {
@@ -120,14 +348,42 @@ pub struct PlayerInfo {
 
     pub entity_id: EntityId,
     pub position: Vec3,
+    pub yaw: f32,
+    pub last_pos_seq: u32, // seq of the last accepted PlayerPos
+    pub last_move_time: u64, // timestamp millis of the last received PlayerPos
+    // see server::movement
+    pub move_budget: MoveBudget,
+    pub correction: Option<PendingCorrection>,
+    pub move_violations: u32,
     pub ping_rtt: u32,
 
     pub chunks_load_distance: IVec2,
 
     pub chunks_loaded: HashSet<IVec3>,
+
+    // entities replicated to the player. see replication::replicate_entities
+    pub entities_visible: HashSet<EntityId>,
+
+    // authoritative. see server::inventory
+    pub inventory: Inventory,
+    // the stack held by the mouse in the inventory ui
+    pub cursor: ItemStack,
+    pub hotbar_index: u8,
+    // CRAFTING_GRID x CRAFTING_GRID. see server::crafting
+    pub crafting_grid: Inventory,
+
+    // the voxel being mined. see server::mining
+    pub mining: Option<MiningState>,
+    pub game_mode: GameMode,
+
+    // see server::health
+    pub health: u32,
+    // seconds of air left under water
+    pub air: f32,
 }
 
 impl PlayerInfo {
-    // fn update(&self) {
-    // }
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
 }
@@ -0,0 +1,105 @@
+//! Fluid Simulation of Water.
+//!
+//! A cellular simulation driven by the scheduled updates: a water voxel is updated when it or a neighbor changed,
+//! and flows every FLOW_TICKS. The fluid level is `Vox::fluid_level`: sources are FLUID_SOURCE, flowing water is lower.
+//! Water falls into the empty voxel below as FALLING_LEVEL, otherwise spreads sideways one level lower,
+//! while resting on a solid or a source. Flowing water fed by no neighbor dries up level by level.
+//! Two horizontal sources make a new source between them.
+
+use bevy::prelude::*;
+
+use super::chunk_tick::{BlockBehaviors, BlockTickCtx};
+use crate::voxel::{Vox, VoxShape, VoxTex};
+
+/// ticks per flow step.
+const FLOW_TICKS: u64 = 5;
+
+/// the level of water falling down, and fed from above.
+const FALLING_LEVEL: u8 = Vox::FLUID_SOURCE - 1;
+
+const HORIZONTAL_NEIGHBORS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+pub fn init(app: &mut App) {
+    let mut behaviors = app.world_mut().resource_mut::<BlockBehaviors>();
+    behaviors.on_scheduled_update(VoxTex::Water, water_update);
+}
+
+fn is_water(v: &Vox) -> bool {
+    v.tex_id == VoxTex::Water
+}
+
+/// water can spread sideways only when resting on a solid or a source, not while falling.
+fn rests(ctx: &BlockTickCtx, p: IVec3) -> bool {
+    ctx.get(p - IVec3::Y).is_some_and(|v| !v.is_nil() && (!is_water(&v) || v.is_fluid_source()))
+}
+
+/// the level of flowing water at p by its neighbors. 0 if nothing feeds it.
+fn inflow_level(ctx: &BlockTickCtx, p: IVec3) -> u8 {
+    let mut sources = 0;
+    let mut level = 0;
+    for n in HORIZONTAL_NEIGHBORS {
+        let Some(v) = ctx.get(p + n).filter(is_water) else {
+            continue;
+        };
+        if v.is_fluid_source() {
+            sources += 1;
+        }
+        if rests(ctx, p + n) {
+            level = level.max(v.fluid_level().saturating_sub(1));
+        }
+    }
+    if sources >= 2 && rests(ctx, p) {
+        return Vox::FLUID_SOURCE;
+    }
+    if ctx.get(p + IVec3::Y).is_some_and(|v| is_water(&v)) {
+        return FALLING_LEVEL;
+    }
+    level
+}
+
+fn fill(v: &mut Vox, level: u8) {
+    v.tex_id = VoxTex::Water;
+    v.shape_id = VoxShape::Isosurface;
+    v.set_fluid_level(level);
+}
+
+fn water_update(ctx: &mut BlockTickCtx, p: IVec3) {
+    // flow in steps, wait for the next one.
+    let wait = FLOW_TICKS - ctx.updates.tick() % FLOW_TICKS;
+    if wait != FLOW_TICKS {
+        ctx.updates.schedule_update(p, wait);
+        return;
+    }
+    let Some(vox) = ctx.get(p) else {
+        return;
+    };
+
+    let level = if vox.is_fluid_source() { Vox::FLUID_SOURCE } else { inflow_level(ctx, p) };
+    if level == 0 {
+        ctx.set(p, |v| {
+            v.tex_id = VoxTex::Nil;
+            v.set_isovalue(Vox::WATER_ISOVALUE);
+        });
+        return;
+    }
+    if level != vox.fluid_level() {
+        ctx.set(p, |v| v.set_fluid_level(level));
+    }
+
+    // Fall
+    let below = p - IVec3::Y;
+    if ctx.get(below).is_some_and(|v| v.is_nil()) {
+        ctx.set(below, |v| fill(v, FALLING_LEVEL));
+        return;
+    }
+
+    // Spread
+    if level <= 1 || !rests(ctx, p) {
+        return;
+    }
+    for n in HORIZONTAL_NEIGHBORS {
+        if ctx.get(p + n).is_some_and(|v| v.is_nil()) {
+            ctx.set(p + n, |v| fill(v, level - 1));
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Player Game Modes.
+//!
+//! Each player has a game mode (`PlayerInfo::game_mode`), the default of new players is `LevelData::game_mode`.
+//! Set by `/gamemode`, synced to the client by `SPacket::PlayerGameMode`, persisted with the `PlayerData`.
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use super::prelude::*;
+use crate::net::{RenetServerHelper, SPacket};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum GameMode {
+    #[default]
+    Survival,
+    Creative,
+    Spectator,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::Survival, GameMode::Creative, GameMode::Spectator];
+
+    /// by name, or by number 0/1/2. e.g. "creative", "c", "1"
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "survival" | "s" | "0" => Self::Survival,
+            "creative" | "c" | "1" => Self::Creative,
+            "spectator" | "sp" | "2" => Self::Spectator,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Survival => "Survival",
+            Self::Creative => "Creative",
+            Self::Spectator => "Spectator",
+        }
+    }
+
+    /// can toggle flying. spectators always fly.
+    pub fn can_fly(self) -> bool {
+        self != Self::Survival
+    }
+
+    /// break voxels instantly, instead of mining.
+    pub fn instant_break(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// placing doesn't consume items, tools don't wear, and broken voxels drop nothing.
+    pub fn infinite_items(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// can break/place voxels and pick up items.
+    pub fn can_interact(self) -> bool {
+        self != Self::Spectator
+    }
+
+    /// no collision with the world.
+    pub fn noclip(self) -> bool {
+        self == Self::Spectator
+    }
+
+    pub fn takes_damage(self) -> bool {
+        self == Self::Survival
+    }
+
+    /// max moving speed (m/s) accepted by the server, a bit above the sprinting/flying speed. see server::movement
+    pub fn max_speed(self) -> f32 {
+        match self {
+            Self::Survival => 12.,
+            Self::Creative => 30.,
+            Self::Spectator => 40.,
+        }
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(Update, cmd_gamemode);
+}
+
+/// set the game mode of the player, and sync it to the client.
+pub fn set_game_mode(server: &mut RenetServer, player: &mut PlayerInfo, game_mode: GameMode) {
+    player.game_mode = game_mode;
+    player.mining = None;
+    server.send_packet(player.client_id, &SPacket::PlayerGameMode { game_mode });
+}
+
+/// /gamemode <mode> [player]
+fn cmd_gamemode(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut serverinfo: ResMut<ServerInfo>,
+    lists: Res<AccessLists>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "gamemode") {
+        if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+        let Some(game_mode) = cmd.arg(1).and_then(GameMode::parse) else {
+            server.send_packet_chat(cmd.client_id, "Usage: /gamemode <survival|creative|spectator> [player]".into());
+            continue;
+        };
+        let player = match cmd.arg(2) {
+            Some(name) => serverinfo.online_players.values_mut().find(|p| p.username.eq_ignore_ascii_case(name)),
+            None => serverinfo.online_players.get_mut(&cmd.client_id),
+        };
+        let Some(player) = player else {
+            server.send_packet_chat(cmd.client_id, format!("Player {} is not online", cmd.arg(2).unwrap_or_default()));
+            continue;
+        };
+        set_game_mode(&mut server, player, game_mode);
+        info!("Set {}'s game mode to {}", player.username, game_mode.name());
+
+        let reply = format!("Set {}'s game mode to {}", player.username, game_mode.name());
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
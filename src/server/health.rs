@@ -0,0 +1,214 @@
+//! Player Health, Damage and Death.
+//!
+//! Health is authoritative on the server (`PlayerInfo::health`), synced to the client by `SPacket::PlayerHealth`.
+//! Damage sources: falling (the client reports its landing velocity by `CPacket::PlayerLand`), drowning in water, and the void.
+//! Only players whose game mode takes damage are hurt, except by `/kill`.
+//! On death the inventory is dropped, the client shows the respawn screen and sends `CPacket::Respawn`,
+//! then the player is moved to the world spawn point (`LevelData::spawn_point`) by `SPacket::PlayerRespawn`.
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+
+use super::{inventory, prelude::*};
+use crate::{
+    net::{RenetServerHelper, SPacket, EYE_HEIGHT},
+    voxel::{ChunkSystem, ServerChunkSystem, VoxTex},
+};
+
+pub const MAX_HEALTH: u32 = 20;
+
+/// seconds of air under water before drowning.
+pub const MAX_AIR: f32 = 15.;
+
+/// landing slower than this (m/s) doesn't hurt. about a 3 voxels fall.
+const SAFE_FALL_SPEED: f32 = 11.;
+const FALL_DAMAGE_PER_SPEED: f32 = 0.8;
+
+/// below this height is the void.
+pub const VOID_Y: f32 = -128.;
+
+/// seconds per regenerated health point.
+const REGEN_SECS: f32 = 4.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageCause {
+    Fall,
+    Drown,
+    Void,
+    Kill,
+}
+
+impl DamageCause {
+    fn death_message(self, username: &str) -> String {
+        match self {
+            Self::Fall => format!("{username} fell from a high place"),
+            Self::Drown => format!("{username} drowned"),
+            Self::Void => format!("{username} fell out of the world"),
+            Self::Kill => format!("{username} was killed"),
+        }
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(FixedUpdate, tick_player_health.in_set(ServerTickSet));
+    app.add_systems(Update, (cmd_kill, cmd_setworldspawn));
+}
+
+pub fn health_packet(player: &PlayerInfo) -> SPacket {
+    SPacket::PlayerHealth {
+        health: player.health,
+        health_max: MAX_HEALTH,
+    }
+}
+
+/// hurt the player. dies when the health reaches 0: the inventory drops and the death is announced.
+pub fn damage(server: &mut RenetServer, player: &mut PlayerInfo, amount: u32, cause: DamageCause, cmds: &mut Commands) {
+    if amount == 0 || !player.is_alive() || (cause != DamageCause::Kill && !player.game_mode.takes_damage()) {
+        return;
+    }
+    player.health = player.health.saturating_sub(amount);
+    debug!("{} took {} damage by {:?}, health {}", player.username, amount, cause, player.health);
+    server.send_packet(player.client_id, &health_packet(player));
+
+    if player.is_alive() {
+        return;
+    }
+    info!("{}", cause.death_message(&player.username));
+    server.broadcast_packet_chat(cause.death_message(&player.username));
+
+    // drop everything, including the cursor stack and the crafting grid.
+    let stacks = player.inventory.items.iter_mut().chain(player.crafting_grid.items.iter_mut()).chain(std::iter::once(&mut player.cursor));
+    for stack in stacks.filter(|s| !s.is_empty()) {
+        super::item_drop::spawn_item_drop(cmds, player.position, *stack);
+        stack.clear();
+    }
+    player.mining = None;
+    server.send_packet(player.client_id, &inventory::content_packet(player));
+}
+
+/// the client landed with the vertical speed (m/s, positive downwards). falling into water doesn't hurt.
+pub fn on_land(server: &mut RenetServer, player: &mut PlayerInfo, fall_velocity: f32, chunk_sys: &ServerChunkSystem, cmds: &mut Commands) {
+    let in_water = chunk_sys.get_voxel(player.position.floor().as_ivec3()).is_some_and(|v| v.tex_id == VoxTex::Water);
+    if in_water || !fall_velocity.is_finite() {
+        return;
+    }
+    let amount = ((fall_velocity - SAFE_FALL_SPEED) * FALL_DAMAGE_PER_SPEED).round();
+    if amount > 0. {
+        damage(server, player, amount as u32, DamageCause::Fall, cmds);
+    }
+}
+
+/// respawn a dead player at the world spawn point.
+pub fn on_respawn(server: &mut RenetServer, player: &mut PlayerInfo, level: &LevelData) {
+    if player.is_alive() {
+        return;
+    }
+    player.health = MAX_HEALTH;
+    player.air = MAX_AIR;
+    super::movement::expect_position(player, level.spawn_point);
+    info!("{} respawned at {}", player.username, player.position);
+
+    server.send_packet(player.client_id, &health_packet(player));
+    server.send_packet(player.client_id, &SPacket::PlayerRespawn { position: player.position });
+}
+
+/// drowning, the void, and regeneration.
+fn tick_player_health(
+    mut serverinfo: ResMut<ServerInfo>,
+    chunk_sys: Res<ServerChunkSystem>,
+    tick: Res<ServerTick>,
+    mut server: ResMut<RenetServer>,
+    mut cmds: Commands,
+) {
+    let dt_sec = 1. / tick.tps;
+    let tps = tick.tps.round().max(1.) as u64;
+    // damage/regen once a second
+    let every_sec = tick.tick % tps == 0;
+
+    for player in serverinfo.online_players.values_mut() {
+        if !player.is_alive() {
+            continue;
+        }
+        if player.position.y < VOID_Y {
+            if every_sec {
+                damage(&mut server, player, 4, DamageCause::Void, &mut cmds);
+            }
+            continue;
+        }
+
+        let eye = (player.position + Vec3::Y * EYE_HEIGHT).floor().as_ivec3();
+        let under_water = chunk_sys.get_voxel(eye).is_some_and(|v| v.tex_id == VoxTex::Water);
+        if under_water && player.game_mode.takes_damage() {
+            player.air = (player.air - dt_sec).max(0.);
+            if player.air == 0. && every_sec {
+                damage(&mut server, player, 2, DamageCause::Drown, &mut cmds);
+            }
+        } else {
+            player.air = MAX_AIR;
+        }
+
+        let regen_ticks = (REGEN_SECS * tick.tps).round().max(1.) as u64;
+        if player.health < MAX_HEALTH && player.air > 0. && tick.tick % regen_ticks == 0 {
+            player.health += 1;
+            server.send_packet(player.client_id, &health_packet(player));
+        }
+    }
+}
+
+/// /kill [player]
+fn cmd_kill(
+    mut cmds_events: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut serverinfo: ResMut<ServerInfo>,
+    lists: Res<AccessLists>,
+    mut cmds: Commands,
+) {
+    for cmd in cmds_events.read().filter(|c| c.name() == "kill") {
+        let is_op = serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username));
+        // killing yourself is always allowed.
+        if cmd.arg(1).is_some() && !is_op {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+        let player = match cmd.arg(1) {
+            Some(name) => serverinfo.online_players.values_mut().find(|p| p.username.eq_ignore_ascii_case(name)),
+            None => serverinfo.online_players.get_mut(&cmd.client_id),
+        };
+        let Some(player) = player else {
+            server.send_packet_chat(cmd.client_id, format!("Player {} is not online", cmd.arg(1).unwrap_or_default()));
+            continue;
+        };
+        let health = player.health;
+        damage(&mut server, player, health, DamageCause::Kill, &mut cmds);
+    }
+}
+
+/// /setworldspawn [x y z]. the sender's position if not given.
+fn cmd_setworldspawn(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    serverinfo: Res<ServerInfo>,
+    lists: Res<AccessLists>,
+    mut level: ResMut<LevelData>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "setworldspawn") {
+        let Some(sender) = serverinfo.online_players.get(&cmd.client_id) else {
+            continue;
+        };
+        if !lists.is_op(&sender.username) {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+        let pos = match (cmd.arg(1), cmd.arg(2), cmd.arg(3)) {
+            (None, None, None) => Some(sender.position),
+            (Some(x), Some(y), Some(z)) => x.parse().ok().zip(y.parse().ok()).zip(z.parse().ok()).map(|((x, y), z)| Vec3::new(x, y, z)),
+            _ => None,
+        };
+        let Some(pos) = pos else {
+            server.send_packet_chat(cmd.client_id, "Usage: /setworldspawn [x y z]".into());
+            continue;
+        };
+        level.spawn_point = pos;
+        server.send_packet_chat(cmd.client_id, format!("Set the world spawn point to {:.1} {:.1} {:.1}", pos.x, pos.y, pos.z));
+    }
+}
@@ -1,23 +1,200 @@
+//! Integrated Server of Singleplayer.
+//!
+//! Runs a headless server App on its own thread while a local world is opened, so the server ticks don't share
+//! the render frame. Started by `IntegratedServer::start` when the world is opened, stopped by dropping it on
+//! exit_world: the world is saved and the thread joined. Binds loopback only, unless opened to LAN: then it binds
+//! all interfaces and announces the game to the local network (see `net::lan`).
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use bevy::app::{AppExit, ScheduleRunnerPlugin};
+use bevy_renet::{netcode::NetcodeServerTransport, renet::RenetServer};
 use rand::Rng;
 
-use crate::{net::ServerNetworkPlugin, prelude::*, voxel::ServerVoxelPlugin};
+use crate::{
+    channel_impl::{Receiver, Sender},
+    item::{recipe, Items, Recipes},
+    net::{auth::AuthMode, lan, ServerNetworkPlugin},
+    prelude::*,
+    voxel::{ServerChunkSystem, ServerVoxelPlugin},
+};
+
+use super::{
+    dedicated_server::save_world,
+    prelude::{AccessLists, LevelData, ServerInfo, ServerSettings},
+};
+
+/// Client: the running Integrated Server of the opened local world. drop to stop it.
+#[derive(Resource)]
+pub struct IntegratedServer {
+    pub world_dir: PathBuf,
+    pub port: u16,
+    pub open_to_lan: bool,
 
-use super::prelude::{ServerInfo, ServerSettings};
+    stop_tx: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IntegratedServer {
+    /// start the server of the local world on a new thread, on a random port. the host is the operator.
+    pub fn start(world_dir: PathBuf, open_to_lan: bool, host: &str) -> Self {
+        let port = 6000 + rand::thread_rng().gen_range(0..6000);
+        let (stop_tx, stop_rx) = crate::channel_impl::bounded(1);
 
-pub struct IntegratedServerPlugin;
+        info!("Starting Integrated Server of {} at port {}", world_dir.display(), port);
+        let plugin = IntegratedServerPlugin {
+            world_dir: world_dir.clone(),
+            port,
+            open_to_lan,
+            host: host.to_lowercase(),
+            stop_rx,
+        };
+        let thread = std::thread::Builder::new()
+            .name("Integrated Server".into())
+            .spawn(move || {
+                let frame_time = Duration::from_secs_f32(1.0 / 60.0);
+                App::new()
+                    .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(frame_time)))
+                    .add_plugins(plugin)
+                    .run();
+                info!("Integrated Server stopped");
+            })
+            .expect("Failed to spawn the Integrated Server thread");
+
+        Self {
+            world_dir,
+            port,
+            open_to_lan,
+            stop_tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for IntegratedServer {
+    /// stop the server, blocks until the world is saved.
+    fn drop(&mut self) {
+        let _ = self.stop_tx.try_send(());
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Integrated Server thread panicked");
+            }
+        }
+    }
+}
+
+/// the server App of the Integrated Server thread. the log and the task pools are shared with the client.
+struct IntegratedServerPlugin {
+    world_dir: PathBuf,
+    port: u16,
+    open_to_lan: bool,
+    // username of the player hosting the world
+    host: String,
+    stop_rx: Receiver<()>,
+}
 
 impl Plugin for IntegratedServerPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ServerInfo::default());
-        app.insert_resource(ServerSettings {
-            port: 6000 + rand::thread_rng().gen_range(0..6000),
+        let level = LevelData::load(&self.world_dir);
+        let mut cfg = ServerSettings {
+            port: self.port,
+            bind_addr: Ipv4Addr::LOCALHOST.into(),
+            motd: level.name.clone(),
+            auth_mode: AuthMode::Unsecure, // LAN
+            world_dir: self.world_dir.to_string_lossy().into_owned(),
             ..default()
-        });
+        };
+        if self.open_to_lan {
+            cfg.bind_addr = Ipv4Addr::UNSPECIFIED.into();
+            // LAN players connect by the ip of this machine, the ConnectTokens must include it.
+            cfg.public_addresses.push(SocketAddr::from((Ipv4Addr::LOCALHOST, self.port)));
+            if let Some(ip) = lan::local_ip() {
+                info!("Opened to LAN at {}:{}", ip, self.port);
+                cfg.public_addresses.push(SocketAddr::new(ip, self.port));
+            }
+            match lan::LanAnnouncer::broadcast() {
+                Ok(announcer) => {
+                    app.insert_resource(announcer);
+                    app.add_systems(Update, lan::lan_announce_sys);
+                }
+                Err(err) => warn!("Failed to announce the LAN game: {}", err),
+            }
+        }
+        app.insert_resource(Time::<Fixed>::from_hz(cfg.tps.max(1.) as f64));
+        app.insert_resource(level);
+        app.insert_resource(cfg);
+        app.insert_resource(ServerInfo::default());
+        // the lists of the world. the host is always an operator, LAN guests only by /op.
+        let mut lists = AccessLists::load(&self.world_dir);
+        lists.ops.insert(self.host.to_lowercase());
+        app.insert_resource(lists);
+        app.insert_resource(StopSignal(self.stop_rx.clone()));
+
+        let mut items = Items::default();
+        items.register_all();
+        app.insert_resource(Recipes::load(recipe::RECIPES_DIR, &items));
+        app.insert_resource(items);
+
+        // Commands
+        super::command::init(app);
+
+        // Tick
+        super::tick::init(app);
+        super::level::init(app); // World Time
+        super::item_drop::init(app);
+        super::inventory::init(app);
+        super::mining::init(app);
+        super::game_mode::init(app);
+        super::health::init(app);
+        super::player_data::init(app);
+        super::movement::init(app);
+        super::chunk_tick::init(app);
+        super::fluid::init(app);
 
         // Network
         app.add_plugins(ServerNetworkPlugin);
 
         // ChunkSystem
         app.add_plugins(ServerVoxelPlugin);
+
+        app.add_systems(First, on_stop_signal);
+        app.add_systems(Last, on_exit); // save the world.
+    }
+}
+
+#[derive(Resource)]
+struct StopSignal(Receiver<()>);
+
+/// stop when the client asks, or when the client is gone.
+fn on_stop_signal(stop: Res<StopSignal>, mut exit: EventWriter<AppExit>) {
+    if !matches!(stop.0.try_recv(), Err(crate::channel_impl::TryRecvError::Empty)) {
+        exit.write(AppExit::Success);
+    }
+}
+
+fn on_exit(
+    mut exit_events: EventReader<AppExit>,
+    cfg: Res<ServerSettings>,
+    mut level: ResMut<LevelData>,
+    serverinfo: Res<ServerInfo>,
+    items: Res<Items>,
+    chunk_sys: Res<ServerChunkSystem>,
+    mut server: ResMut<RenetServer>,
+    transport: Option<ResMut<NetcodeServerTransport>>,
+) {
+    if exit_events.is_empty() {
+        return;
+    }
+    exit_events.clear();
+
+    save_world(&cfg.world_dir, &mut level, &serverinfo, &items, &chunk_sys);
+
+    if let Some(mut transport) = transport {
+        transport.disconnect_all(&mut server);
     }
 }
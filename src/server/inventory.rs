@@ -0,0 +1,145 @@
+//! Server-side Player Inventory.
+//!
+//! The inventory is authoritative on the server (`PlayerInfo::inventory`). Clients only send actions,
+//! the server applies them and answers with the changed slots (`SPacket::InventorySlots`),
+//! or the whole content (`SPacket::InventoryContent`) to resync after an invalid action.
+//! Persisted with the `PlayerData`.
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+
+use super::prelude::*;
+use crate::{
+    item::{Inventory, ItemStack, Items},
+    net::{RenetServerHelper, SPacket},
+};
+
+pub fn init(app: &mut App) {
+    app.add_systems(Update, (cmd_give, cmd_clear));
+}
+
+pub fn content_packet(player: &PlayerInfo) -> SPacket {
+    SPacket::InventoryContent {
+        items: player.inventory.items.clone(),
+        cursor: player.cursor,
+        hotbar_index: player.hotbar_index,
+    }
+}
+
+/// send the slots changed since `before`, and the cursor. nothing if unchanged.
+pub fn send_changes(server: &mut RenetServer, player: &PlayerInfo, before: &Inventory, cursor_before: ItemStack) {
+    let slots = player.inventory.changed_slots(before);
+    if slots.is_empty() && player.cursor == cursor_before {
+        return;
+    }
+    server.send_packet(player.client_id, &SPacket::InventorySlots { slots, cursor: player.cursor });
+}
+
+pub fn on_click(server: &mut RenetServer, player: &mut PlayerInfo, slot: u16, secondary: bool, items: &Items) {
+    let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+    if !player.inventory.click(slot as usize, secondary, &mut player.cursor, items) {
+        warn!("Invalid inventory click slot {} from {}", slot, player.username);
+        server.send_packet(player.client_id, &content_packet(player));
+        return;
+    }
+    send_changes(server, player, &before, cursor_before);
+}
+
+pub fn on_transfer(server: &mut RenetServer, player: &mut PlayerInfo, slot: u16, items: &Items) {
+    let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+    if !player.inventory.transfer(slot as usize, items) {
+        warn!("Invalid inventory transfer slot {} from {}", slot, player.username);
+        server.send_packet(player.client_id, &content_packet(player));
+        return;
+    }
+    send_changes(server, player, &before, cursor_before);
+}
+
+pub fn on_hotbar_select(server: &mut RenetServer, player: &mut PlayerInfo, index: u8) {
+    if index as usize >= Inventory::HOTBAR_SLOTS {
+        server.send_packet(player.client_id, &content_packet(player));
+        return;
+    }
+    player.hotbar_index = index;
+}
+
+/// the online player by name, or the sender if no name given.
+fn target_player<'a>(serverinfo: &'a mut ServerInfo, sender: ClientId, name: Option<&str>) -> Option<&'a mut PlayerInfo> {
+    match name {
+        Some(name) => serverinfo.online_players.values_mut().find(|p| p.username.eq_ignore_ascii_case(name)),
+        None => serverinfo.online_players.get_mut(&sender),
+    }
+}
+
+/// /give <player> <item> [count]
+fn cmd_give(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut serverinfo: ResMut<ServerInfo>,
+    lists: Res<AccessLists>,
+    items: Res<Items>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "give") {
+        if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+        let (Some(name), Some(item_name)) = (cmd.arg(1), cmd.arg(2)) else {
+            server.send_packet_chat(cmd.client_id, "Usage: /give <player> <item> [count]".into());
+            continue;
+        };
+        let Some(item_id) = items.id_of(item_name) else {
+            server.send_packet_chat(cmd.client_id, format!("Unknown item: {}", item_name));
+            continue;
+        };
+        let Some(count) = cmd.arg(3).map_or(Some(1), |s| s.parse::<u32>().ok()) else {
+            server.send_packet_chat(cmd.client_id, "Usage: /give <player> <item> [count]".into());
+            continue;
+        };
+        let Some(player) = target_player(&mut serverinfo, cmd.client_id, Some(name)) else {
+            server.send_packet_chat(cmd.client_id, format!("Player {} is not online", name));
+            continue;
+        };
+
+        let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+        let mut remaining = count;
+        while remaining > 0 {
+            let n = remaining.min(items.max_stack(item_id) as u32);
+            let mut stack = items.new_stack(item_id, n as u8);
+            player.inventory.insert(&mut stack, &items);
+            remaining -= n - stack.count as u32;
+            if !stack.is_empty() {
+                break; // full
+            }
+        }
+        send_changes(&mut server, player, &before, cursor_before);
+
+        let reply = format!("Gave {} [{}] to {}", count - remaining, item_name, player.username);
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
+
+/// /clear [player]
+fn cmd_clear(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut serverinfo: ResMut<ServerInfo>,
+    lists: Res<AccessLists>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "clear") {
+        if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) {
+            server.send_packet_chat(cmd.client_id, "You do not have permission to use this command.".into());
+            continue;
+        }
+        let Some(player) = target_player(&mut serverinfo, cmd.client_id, cmd.arg(1)) else {
+            server.send_packet_chat(cmd.client_id, format!("Player {} is not online", cmd.arg(1).unwrap_or_default()));
+            continue;
+        };
+        player.inventory.clear();
+        player.cursor.clear();
+        server.send_packet(player.client_id, &content_packet(player));
+
+        let reply = format!("Cleared the inventory of {}", player.username);
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
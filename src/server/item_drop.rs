@@ -0,0 +1,183 @@
+//! Item Drop Entities.
+//!
+//! Spawned when a voxel is broken, with the block's drop (`VoxTex::drop_item`). Replicated as `EntityType::ItemDrop`.
+//! Simple physics (gravity, landing on voxels), merge with nearby drops of the same item,
+//! despawn after DESPAWN_SECS, and picked up into the inventory of players close enough.
+
+use bevy::{platform::collections::HashSet, prelude::*};
+use bevy_renet::renet::RenetServer;
+use rand::Rng;
+
+use super::{inventory, prelude::*};
+use crate::{
+    item::{ItemStack, Items},
+    net::{
+        replication::{EntityType, Replicated},
+        EntityId, RenetServerHelper, SPacket,
+    },
+    voxel::{ChunkSystem, ServerChunkSystem, VoxShape, VoxTex},
+};
+
+const DESPAWN_SECS: f32 = 300.;
+const PICKUP_DELAY_SECS: f32 = 0.5;
+const PICKUP_RADIUS: f32 = 1.5;
+const MERGE_RADIUS: f32 = 1.0;
+const GRAVITY: f32 = 20.;
+
+/// the item stack of an item drop. replicated.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DroppedItem(pub ItemStack);
+
+#[derive(Component, Default)]
+pub struct ItemDropMotion {
+    pub velocity: Vec3,
+    pub on_ground: bool,
+    // seconds since spawned
+    pub age: f32,
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(FixedUpdate, (tick_item_drops, merge_item_drops, pickup_item_drops).chain().in_set(ServerTickSet));
+}
+
+pub fn spawn_item_drop(cmds: &mut Commands, position: Vec3, stack: ItemStack) -> Entity {
+    let mut rng = rand::rng();
+    cmds.spawn((
+        Transform::from_translation(position),
+        Replicated::new(EntityType::ItemDrop),
+        DroppedItem(stack),
+        ItemDropMotion {
+            velocity: Vec3::new(rng.random_range(-1.0..1.0), 4., rng.random_range(-1.0..1.0)),
+            ..default()
+        },
+    ))
+    .id()
+}
+
+/// drops fall through water and plants.
+fn is_solid(chunk_sys: &ServerChunkSystem, p: Vec3) -> bool {
+    chunk_sys
+        .get_voxel(p.floor().as_ivec3())
+        .is_some_and(|v| !v.is_nil() && v.tex_id != VoxTex::Water && v.shape_id != VoxShape::Grass)
+}
+
+fn tick_item_drops(
+    mut query: Query<(Entity, &mut Transform, &mut ItemDropMotion), With<DroppedItem>>,
+    chunk_sys: Res<ServerChunkSystem>,
+    tick: Res<ServerTick>,
+    mut cmds: Commands,
+) {
+    let dt = 1. / tick.tps;
+    for (entity, mut trans, mut motion) in query.iter_mut() {
+        motion.age += dt;
+        if motion.age > DESPAWN_SECS {
+            cmds.entity(entity).despawn();
+            continue;
+        }
+        let pos = trans.translation;
+        if !chunk_sys.has_chunk(crate::voxel::Chunk::as_chunkpos(pos.floor().as_ivec3())) {
+            continue; // freeze in unloaded chunks
+        }
+
+        motion.on_ground = is_solid(&chunk_sys, pos - Vec3::Y * 0.05);
+        if motion.on_ground {
+            motion.velocity.y = motion.velocity.y.max(0.);
+            motion.velocity.x *= 0.5;
+            motion.velocity.z *= 0.5;
+        } else {
+            motion.velocity.y -= GRAVITY * dt;
+        }
+
+        // move per axis, stop on the solid voxels.
+        let mut next = pos;
+        for axis in 0..3 {
+            let mut p = next;
+            p[axis] += motion.velocity[axis] * dt;
+            if is_solid(&chunk_sys, p) {
+                if axis == 1 && motion.velocity.y < 0. {
+                    p.y = p.y.floor() + 1.; // land on top
+                } else {
+                    p[axis] = next[axis];
+                }
+                motion.velocity[axis] = 0.;
+            }
+            next = p;
+        }
+
+        if next.distance_squared(pos) > 0.0001 * 0.0001 {
+            trans.translation = next;
+        }
+    }
+}
+
+fn merge_item_drops(mut query: Query<(Entity, &Transform, &mut DroppedItem)>, items: Res<Items>, mut cmds: Commands) {
+    let drops: Vec<(Entity, Vec3)> = query.iter().map(|(e, t, _)| (e, t.translation)).collect();
+    let mut merged = HashSet::new();
+
+    for (i, &(a, pos_a)) in drops.iter().enumerate() {
+        for &(b, pos_b) in &drops[i + 1..] {
+            if merged.contains(&a) || merged.contains(&b) || pos_a.distance_squared(pos_b) > MERGE_RADIUS * MERGE_RADIUS {
+                continue;
+            }
+            let Ok([(_, _, mut item_a), (_, _, mut item_b)]) = query.get_many_mut([a, b]) else {
+                continue;
+            };
+            let max_stack = items.max_stack(item_a.0.item_id);
+            if !item_a.0.stacks_with(&item_b.0) || item_a.0.count >= max_stack {
+                continue;
+            }
+            let mut stack = item_b.0;
+            item_a.0.merge(&mut stack, max_stack);
+            item_b.0 = stack;
+            if stack.is_empty() {
+                cmds.entity(b).despawn();
+                merged.insert(b);
+            }
+        }
+    }
+}
+
+fn pickup_item_drops(
+    mut query: Query<(Entity, &Transform, &mut DroppedItem, &ItemDropMotion)>,
+    mut serverinfo: ResMut<ServerInfo>,
+    mut server: ResMut<RenetServer>,
+    items: Res<Items>,
+    mut cmds: Commands,
+) {
+    for (entity, trans, mut item, motion) in query.iter_mut() {
+        if motion.age < PICKUP_DELAY_SECS {
+            continue;
+        }
+        let Some(player) = serverinfo
+            .online_players
+            .values_mut()
+            .find(|p| p.is_alive() && p.game_mode.can_interact() && p.position.distance_squared(trans.translation) < PICKUP_RADIUS * PICKUP_RADIUS)
+        else {
+            continue;
+        };
+
+        // the remaining stays on the ground if the inventory is full.
+        let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+        let mut stack = item.0;
+        player.inventory.insert(&mut stack, &items);
+        if stack == item.0 {
+            continue;
+        }
+        inventory::send_changes(&mut server, player, &before, cursor_before);
+
+        let mut picked = item.0;
+        picked.count -= stack.count;
+        server.send_packet(
+            player.client_id,
+            &SPacket::PickupItem {
+                entity_id: EntityId::from_server(entity),
+                stack: picked,
+            },
+        );
+        if stack.is_empty() {
+            cmds.entity(entity).despawn();
+        } else {
+            item.0 = stack;
+        }
+    }
+}
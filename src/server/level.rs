@@ -0,0 +1,222 @@
+//! Server World Level: world time and game rules.
+//!
+//! The server owns the world time. it advances on the server tick and is synced to clients by `SPacket::WorldTime`
+//! on login, on change and periodically. clients only advance it locally between syncs.
+//! Persisted as level.json in the world directory, which is also the metadata of local worlds. see client::saves
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use super::prelude::*;
+use crate::{
+    net::{RenetServerHelper, SPacket},
+    util::current_timestamp_millis,
+};
+
+const LEVEL_FILE: &str = "level.json";
+
+/// ticks between periodic WorldTime syncs.
+const WORLD_TIME_SYNC_TICKS: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GameRules {
+    // advance the daytime. otherwise the time stays.
+    pub do_daylight_cycle: bool,
+    // random ticked voxels per chunk per tick. 0 disables. see server::chunk_tick
+    pub random_tick_speed: u32,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            do_daylight_cycle: true,
+            random_tick_speed: 3,
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LevelData {
+    pub name: String,
+    pub seed: u64,
+
+    // [0, 1). 0: sunrise, 0.25: noon, 0.5: sunset, 0.75: midnight
+    pub daytime: f32,
+    // seconds a day time long
+    pub daytime_length: f32,
+
+    // seconds
+    pub time_inhabited: f64,
+    // unix millis
+    pub time_created: u64,
+    pub time_modified: u64,
+
+    pub game_rules: GameRules,
+    // of new players
+    pub game_mode: GameMode,
+    // where players respawn. set by /setworldspawn
+    pub spawn_point: Vec3,
+}
+
+impl Default for LevelData {
+    fn default() -> Self {
+        Self {
+            name: "world".into(),
+            seed: 0,
+            daytime: 0.15,
+            daytime_length: 60. * 24.,
+            time_inhabited: 0.,
+            time_created: 0,
+            time_modified: 0,
+            game_rules: GameRules::default(),
+            game_mode: GameMode::default(),
+            spawn_point: Vec3::ZERO,
+        }
+    }
+}
+
+impl LevelData {
+    pub fn load(world_dir: impl AsRef<Path>) -> Self {
+        let file = world_dir.as_ref().join(LEVEL_FILE);
+        match std::fs::read_to_string(&file) {
+            Ok(str) => serde_json::from_str(&str).unwrap_or_else(|err| {
+                error!("Failed to parse {}: {err}", file.display());
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// save, and update the modified time. errors are logged, see `try_save`.
+    pub fn save(&mut self, world_dir: impl AsRef<Path>) {
+        if let Err(err) = self.try_save(&world_dir) {
+            error!("Failed to save {}: {err}", world_dir.as_ref().join(LEVEL_FILE).display());
+        }
+    }
+
+    /// save, and update the modified time.
+    pub fn try_save(&mut self, world_dir: impl AsRef<Path>) -> std::io::Result<()> {
+        self.time_modified = current_timestamp_millis();
+        if self.time_created == 0 {
+            self.time_created = self.time_modified;
+        }
+        std::fs::create_dir_all(world_dir.as_ref())?;
+        std::fs::write(world_dir.as_ref().join(LEVEL_FILE), serde_json::to_string_pretty(self).unwrap())
+    }
+
+    /// the WorldTime packet. the day length is sent as 0 if the cycle is off, so clients stop advancing.
+    pub fn world_time_packet(&self) -> SPacket {
+        SPacket::WorldTime {
+            daytime: self.daytime,
+            daytime_length: if self.game_rules.do_daylight_cycle { self.daytime_length } else { 0. },
+        }
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(FixedUpdate, tick_world_time.in_set(ServerTickSet));
+    app.add_systems(Update, (cmd_time, cmd_gamerule));
+}
+
+fn tick_world_time(mut level: ResMut<LevelData>, tick: Res<ServerTick>, mut server: ResMut<RenetServer>) {
+    let dt_sec = 1. / tick.tps;
+    level.time_inhabited += dt_sec as f64;
+
+    if level.game_rules.do_daylight_cycle && level.daytime_length != 0. {
+        level.daytime = (level.daytime + dt_sec / level.daytime_length).fract();
+    }
+
+    if tick.tick % WORLD_TIME_SYNC_TICKS == 0 {
+        server.broadcast_packet(&level.world_time_packet());
+    }
+}
+
+fn parse_daytime(s: &str) -> Option<f32> {
+    Some(match s {
+        "sunrise" => 0.,
+        "day" => 0.1,
+        "noon" => 0.25,
+        "sunset" => 0.5,
+        "night" => 0.6,
+        "midnight" => 0.75,
+        _ => s.parse().ok()?,
+    })
+}
+
+/// /time set <daytime|day|noon|night|..>, /time add <daytime>, /time query
+fn cmd_time(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut level: ResMut<LevelData>,
+    serverinfo: Res<ServerInfo>,
+    lists: Res<AccessLists>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "time") {
+        let reply = match (cmd.arg(1), cmd.arg(2).and_then(parse_daytime)) {
+            (Some("query") | None, _) => {
+                format!("The time is {:.4} (day length {}s)", level.daytime, level.daytime_length)
+            }
+            (Some("set" | "add"), _) if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) => {
+                "You do not have permission to use this command.".to_string()
+            }
+            (Some("set"), Some(daytime)) => {
+                level.daytime = daytime.rem_euclid(1.);
+                server.broadcast_packet(&level.world_time_packet());
+                format!("Set the time to {:.4}", level.daytime)
+            }
+            (Some("add"), Some(amount)) => {
+                level.daytime = (level.daytime + amount).rem_euclid(1.);
+                server.broadcast_packet(&level.world_time_packet());
+                format!("Set the time to {:.4}", level.daytime)
+            }
+            _ => "Usage: /time <set|add|query> [daytime]".to_string(),
+        };
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
+
+/// /gamerule [rule] [value]
+fn cmd_gamerule(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut level: ResMut<LevelData>,
+    serverinfo: Res<ServerInfo>,
+    lists: Res<AccessLists>,
+) {
+    for cmd in cmds.read().filter(|c| c.name() == "gamerule") {
+        let reply = match (cmd.arg(1), cmd.arg(2)) {
+            (None, _) => "Game rules: doDaylightCycle, randomTickSpeed".to_string(),
+            (Some("doDaylightCycle"), None) => {
+                format!("Gamerule doDaylightCycle is currently set to: {}", level.game_rules.do_daylight_cycle)
+            }
+            (Some("randomTickSpeed"), None) => {
+                format!("Gamerule randomTickSpeed is currently set to: {}", level.game_rules.random_tick_speed)
+            }
+            (Some(_), Some(_)) if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) => {
+                "You do not have permission to use this command.".to_string()
+            }
+            (Some("doDaylightCycle"), Some(value)) => match value.parse::<bool>() {
+                Ok(value) => {
+                    level.game_rules.do_daylight_cycle = value;
+                    server.broadcast_packet(&level.world_time_packet());
+                    format!("Gamerule doDaylightCycle is now set to: {}", value)
+                }
+                Err(_) => "Usage: /gamerule doDaylightCycle <true|false>".to_string(),
+            },
+            (Some("randomTickSpeed"), Some(value)) => match value.parse::<u32>() {
+                Ok(value) => {
+                    level.game_rules.random_tick_speed = value;
+                    format!("Gamerule randomTickSpeed is now set to: {}", value)
+                }
+                Err(_) => "Usage: /gamerule randomTickSpeed <number>".to_string(),
+            },
+            (Some(rule), _) => format!("Unknown game rule: {}", rule),
+        };
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
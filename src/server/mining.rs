@@ -0,0 +1,174 @@
+//! Server-side Mining (hold-to-break).
+//!
+//! The client sends `MiningAction::Start` when it begins holding attack on a voxel, and `Finish` when its local progress completes.
+//! The server checks the time elapsed against the break time of the voxel with the held tool (`VoxTex::break_time`),
+//! then breaks it and wears the tool. Rejected finishes are reverted on the client by a ChunkModify of the actual voxel.
+//! The crack stage is broadcast to the other players by `SPacket::BreakProgress`.
+//! Creative players break instantly by `CPacket::BreakVoxels` instead, spectators can't break at all.
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+
+use super::{inventory, prelude::*};
+use crate::{
+    item::{ItemId, Items, ToolType},
+    net::{MiningAction, RenetServerHelper, SPacket, BREAK_STAGES},
+    voxel::{break_voxel, current_cells, send_chunk_modify, ChunkSystem, ServerChunkSystem, VoxTex, BREAK_REACH},
+};
+
+/// the part of the break time that must elapse on the server. the rest is tolerated for latency jitter.
+const MINING_TOLERANCE: f64 = 0.9;
+
+/// the voxel a player is mining.
+#[derive(Debug, Clone, Copy)]
+pub struct MiningState {
+    pub pos: IVec3,
+    pub start_tick: u64,
+    // ticks to break it with the held tool
+    pub break_ticks: u64,
+    // the hotbar slot and item of the tool held at the start, worn at the finish. None by hand.
+    pub tool: Option<(u8, ItemId)>,
+    // the last broadcast crack stage
+    pub stage: i8,
+}
+
+#[derive(Event)]
+pub struct MiningEvent {
+    pub client_id: ClientId,
+    pub action: MiningAction,
+}
+
+pub fn init(app: &mut App) {
+    app.add_event::<MiningEvent>();
+
+    app.add_systems(Update, on_mining_events);
+    app.add_systems(FixedUpdate, broadcast_mining_progress.in_set(ServerTickSet));
+}
+
+/// the tool type of the stack in the selected hotbar slot.
+pub fn held_tool(player: &PlayerInfo, items: &Items) -> Option<ToolType> {
+    let stack = player.inventory.items.get(player.hotbar_index as usize)?;
+    items.def(stack.item_id).filter(|_| !stack.is_empty())?.tool
+}
+
+fn broadcast_stop(server: &mut RenetServer, player: &PlayerInfo, pos: IVec3) {
+    server.broadcast_packet_except(
+        player.client_id,
+        &SPacket::BreakProgress {
+            entity_id: player.entity_id,
+            pos,
+            stage: -1,
+        },
+    );
+}
+
+fn on_mining_events(
+    mut events: EventReader<MiningEvent>,
+    mut serverinfo: ResMut<ServerInfo>,
+    chunk_sys: Res<ServerChunkSystem>,
+    items: Res<Items>,
+    tick: Res<ServerTick>,
+    mut server: ResMut<RenetServer>,
+    mut updates: ResMut<ScheduledUpdates>,
+    mut cmds: Commands,
+) {
+    // broken cells to send after the events, the players are borrowed meanwhile. (chunkpos, cell, breaker)
+    let mut modified = Vec::new();
+
+    for e in events.read() {
+        let Some(player) = serverinfo.online_players.get_mut(&e.client_id) else {
+            continue;
+        };
+        match e.action {
+            MiningAction::Start { pos } => {
+                if let Some(prev) = player.mining.take() {
+                    broadcast_stop(&mut server, player, prev.pos);
+                }
+                if !player.is_alive() || !player.game_mode.can_interact() || pos.as_vec3().distance(player.position) > BREAK_REACH {
+                    continue;
+                }
+                let tool = held_tool(player, &items);
+                let Some(break_time) = chunk_sys.get_voxel(pos).and_then(|v| VoxTex::break_time(v.tex_id, tool)) else {
+                    continue;
+                };
+                let slot = player.hotbar_index;
+                player.mining = Some(MiningState {
+                    pos,
+                    start_tick: tick.tick,
+                    break_ticks: (break_time * tick.tps).ceil() as u64,
+                    tool: tool.map(|_| (slot, player.inventory.items[slot as usize].item_id)),
+                    stage: -1,
+                });
+            }
+            MiningAction::Abort => {
+                if let Some(prev) = player.mining.take() {
+                    broadcast_stop(&mut server, player, prev.pos);
+                }
+            }
+            MiningAction::Finish { pos } => {
+                let mining = player.mining.take();
+                if let Some(prev) = mining {
+                    broadcast_stop(&mut server, player, prev.pos);
+                }
+                let valid = player.game_mode.instant_break()
+                    || mining.is_some_and(|m| m.pos == pos && (tick.tick - m.start_tick) as f64 >= m.break_ticks as f64 * MINING_TOLERANCE);
+
+                let cell = if valid && player.is_alive() && player.game_mode.can_interact() && pos.as_vec3().distance(player.position) <= BREAK_REACH {
+                    break_voxel(&chunk_sys, pos, !player.game_mode.infinite_items(), &items, &mut cmds)
+                } else {
+                    None
+                };
+                let Some(cell) = cell else {
+                    warn!("Rejected mining finish at {} from {}", pos, player.username);
+                    for (chunkpos, voxel) in current_cells(&chunk_sys, &[pos]) {
+                        server.send_packet(e.client_id, &SPacket::ChunkModify { chunkpos, voxel });
+                    }
+                    continue;
+                };
+
+                updates.notify_changed(pos);
+
+                modified.push((crate::voxel::Chunk::as_chunkpos(pos), cell, e.client_id));
+
+                // wear the tool it was mined with, if it's still there.
+                let tool = mining
+                    .and_then(|m| m.tool)
+                    .filter(|&(slot, item_id)| player.inventory.items.get(slot as usize).is_some_and(|s| !s.is_empty() && s.item_id == item_id));
+                if let Some((slot, _)) = tool.filter(|_| !player.game_mode.infinite_items()) {
+                    let (before, cursor_before) = (player.inventory.clone(), player.cursor);
+                    if player.inventory.items[slot as usize].damage(1) {
+                        debug!("{}'s tool broke", player.username);
+                    }
+                    inventory::send_changes(&mut server, player, &before, cursor_before);
+                }
+            }
+        }
+    }
+
+    // the breaker has applied it locally.
+    for (chunkpos, cell, breaker) in modified {
+        send_chunk_modify(&mut server, &serverinfo, chunkpos, vec![cell], Some(breaker));
+    }
+}
+
+/// broadcast the crack stage of the mining players, when it changes.
+fn broadcast_mining_progress(mut serverinfo: ResMut<ServerInfo>, tick: Res<ServerTick>, mut server: ResMut<RenetServer>) {
+    for player in serverinfo.online_players.values_mut() {
+        let Some(mining) = player.mining.as_mut() else {
+            continue;
+        };
+        let elapsed = tick.tick - mining.start_tick;
+        let stage = (elapsed * BREAK_STAGES / mining.break_ticks.max(1)).min(BREAK_STAGES - 1) as i8;
+        if stage == mining.stage {
+            continue;
+        }
+        mining.stage = stage;
+
+        let packet = SPacket::BreakProgress {
+            entity_id: player.entity_id,
+            pos: mining.pos,
+            stage,
+        };
+        server.broadcast_packet_except(player.client_id, &packet);
+    }
+}
@@ -1,8 +1,29 @@
 pub mod dedicated_server;
+pub mod item_drop;
 
+pub mod access_list;
+pub mod chunk_tick;
+pub mod command;
+pub mod crafting;
+pub mod fluid;
+pub mod game_mode;
+pub mod health;
 mod integrated_server;
+pub mod inventory;
+pub mod level;
+pub mod mining;
+pub mod movement;
+pub mod player_data;
+pub mod tick;
 
 pub mod prelude {
-    pub use super::dedicated_server::{DedicatedServerPlugin, PlayerInfo, ServerInfo, ServerSettings};
-    pub use super::integrated_server::IntegratedServerPlugin;
+    pub use super::access_list::AccessLists;
+    pub use super::chunk_tick::{BlockBehaviors, ScheduledUpdates};
+    pub use super::command::ServerCommand;
+    pub use super::game_mode::GameMode;
+    pub use super::dedicated_server::{ClientConnection, DedicatedServerPlugin, PlayerInfo, ServerInfo, ServerSettings};
+    pub use super::integrated_server::IntegratedServer;
+    pub use super::level::{GameRules, LevelData};
+    pub use super::player_data::PlayerData;
+    pub use super::tick::{ServerTick, ServerTickSet};
 }
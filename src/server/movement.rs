@@ -0,0 +1,208 @@
+//! Movement Validation.
+//!
+//! `CPacket::PlayerPos` is checked before it's accepted: the moved distance is spent from a budget that accrues
+//! the max speed of the game mode over the real elapsed time, and the player can't move into solid voxels (except noclip).
+//! A rejected move teleports the client back by `SPacket::PlayerCorrection`, and counts a violation.
+//! Violations decay over time, the player is kicked when they pile up to MAX_VIOLATIONS.
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+
+use super::prelude::*;
+use crate::{
+    net::{RenetServerHelper, SPacket},
+    util::current_timestamp_millis,
+    voxel::{ChunkSystem, ServerChunkSystem, Vox, VoxShape, VoxTex},
+};
+
+pub const MAX_VIOLATIONS: u32 = 10;
+
+/// seconds per forgiven violation.
+const VIOLATION_DECAY_SECS: f32 = 5.;
+
+/// extra distance allowed for the network jitter and the client/server clock differences. granted once, not per packet.
+const MOVE_TOLERANCE: f32 = 1.5;
+
+/// falling is faster than walking. about the terminal velocity.
+const MAX_FALL_SPEED: f32 = 80.;
+
+/// the budget saves up this much time of movement at most, so that a long silence doesn't allow a teleport.
+const MAX_MOVE_SECS: f32 = 1.;
+
+/// resend the correction if the client hasn't arrived at it in this time.
+const CORRECTION_TIMEOUT_MILLIS: u64 = 2000;
+
+/// isosurface voxels are solid when deeper than this. the smooth surface is walkable around 0.
+const SOLID_ISOVALUE: f32 = 0.5;
+
+/// the distances the player may still move, horizontally, up and down.
+/// accrues `speed * elapsed` on every PlayerPos, and spends the moved distance. starts with the MOVE_TOLERANCE.
+#[derive(Clone, Copy)]
+pub struct MoveBudget {
+    pub horizontal: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+impl Default for MoveBudget {
+    fn default() -> Self {
+        Self {
+            horizontal: MOVE_TOLERANCE,
+            up: MOVE_TOLERANCE,
+            down: MOVE_TOLERANCE,
+        }
+    }
+}
+
+impl MoveBudget {
+    fn accrue(&mut self, dt_sec: f32, max_speed: f32, max_fall: f32) {
+        let accrue = |budget: &mut f32, speed: f32| *budget = (*budget + speed * dt_sec).min(speed * MAX_MOVE_SECS + MOVE_TOLERANCE);
+        accrue(&mut self.horizontal, max_speed);
+        accrue(&mut self.up, max_speed);
+        accrue(&mut self.down, max_fall);
+    }
+
+    /// false if overspent.
+    fn spend(&mut self, delta: Vec3) -> bool {
+        self.horizontal -= delta.xz().length();
+        self.up -= delta.y.max(0.);
+        self.down -= (-delta.y).max(0.);
+        self.horizontal >= 0. && self.up >= 0. && self.down >= 0.
+    }
+}
+
+/// the player position the client must arrive at, before its PlayerPos are accepted again.
+#[derive(Clone, Copy)]
+pub struct PendingCorrection {
+    pub position: Vec3,
+    pub sent_at: u64,
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(FixedUpdate, tick_violations.in_set(ServerTickSet));
+}
+
+fn is_solid(vox: &Vox) -> bool {
+    if vox.is_nil() || vox.tex_id == VoxTex::Water {
+        return false;
+    }
+    match vox.shape_id {
+        VoxShape::Cube => true,
+        VoxShape::Isosurface => vox.isovalue() > SOLID_ISOVALUE,
+        _ => false,
+    }
+}
+
+/// the server moved the player (login, respawn), ignore the moves of the client until it arrives.
+pub fn expect_position(player: &mut PlayerInfo, position: Vec3) {
+    player.position = position;
+    player.move_budget = MoveBudget::default();
+    player.correction = Some(PendingCorrection {
+        position,
+        sent_at: current_timestamp_millis(),
+    });
+}
+
+/// teleport the client back to the position.
+pub fn correct_position(server: &mut RenetServer, player: &mut PlayerInfo, position: Vec3) {
+    expect_position(player, position);
+    server.send_packet(player.client_id, &SPacket::PlayerCorrection { position });
+}
+
+/// validate and accept the moved position of the player.
+pub fn on_player_pos(server: &mut RenetServer, player: &mut PlayerInfo, position: Vec3, yaw: f32, chunk_sys: &ServerChunkSystem) {
+    let now = current_timestamp_millis();
+    if !position.is_finite() || !yaw.is_finite() {
+        player.move_violations += 1;
+        warn!("{} sent an invalid position {} ({}/{})", player.username, position, player.move_violations, MAX_VIOLATIONS);
+        return;
+    }
+
+    // moves sent before the correction arrived are stale.
+    if let Some(correction) = player.correction {
+        if position.distance(correction.position) > MOVE_TOLERANCE {
+            if now.saturating_sub(correction.sent_at) > CORRECTION_TIMEOUT_MILLIS {
+                player.move_violations += 1;
+                warn!("{} ignored the position correction ({}/{})", player.username, player.move_violations, MAX_VIOLATIONS);
+                correct_position(server, player, correction.position);
+            }
+            return;
+        }
+        player.correction = None;
+        player.last_move_time = now;
+    }
+
+    // the real elapsed time, no per-packet floor. the budget caps the saved up time.
+    let dt_sec = now.saturating_sub(player.last_move_time) as f32 / 1000.;
+    player.last_move_time = now;
+
+    let delta = position - player.position;
+    let max_speed = player.game_mode.max_speed();
+    let max_fall = if player.game_mode.can_fly() { max_speed } else { MAX_FALL_SPEED };
+
+    player.move_budget.accrue(dt_sec, max_speed, max_fall);
+    let too_fast = !player.move_budget.spend(delta);
+    let into_solid = !player.game_mode.noclip() && chunk_sys.get_voxel(position.floor().as_ivec3()).is_some_and(is_solid);
+
+    if too_fast || into_solid {
+        player.move_violations += 1;
+        let reason = if too_fast { "moved too fast" } else { "moved into a solid voxel" };
+        warn!(
+            "{} {}: {} -> {} ({}/{})",
+            player.username, reason, player.position, position, player.move_violations, MAX_VIOLATIONS
+        );
+        let back = player.position;
+        correct_position(server, player, back);
+        return;
+    }
+
+    player.position = position;
+    player.yaw = yaw;
+}
+
+/// forgive violations over time, and kick the players exceeded.
+fn tick_violations(mut serverinfo: ResMut<ServerInfo>, tick: Res<ServerTick>, mut server: ResMut<RenetServer>) {
+    let decay_ticks = (VIOLATION_DECAY_SECS * tick.tps).round().max(1.) as u64;
+    let decay = tick.tick % decay_ticks == 0;
+
+    let mut kicks = Vec::new();
+    for player in serverinfo.online_players.values_mut() {
+        if decay {
+            player.move_violations = player.move_violations.saturating_sub(1);
+        }
+        if player.move_violations >= MAX_VIOLATIONS {
+            kicks.push(player.client_id);
+        }
+    }
+    for client_id in kicks {
+        if serverinfo.connections.get(&client_id).is_some_and(|c| c.disconnect_at.is_none()) {
+            warn!("Kicking {} for illegal movement", serverinfo.online_players[&client_id].username);
+            serverinfo.kick(&mut server, client_id, "Kicked for illegal movement".into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_tolerance_granted_once() {
+        let (speed, dt) = (5.0, 1.0 / 60.0);
+
+        // at the max speed for 10s, never overspent.
+        let mut budget = MoveBudget::default();
+        for _ in 0..600 {
+            budget.accrue(dt, speed, speed);
+            assert!(budget.spend(Vec3::X * speed * dt));
+        }
+
+        // 20% faster at 60 Hz: the tolerance runs out in a few seconds, instead of per packet.
+        let mut budget = MoveBudget::default();
+        let overspent = (0..600).position(|_| {
+            budget.accrue(dt, speed, speed);
+            !budget.spend(Vec3::X * speed * 1.2 * dt)
+        });
+        assert!(overspent.is_some_and(|i| i < 120));
+    }
+}
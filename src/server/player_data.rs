@@ -0,0 +1,140 @@
+//! Per-player save data, in `<world_dir>/playerdata/<user_id>.json`.
+//! Loaded on login, saved on logout, every AUTOSAVE_SECS with the level and the modified chunks, and on server exit.
+
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::prelude::*;
+use crate::{
+    item::{Inventory, ItemStack, Items},
+    voxel::ServerChunkSystem,
+};
+
+const PLAYERDATA_DIR: &str = "playerdata";
+
+/// seconds between saving the world: the level, the online players and the modified chunks.
+const AUTOSAVE_SECS: f32 = 60.;
+
+/// a non-empty inventory slot. saved by the item str id, so it survives item num id changes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedStack {
+    pub slot: u16,
+    pub item: String,
+    pub count: u8,
+    #[serde(default)]
+    pub durability: u16,
+    #[serde(default)]
+    pub meta: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlayerData {
+    pub inventory: Vec<SavedStack>,
+    pub hotbar_index: u8,
+    // None: the level default
+    pub game_mode: Option<GameMode>,
+    // None: the world spawn point
+    pub position: Option<Vec3>,
+    // None: full health
+    pub health: Option<u32>,
+}
+
+pub fn init(app: &mut App) {
+    app.add_systems(FixedUpdate, autosave_world.in_set(ServerTickSet));
+}
+
+fn autosave_world(
+    serverinfo: Res<ServerInfo>,
+    cfg: Res<ServerSettings>,
+    items: Res<Items>,
+    tick: Res<ServerTick>,
+    mut level: ResMut<LevelData>,
+    chunk_sys: Res<ServerChunkSystem>,
+) {
+    let autosave_ticks = (AUTOSAVE_SECS * tick.tps).round().max(1.) as u64;
+    if tick.tick == 0 || tick.tick % autosave_ticks != 0 {
+        return;
+    }
+    debug!("Autosaving the world, {} players", serverinfo.online_players.len());
+    super::dedicated_server::save_world(&cfg.world_dir, &mut level, &serverinfo, &items, &chunk_sys);
+}
+
+impl PlayerData {
+    fn file(world_dir: impl AsRef<Path>, user_id: u64) -> PathBuf {
+        world_dir.as_ref().join(PLAYERDATA_DIR).join(format!("{user_id}.json"))
+    }
+
+    /// the saved data of the player, or default for new players.
+    pub fn load(world_dir: impl AsRef<Path>, user_id: u64) -> Self {
+        let file = Self::file(world_dir, user_id);
+        match std::fs::read_to_string(&file) {
+            Ok(str) => serde_json::from_str(&str).unwrap_or_else(|err| {
+                error!("Failed to parse {}: {err}", file.display());
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, world_dir: impl AsRef<Path>, user_id: u64) {
+        let file = Self::file(world_dir, user_id);
+        let result = std::fs::create_dir_all(file.parent().unwrap()).and_then(|_| std::fs::write(&file, serde_json::to_string_pretty(self).unwrap()));
+        if let Err(err) = result {
+            error!("Failed to save {}: {err}", file.display());
+        }
+    }
+
+    /// the data to save of an online player. the cursor stack and the crafting grid aren't saved, they're put back
+    /// into the saved inventory as far as they fit. the player keeps them, on logout see `crafting::return_held_items`.
+    pub fn from_player(player: &PlayerInfo, items: &Items) -> Self {
+        let mut inventory = player.inventory.clone();
+        for &stack in std::iter::once(&player.cursor).chain(player.crafting_grid.items.iter()) {
+            let mut stack = stack;
+            inventory.insert(&mut stack, items);
+        }
+
+        let inventory = inventory
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, stack)| !stack.is_empty())
+            .filter_map(|(slot, stack)| {
+                Some(SavedStack {
+                    slot: slot as u16,
+                    item: items.str_id(stack.item_id)?.clone(),
+                    count: stack.count,
+                    durability: stack.durability,
+                    meta: stack.meta,
+                })
+            })
+            .collect();
+        Self {
+            inventory,
+            hotbar_index: player.hotbar_index,
+            game_mode: Some(player.game_mode),
+            position: Some(player.position),
+            health: Some(player.health),
+        }
+    }
+
+    /// the saved inventory. unknown items (e.g. removed) are dropped with a warning.
+    pub fn inventory(&self, items: &Items) -> Inventory {
+        let mut inventory = Inventory::new(Inventory::PLAYER_SLOTS);
+        for saved in &self.inventory {
+            let (Some(item_id), Some(slot)) = (items.id_of(&saved.item), inventory.items.get_mut(saved.slot as usize)) else {
+                warn!("Dropped unknown saved item {} in slot {}", saved.item, saved.slot);
+                continue;
+            };
+            *slot = ItemStack {
+                count: saved.count,
+                item_id,
+                durability: saved.durability,
+                meta: saved.meta,
+            };
+        }
+        inventory
+    }
+}
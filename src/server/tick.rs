@@ -0,0 +1,168 @@
+//! Fixed-rate Server Tick.
+//!
+//! The simulation (chunk loading, entity updates, world time) runs in `FixedUpdate` in the `ServerTickSet`, at `ServerSettings::tps`.
+//! Both the dedicated and the integrated server set the fixed timestep of their App to the tps, the integrated server
+//! runs its own App on a separate thread (see integrated_server). The ticks are still gated by an accumulator,
+//! at most once per fixed step, so they keep the tps if it differs from the timestep.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+
+use super::prelude::*;
+use crate::net::RenetServerHelper;
+
+/// Systems of the server simulation. only run on a server tick, not while frozen.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerTickSet;
+
+#[derive(Resource)]
+pub struct ServerTick {
+    // ticks since the server start.
+    pub tick: u64,
+
+    // target ticks per second.
+    pub tps: f32,
+
+    // /tick freeze. while paused, only run paused_steps ticks.
+    pub is_paused: bool,
+    pub paused_steps: u32,
+
+    // measured
+    pub mspt: f32, // average milliseconds per tick
+    pub measured_tps: f32,
+
+    accum: f32,
+    should_tick: bool,
+    tick_start: Option<Instant>,
+
+    window_start: Option<Instant>,
+    window_ticks: u32,
+}
+
+impl Default for ServerTick {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            tps: 20.,
+            is_paused: false,
+            paused_steps: 0,
+            mspt: 0.,
+            measured_tps: 0.,
+            accum: 0.,
+            should_tick: false,
+            tick_start: None,
+            window_start: None,
+            window_ticks: 0,
+        }
+    }
+}
+
+impl ServerTick {
+    pub fn tick_rate(&self) -> u32 {
+        self.tps.round() as u32
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "TPS: {:.1} (target {}), MSPT: {:.2}ms{}",
+            self.measured_tps,
+            self.tps,
+            self.mspt,
+            if self.is_paused { ", frozen" } else { "" }
+        )
+    }
+}
+
+pub fn init(app: &mut App) {
+    app.insert_resource(ServerTick::default());
+
+    app.configure_sets(FixedUpdate, ServerTickSet.run_if(|tick: Res<ServerTick>| tick.should_tick));
+    app.add_systems(FixedFirst, tick_begin);
+    app.add_systems(FixedLast, tick_end);
+
+    app.add_systems(Update, cmd_tick);
+}
+
+fn tick_begin(mut tick: ResMut<ServerTick>, cfg: Res<ServerSettings>, time: Res<Time<Fixed>>) {
+    tick.tps = cfg.tps.max(1.);
+    let tick_secs = 1. / tick.tps;
+
+    // at most one tick per fixed step, drop the backlog instead of spiraling.
+    tick.accum = (tick.accum + time.delta_secs()).min(tick_secs * 2.);
+    tick.should_tick = false;
+    if tick.accum < tick_secs - 0.0001 {
+        return;
+    }
+    tick.accum -= tick_secs;
+
+    if tick.is_paused {
+        if tick.paused_steps == 0 {
+            return;
+        }
+        tick.paused_steps -= 1;
+    }
+    tick.should_tick = true;
+    tick.tick += 1;
+    tick.tick_start = Some(Instant::now());
+}
+
+fn tick_end(mut tick: ResMut<ServerTick>) {
+    let Some(tick_start) = tick.tick_start.take() else {
+        return;
+    };
+    let now = Instant::now();
+    let ms = (now - tick_start).as_secs_f32() * 1000.;
+    tick.mspt = if tick.mspt == 0. { ms } else { tick.mspt * 0.95 + ms * 0.05 };
+
+    // ticks in the last second
+    let window_start = *tick.window_start.get_or_insert(now);
+    tick.window_ticks += 1;
+    let elapsed = now - window_start;
+    if elapsed >= Duration::from_secs(1) {
+        tick.measured_tps = tick.window_ticks as f32 / elapsed.as_secs_f32();
+        tick.window_ticks = 0;
+        tick.window_start = Some(now);
+    }
+}
+
+/// /tps, /tick freeze|unfreeze|step [n]
+fn cmd_tick(
+    mut cmds: EventReader<ServerCommand>,
+    mut server: ResMut<RenetServer>,
+    mut tick: ResMut<ServerTick>,
+    serverinfo: Res<ServerInfo>,
+    lists: Res<AccessLists>,
+) {
+    for cmd in cmds.read() {
+        let reply = match (cmd.name(), cmd.arg(1)) {
+            ("tps", _) => tick.summary(),
+            ("tick", _) if !serverinfo.online_players.get(&cmd.client_id).is_some_and(|p| lists.is_op(&p.username)) => {
+                "You do not have permission to use this command.".to_string()
+            }
+            ("tick", Some("freeze")) => {
+                tick.is_paused = true;
+                tick.paused_steps = 0;
+                "The game is frozen".to_string()
+            }
+            ("tick", Some("unfreeze")) => {
+                tick.is_paused = false;
+                "The game is unfrozen".to_string()
+            }
+            ("tick", Some("step")) => {
+                if !tick.is_paused {
+                    "The game is not frozen, /tick freeze first".to_string()
+                } else if let Some(n) = cmd.arg(2).map_or(Some(1), |s| s.parse::<u32>().ok()) {
+                    tick.paused_steps += n;
+                    format!("Stepping {} tick(s)", n)
+                } else {
+                    "Usage: /tick step [n]".to_string()
+                }
+            }
+            ("tick", _) => "Usage: /tick <freeze|unfreeze|step [n]>".to_string(),
+            _ => continue,
+        };
+        server.send_packet_chat(cmd.client_id, reply);
+    }
+}
@@ -96,6 +96,16 @@ pub fn hashcode<T: Hash>(t: &T) -> u64 {
     s.finish()
 }
 
+/// 64-bit FNV-1a. unlike `hashcode`, stable across builds and Rust versions, for persisted ids.
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
 // Iter
 
 use std::sync::Arc;
@@ -217,6 +227,18 @@ pub fn http_get_json<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
     Ok(serde_json::from_value(client.get(url).send()?.json()?)?)
 }
 
+pub fn http_get_bytes(url: &str) -> anyhow::Result<Vec<u8>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let resp = client.get(url).send()?.error_for_status()?;
+    Ok(resp.bytes()?.to_vec())
+}
+
+/// the percent-decoded value of the query parameter of a request path, e.g. "/op?username=a%20b".
+pub fn query_param(path: &str, key: &str) -> Option<String> {
+    let url = reqwest::Url::parse("http://localhost").ok()?.join(path).ok()?;
+    url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned())
+}
+
 pub async fn http_get_json_async<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
     // let client = reqwest::Client::builder().build()?;
     // Ok(serde_json::from_value(client.get(url).send().await?.json().await?)?)
@@ -22,16 +22,40 @@ impl Registry {
         self.vec.get(num_id as usize)
     }
 
-    pub fn get(&self, str_id: &String) -> Option<RegId> {
+    pub fn get(&self, str_id: &str) -> Option<RegId> {
         let num_id = *self.map.get(str_id)?;
         Some(num_id)
     }
 
+    /// sort by the str ids, so the num ids are the same for everyone with the same entries, regardless of the insertion order.
+    /// RegIds returned by `insert` before are invalid after.
     pub fn build_num_id(&mut self) {
         self.vec.sort_unstable();
+        self.vec.dedup();
+        self.map = self.vec.iter().enumerate().map(|(i, str_id)| (str_id.clone(), i as RegId)).collect();
     }
 
     pub fn len(&self) -> usize {
         self.map.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_num_id_keeps_get_and_at_consistent() {
+        let mut reg = Registry::default();
+        reg.insert("stick");
+        reg.insert("apple");
+        reg.insert("coal");
+        reg.build_num_id();
+
+        assert_eq!(reg.vec, ["apple", "coal", "stick"]);
+        for str_id in ["apple", "coal", "stick"] {
+            let num_id = reg.get(str_id).unwrap();
+            assert_eq!(reg.at(num_id).map(String::as_str), Some(str_id));
+        }
+    }
+}
@@ -13,6 +13,9 @@ pub struct Chunk {
 
     pub is_populated: bool,
 
+    // Server: changed since loaded, saved by the ChunkLoader on unload.
+    pub is_modified: bool,
+
     pub entity: Entity,
     pub mesh_handle_terrain: Handle<Mesh>, // solid terrain
     pub mesh_handle_foliage: Handle<Mesh>,
@@ -36,6 +39,7 @@ impl Chunk {
             voxel: [Vox::default(); Self::LEN3],
             chunkpos,
             is_populated: false,
+            is_modified: false,
             neighbor_chunks: Default::default(),
             chunkptr_weak: Weak::default(),
             entity: Entity::PLACEHOLDER,
@@ -1,18 +1,55 @@
+//! Chunk Storage of a world. modified chunks are saved in `<world_dir>/chunks/<x>_<y>_<z>.bin`,
+//! the others are generated again by the worldgen when loaded.
 
-struct ChunkLoader {
+use std::path::{Path, PathBuf};
 
-    save_dir: Path,
+use bevy::prelude::*;
 
-}
+use super::Chunk;
+use crate::net::CellData;
 
-impl ChunkLoader {
+const CHUNKS_DIR: &str = "chunks";
 
-    fn load_chunk(chunk: &mut Chunk) {
+#[derive(Clone)]
+pub struct ChunkLoader {
+    save_dir: PathBuf,
+}
 
+impl ChunkLoader {
+    pub fn new(world_dir: impl AsRef<Path>) -> Self {
+        Self {
+            save_dir: world_dir.as_ref().join(CHUNKS_DIR),
+        }
     }
 
-    fn save_chunk(chunk: &mut Chunk) {
+    fn chunk_file(&self, chunkpos: IVec3) -> PathBuf {
+        self.save_dir.join(format!("{}_{}_{}.bin", chunkpos.x, chunkpos.y, chunkpos.z))
+    }
 
+    /// load the saved chunk. false if it's not saved (or broken), then it should be generated.
+    pub fn load_chunk(&self, chunk: &mut Chunk) -> bool {
+        let file = self.chunk_file(chunk.chunkpos);
+        let Ok(bytes) = std::fs::read(&file) else {
+            return false;
+        };
+        match bincode::deserialize::<Vec<CellData>>(&bytes) {
+            Ok(cells) => {
+                CellData::to_chunk(&cells, chunk);
+                true
+            }
+            Err(err) => {
+                error!("Failed to load chunk {}: {err}", file.display());
+                false
+            }
+        }
     }
 
-}
\ No newline at end of file
+    pub fn save_chunk(&self, chunk: &Chunk) {
+        let file = self.chunk_file(chunk.chunkpos);
+        let bytes = bincode::serialize(&CellData::from_chunk(chunk)).unwrap();
+        let result = std::fs::create_dir_all(&self.save_dir).and_then(|_| std::fs::write(&file, bytes));
+        if let Err(err) = result {
+            error!("Failed to save chunk {}: {err}", file.display());
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::f32::consts::PI;
 
 use bevy::{
-    math::{vec2, vec3},
+    math::{ivec3, vec2, vec3},
     prelude::*,
 };
 
@@ -50,7 +50,7 @@ pub fn generate_chunk_mesh_liquid(vbuf: &mut VertexBuffer, chunk: &Chunk) {
         let c = chunk.at_voxel(lp);
 
         if c.tex_id == VoxTex::Water {
-            put_cube(vbuf, lp, chunk, c);
+            put_liquid(vbuf, lp, chunk, c);
         }
     });
 }
@@ -296,6 +296,66 @@ fn put_cube(vbuf: &mut VertexBuffer, lp: IVec3, chunk: &Chunk, vox: &Vox) {
     }
 }
 
+/// height of the fluid surface in the voxel. full when there is fluid above.
+fn fluid_height(lp: IVec3, chunk: &Chunk) -> Option<f32> {
+    let vox = chunk.get_voxel_rel_or_default(lp);
+    if vox.tex_id == VoxTex::Water {
+        if chunk.get_voxel_rel_or_default(lp + IVec3::Y).tex_id == VoxTex::Water {
+            return Some(1.0);
+        }
+        Some(vox.fluid_height())
+    } else if vox.is_nil() {
+        Some(0.0)
+    } else {
+        None // solid, doesn't affect the surface.
+    }
+}
+
+// a liquid cube with the top at its fluid level. the top corners are averaged with the 4 voxels around,
+// so the surface is continuous and slopes down along the flow.
+fn put_liquid(vbuf: &mut VertexBuffer, lp: IVec3, chunk: &Chunk, vox: &Vox) {
+    let own_height = fluid_height(lp, chunk).unwrap_or(1.0);
+    let mut corner_heights = [[own_height; 2]; 2];
+    for cx in 0..2 {
+        for cz in 0..2 {
+            let heights = [ivec3(cx - 1, 0, cz - 1), ivec3(cx, 0, cz - 1), ivec3(cx - 1, 0, cz), ivec3(cx, 0, cz)]
+                .map(|rp| fluid_height(lp + rp, chunk));
+            let corner = &mut corner_heights[cx as usize][cz as usize];
+            if heights.iter().flatten().any(|&h| h >= 1.0) {
+                *corner = 1.0;
+            } else if heights.iter().flatten().count() != 0 {
+                *corner = heights.iter().flatten().sum::<f32>() / heights.iter().flatten().count() as f32;
+            }
+        }
+    }
+    let lowered = own_height < 1.0;
+
+    for face_i in 0..6 {
+        let face_dir = Vec3::from_slice(&CUBE_NORM[face_i * 18..]).as_ivec3();
+
+        // the lowered top is visible under an obaque cube.
+        let neib = chunk.get_voxel_rel_or_default(lp + face_dir);
+        if neib.is_obaque_cube() && !(lowered && face_dir == IVec3::Y) {
+            continue;
+        }
+        if neib.tex_id == VoxTex::Water {
+            continue;
+        }
+
+        for vert_i in 0..6 {
+            let mut p = Vec3::from_slice(&CUBE_POS[face_i * 18 + vert_i * 3..]);
+            if p.y == 1.0 {
+                p.y = corner_heights[p.x as usize][p.z as usize];
+            }
+            vbuf.push_vertex(
+                p + lp.as_vec3(),
+                Vec2::new(vox.tex_id as f32, neib.light.red() as f32),
+                Vec3::from_slice(&CUBE_NORM[face_i * 18 + vert_i * 3..]),
+            );
+        }
+    }
+}
+
 // put a -X face in middle of pos. for foliages.
 pub fn put_face(vbuf: &mut VertexBuffer, tex_id: u16, pos: Vec3, rot: Quat, scale: Vec2) {
     // -X Face
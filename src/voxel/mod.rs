@@ -1,5 +1,6 @@
 mod vox;
 mod chunk;
+mod chunk_storage;
 pub mod meshgen;
 pub mod worldgen;
 pub mod lighting;
@@ -9,9 +10,10 @@ mod voxel_server;
 mod render;
 
 pub use chunk::Chunk;
+pub use chunk_storage::ChunkLoader;
 pub use vox::{Vox, VoxShape, VoxTex, VoxLight,};
-pub use voxel_client::{ClientChunkSystem, ClientVoxelPlugin, HitResult, VoxelBrush};
-pub use voxel_server::{ServerChunkSystem, ServerVoxelPlugin};
+pub use voxel_client::{ClientChunkSystem, ClientVoxelPlugin, HitResult, MiningProgress, RemoteMining, VoxelBrush};
+pub use voxel_server::{break_voxel, current_cells, send_chunk_modify, set_load_distance, ServerChunkSystem, ServerVoxelPlugin, VoxelBreakEvent, VoxelPlaceEvent, BREAK_REACH};
 
 pub type ChunkPtr = Arc<Chunk>;
 
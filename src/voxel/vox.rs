@@ -1,5 +1,6 @@
 
 
+use crate::item::ToolType;
 use crate::prelude::*;
 
 // naming VoxelUnit?: BlockState, Cell, Voxel or Vox?
@@ -13,6 +14,7 @@ pub struct Vox {
 
     /// SDF value, used for Isosurface Extraction.
     /// 0 -> surface, +0 positive -> void, -0 negative -> solid.
+    /// the fluid level instead for Water, see `fluid_level`.
     pub isoval: u8,
     // Cached FeaturePoint
     // pub cached_fp: Vec3,
@@ -43,6 +45,15 @@ fn isoval_ndc(u: u8) -> f32 {
 }
 
 impl Vox {
+    /// fluid level of a source. flowing fluid is 1..FLUID_SOURCE, lower when farther from the source.
+    pub const FLUID_SOURCE: u8 = 8;
+
+    /// Water is always void to the Isosurface Extraction, a bit below the surface.
+    pub const WATER_ISOVALUE: f32 = -0.1;
+
+    /// chance of broken leaves to drop a sapling instead of an apple.
+    pub const SAPLING_DROP_CHANCE: f32 = 0.1;
+
     pub fn new(tex_id: u16, shape_id: VoxShape, isovalue: f32) -> Self {
         Self {
             tex_id,
@@ -64,11 +75,30 @@ impl Vox {
         if self.shape_id != VoxShape::Isosurface {
             return 0.0;
         }
+        if self.tex_id == VoxTex::Water {
+            return Self::WATER_ISOVALUE;
+        }
         isoval_ndc(self.isoval)
     }
     pub fn set_isovalue(&mut self, val: f32) {
         self.isoval = isoval_u8(val);
     }
+
+    /// the fluid level of Water, stored in `isoval`. values above FLUID_SOURCE (the water generated before levels) are sources.
+    pub fn fluid_level(&self) -> u8 {
+        self.isoval.min(Self::FLUID_SOURCE)
+    }
+    pub fn set_fluid_level(&mut self, level: u8) {
+        self.isoval = level.min(Self::FLUID_SOURCE);
+    }
+    pub fn is_fluid_source(&self) -> bool {
+        self.fluid_level() == Self::FLUID_SOURCE
+    }
+    /// height of the fluid surface in the voxel, lower when flowing. full if there is fluid above, see the liquid meshing.
+    pub fn fluid_height(&self) -> f32 {
+        self.fluid_level() as f32 / (Self::FLUID_SOURCE + 1) as f32
+    }
+
     pub fn is_isoval_empty(&self) -> bool {
         self.isovalue() <= 0.0
     }
@@ -76,6 +106,24 @@ impl Vox {
     pub fn is_obaque_cube(&self) -> bool {
         self.is_cube() && !self.is_nil()
     }
+
+    /// a sapling is a plant of the Leaves texture, it grows into a tree. see server::chunk_tick
+    pub fn is_sapling(&self) -> bool {
+        self.tex_id == VoxTex::Leaves && self.shape_id == VoxShape::Grass
+    }
+
+    /// plants (`VoxShape::Grass`) are placed and grow only on dirt or grass.
+    pub fn supports_plant(&self) -> bool {
+        matches!(self.tex_id, VoxTex::Dirt | VoxTex::Grass) && self.shape_id != VoxShape::Grass
+    }
+
+    /// the drop of the voxel when broken, see `VoxTex::drop_item`. a sapling drops itself, leaves drop a sapling by chance.
+    pub fn drop_item(&self) -> Option<(&'static str, u8)> {
+        if self.is_sapling() || (self.tex_id == VoxTex::Leaves && rand::random::<f32>() < Self::SAPLING_DROP_CHANCE) {
+            return Some(("sapling", 1));
+        }
+        VoxTex::drop_item(self.tex_id)
+    }
 }
 
 
@@ -120,6 +168,7 @@ impl VoxTex {
     pub const Water: u16 = 24;
     pub const Sand: u16 = 19;
     pub const Log: u16 = 13;
+    pub const IronOre: u16 = 2;
 
     pub const ShortGrass: u16 = 13;
     pub const Bush: u16 = 14;
@@ -127,6 +176,51 @@ impl VoxTex {
     pub const Fern: u16 = 16;
     pub const Leaves: u16 = 23;
 
+    /// the drop of the block when broken: (item str id, count). None drops nothing.
+    pub fn drop_item(tex_id: u16) -> Option<(&'static str, u8)> {
+        Some(match tex_id {
+            Self::Stone => ("coal", 1),
+            Self::IronOre => ("iron_ingot", 1),
+            Self::Dirt | Self::Grass => ("dirt", 1),
+            Self::Sand => ("sand", 1),
+            Self::Log => ("stick", 2),
+            Self::Leaves => ("apple", 1),
+            _ => return None,
+        })
+    }
+
+    /// seconds to break the voxel by hand, before the 1.5 factor. None: unbreakable.
+    pub fn hardness(tex_id: u16) -> Option<f32> {
+        Some(match tex_id {
+            Self::Nil | Self::Water => return None,
+            Self::Stone => 1.5,
+            Self::IronOre => 3.0,
+            Self::Log => 2.0,
+            Self::Dirt | Self::Grass | Self::Sand => 0.5,
+            Self::Leaves => 0.2,
+            Self::Bush | Self::Rose | Self::Fern => 0.,
+            _ => 1.0,
+        })
+    }
+
+    /// the tool that mines the voxel faster.
+    pub fn preferred_tool(tex_id: u16) -> Option<ToolType> {
+        match tex_id {
+            Self::Stone | Self::IronOre => Some(ToolType::Pickaxe),
+            Self::Log => Some(ToolType::Axe),
+            Self::Dirt | Self::Grass | Self::Sand => Some(ToolType::Shovel),
+            Self::Leaves => Some(ToolType::Shears),
+            _ => None,
+        }
+    }
+
+    /// seconds to break the voxel with the tool, None for by hand. returns None if unbreakable.
+    pub fn break_time(tex_id: u16, tool: Option<ToolType>) -> Option<f32> {
+        let hardness = Self::hardness(tex_id)?;
+        let speed = if tool.is_some() && tool == Self::preferred_tool(tex_id) { 4. } else { 1. };
+        Some(hardness * 1.5 / speed)
+    }
+
     // [0,1] -> [0,1]
     pub fn map_uv(uv: Vec2, tex_id: u16) -> Vec2 {
         const TEX_CAP: f32 = 24.;
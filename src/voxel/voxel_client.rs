@@ -8,11 +8,14 @@ use bevy::{
 use avian3d::prelude::*;
 use leafwing_input_manager::action_state::ActionState;
 
-use super::{meshgen, render::{self, FoliageMaterial, LiquidMaterial, TerrainMaterial}, ChannelRx, ChannelTx, Chunk, ChunkPtr, ChunkSystem, VoxShape};
+use super::{meshgen, render::{self, FoliageMaterial, LiquidMaterial, TerrainMaterial}, ChannelRx, ChannelTx, Chunk, ChunkPtr, ChunkSystem, VoxShape, VoxTex};
 use crate::{
     client::prelude::*,
+    item::Items,
+    net::{CPacket, MiningAction, RenetClientHelper, BREAK_STAGES},
     util::{as_mut, iter, AsMutRef},
 };
+use bevy_renet::renet::RenetClient;
 
 pub struct ClientVoxelPlugin;
 
@@ -40,6 +43,8 @@ impl Plugin for ClientVoxelPlugin {
         app.insert_resource(HitResult::default());
         app.register_type::<HitResult>();
 
+        app.insert_resource(MiningProgress::default());
+
         // app.add_systems(PreUpdate, raycast.run_if(condition::in_world));
 
         app.add_systems(
@@ -50,6 +55,7 @@ impl Plugin for ClientVoxelPlugin {
                 chunks_remesh_enqueue,
                 draw_gizmos,
                 draw_crosshair_cube.in_set(PhysicsSet::Sync),
+                draw_break_progress,
             )
             .chain()
             .run_if(condition::in_world),
@@ -136,6 +142,7 @@ fn on_world_init(
 fn on_world_exit(mut cmds: Commands) {
     info!("Clear ClientChunkSystem");
     cmds.remove_resource::<ClientChunkSystem>();
+    cmds.insert_resource(MiningProgress::default());
 }
 
 type ChunkLoadingData = Chunk;
@@ -145,6 +152,7 @@ fn chunks_detect_load_and_unload(
     mut chunk_sys: ResMut<ClientChunkSystem>,
     mut chunks_loading: Local<HashSet<IVec3>>, // for detect/skip if is loading
     cfg: Res<ClientSettings>,
+    worldinfo: Res<WorldInfo>,
 
     mut cmds: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -154,6 +162,7 @@ fn chunks_detect_load_and_unload(
 ) {
     let vp = Chunk::as_chunkpos(query_cam.single().unwrap().translation.as_ivec3()); // viewer pos
     let vd = cfg.chunks_load_distance;
+    let seed = worldinfo.seed;
 
     // Chunks Detect Load/Gen
 
@@ -174,7 +183,7 @@ fn chunks_detect_load_and_unload(
             // info!("Load Chunk: {:?}", chunkpos);
             let mut chunk = Chunk::new(chunkpos);
 
-            super::worldgen::generate_chunk(&mut chunk);
+            super::worldgen::generate_chunk(&mut chunk, seed);
 
             tx.send(chunk).unwrap();
         });
@@ -393,6 +402,11 @@ fn raycast(
     mut chunk_sys: ResMut<ClientChunkSystem>,
     cli: Res<ClientInfo>,
     vox_brush: Res<VoxelBrush>,
+    mut net_client: ResMut<RenetClient>,
+    mut mining: ResMut<MiningProgress>,
+    player: Res<ClientPlayerInfo>,
+    items: Res<Items>,
+    time: Res<Time>,
 ) {
     let cam_trans = query_cam.single().unwrap();
     let ray_pos = cam_trans.translation();
@@ -425,18 +439,85 @@ fn raycast(
 
     if cli.curr_ui != CurrentUI::None {
         // todo: cli.is_manipulating()
+        mining.stop(&mut net_client);
+        return;
+    }
+    if !player.game_mode.can_interact() {
+        mining.stop(&mut net_client);
         return;
     }
 
     let action_state = query_input.single().unwrap();
-    let do_break = action_state.just_pressed(&InputAction::Attack);
-    let do_place = action_state.just_pressed(&InputAction::UseItem);
+    let held = player
+        .inventory
+        .items
+        .get(player.hotbar_index as usize)
+        .filter(|s| !s.is_empty())
+        .and_then(|s| items.def(s.item_id));
+    let held_block = held.and_then(|def| def.place_block);
+    let do_break = action_state.just_pressed(&InputAction::Attack) && player.game_mode.instant_break();
+    // the brush places from nothing, so only with infinite items (creative) when not holding a block item.
+    let do_place = action_state.just_pressed(&InputAction::UseItem) && player.game_mode.infinite_items() && held_block.is_none();
+
+    // Place the held block item, the server takes it from the stack.
+    if let Some((def, tex_id)) = held
+        .and_then(|def| Some((def, held_block?)))
+        .filter(|_| hit_result.is_hit && action_state.just_pressed(&InputAction::UseItem))
+    {
+        let pos = hit_result.voxel_pos + hit_result.normal.round().as_ivec3();
+        if let Some(v) = chunk_sys
+            .get_voxel(pos)
+            .filter(|v| (v.is_nil() || v.tex_id == VoxTex::Water) && def.can_place_on(chunk_sys.get_voxel(pos - IVec3::Y)))
+        {
+            // predict. reverted by the server's ChunkModify if rejected.
+            let v = v.as_mut();
+            v.tex_id = tex_id;
+            v.shape_id = def.place_shape;
+            v.set_isovalue(0.);
+            chunk_sys.mark_chunk_remesh(Chunk::as_chunkpos(pos));
+            net_client.send_packet(&CPacket::PlaceVoxel { pos });
+        }
+    }
+
+    // Hold-to-mine, the server validates. see server::mining
+    if !player.game_mode.instant_break() {
+        let held_tool = held.and_then(|def| def.tool);
+        // the targeted voxel and its break time
+        let target = Some(hit_result.voxel_pos)
+            .filter(|_| hit_result.is_hit && action_state.pressed(&InputAction::Attack))
+            .and_then(|p| Some((p, VoxTex::break_time(chunk_sys.get_voxel(p)?.tex_id, held_tool)?)));
+
+        if target.map(|t| t.0) != mining.target {
+            mining.stop(&mut net_client);
+            if let Some((pos, break_time)) = target {
+                mining.target = Some(pos);
+                mining.break_time = break_time;
+                net_client.send_packet(&CPacket::Mining(MiningAction::Start { pos }));
+            }
+        }
+        if let Some(pos) = mining.target {
+            mining.progress += time.delta_secs() / mining.break_time.max(f32::EPSILON);
+            if mining.progress >= 1. {
+                // predict. reverted by the server's ChunkModify if rejected.
+                if let Some(v) = chunk_sys.get_voxel(pos) {
+                    let v = v.as_mut();
+                    v.tex_id = VoxTex::Nil;
+                    v.set_isovalue(v.isovalue().min(0.));
+                    chunk_sys.mark_chunk_remesh(Chunk::as_chunkpos(pos));
+                }
+                net_client.send_packet(&CPacket::Mining(MiningAction::Finish { pos }));
+                mining.target = None;
+                mining.progress = 0.;
+            }
+        }
+    }
 
     if hit_result.is_hit && (do_break || do_place) {
         let brush = &*vox_brush;
         let n = brush.size as i32;
 
         // These code is Horrible
+        let mut broken = Vec::new();
 
         iter::iter_aabb(n, n, |lp| {
             // +0.01*norm: for placing cube like MC.
@@ -459,14 +540,18 @@ fn raycast(
                         if brush.shape != VoxShape::Isosurface {
                             v.set_isovalue(0.0);
                         }
-                    } else if v.is_isoval_empty() {
+                    } else if v.is_isoval_empty() && !v.is_nil() {
                         v.tex_id = 0;
+                        broken.push(p);
                     }
                 }
 
                 chunk_sys.mark_chunk_remesh(Chunk::as_chunkpos(p)); // CLIS
             }
         });
+        if !broken.is_empty() {
+            net_client.send_packet(&CPacket::BreakVoxels { positions: broken });
+        }
         // let mut map = HashMap::new();
         // let pack = map.entry(chunkpos).or_insert_with(Vec::new);
         // pack.push(CellData::from_cell(Chunk::local_idx(Chunk::as_localpos(p)) as u16, &c));
@@ -477,6 +562,43 @@ fn raycast(
     }
 }
 
+/// Client: the voxel being mined by the player.
+#[derive(Resource, Default, Debug)]
+pub struct MiningProgress {
+    pub target: Option<IVec3>,
+    // [0, 1]
+    pub progress: f32,
+    // seconds
+    pub break_time: f32,
+}
+
+impl MiningProgress {
+    fn stop(&mut self, net_client: &mut RenetClient) {
+        if self.target.take().is_some() {
+            net_client.send_packet(&CPacket::Mining(MiningAction::Abort));
+        }
+        self.progress = 0.;
+    }
+}
+
+/// Client: the crack stage of the voxel a remote player is mining. from SPacket::BreakProgress
+#[derive(Component, Debug)]
+pub struct RemoteMining {
+    pub pos: IVec3,
+    pub stage: i8,
+}
+
+fn draw_break_progress(mut gizmos: Gizmos, mining: Res<MiningProgress>, query_remote: Query<&RemoteMining>) {
+    let local = mining.target.map(|pos| (pos, mining.progress));
+    let remote = query_remote.iter().map(|m| (m.pos, m.stage as f32 / BREAK_STAGES as f32));
+
+    for (pos, progress) in local.into_iter().chain(remote) {
+        // shrinks and darkens as the crack grows.
+        let trans = Transform::from_translation(pos.as_vec3() + 0.5).with_scale(Vec3::splat(1.02 - progress * 0.3));
+        gizmos.cuboid(trans, Color::srgba(0., 0., 0., 0.3 + progress * 0.7));
+    }
+}
+
 fn draw_crosshair_cube(mut gizmos: Gizmos, hit_result: Res<HitResult>, vbrush: Res<VoxelBrush>) {
     if hit_result.is_hit {
         if vbrush.shape == VoxShape::Isosurface {
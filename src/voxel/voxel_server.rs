@@ -3,18 +3,20 @@ use bevy::{
     tasks::AsyncComputeTaskPool,
     platform::collections::{HashMap, HashSet},
 };
-use bevy_renet::renet::RenetServer;
+use bevy_renet::renet::{ClientId, RenetServer};
 use avian3d::prelude::*;
 use std::sync::Arc;
 
-use super::{ChannelRx, ChannelTx, Chunk, ChunkPtr, ChunkSystem};
+use super::{ChannelRx, ChannelTx, Chunk, ChunkLoader, ChunkPtr, ChunkSystem, VoxTex};
 use crate::{
+    item::Items,
     net::{CellData, RenetServerHelper, SPacket},
-    server::prelude::ServerInfo,
+    server::prelude::{LevelData, PlayerInfo, ScheduledUpdates, ServerInfo, ServerSettings, ServerTickSet},
     util::{iter, AsMutRef},
 };
 
-type ChunkLoadingData = (IVec3, ChunkPtr);
+// (chunkpos, chunk, the world version it was loaded for)
+type ChunkLoadingData = (IVec3, ChunkPtr, u64);
 
 pub struct ServerVoxelPlugin;
 
@@ -28,7 +30,190 @@ impl Plugin for ServerVoxelPlugin {
             app.insert_resource(ChannelRx(rx));
         }
 
-        app.add_systems(Update, chunks_load);
+        app.add_event::<VoxelBreakEvent>();
+        app.add_event::<VoxelPlaceEvent>();
+
+        app.add_systems(FixedUpdate, chunks_load.in_set(ServerTickSet));
+        app.add_systems(Update, (on_break_voxels, on_place_voxels));
+    }
+}
+
+/// voxels broken by a player, from CPacket::BreakVoxels.
+#[derive(Event)]
+pub struct VoxelBreakEvent {
+    pub client_id: ClientId,
+    pub positions: Vec<IVec3>,
+}
+
+/// a voxel placed by a player from the held item, from CPacket::PlaceVoxel.
+#[derive(Event)]
+pub struct VoxelPlaceEvent {
+    pub client_id: ClientId,
+    pub pos: IVec3,
+}
+
+/// set the chunks load distance of the player. the chunks out of it are deleted on the client now,
+/// the new ones are streamed by chunks_load.
+pub fn set_load_distance(net_server: &mut RenetServer, player: &mut PlayerInfo, load_distance: IVec2) {
+    player.chunks_load_distance = load_distance;
+
+    let cp = Chunk::as_chunkpos(player.position.as_ivec3());
+    let client_id = player.client_id;
+    player.chunks_loaded.retain(|&chunkpos| {
+        let keep = crate::voxel::is_chunk_in_load_distance(cp, chunkpos, load_distance);
+        if !keep {
+            net_server.send_packet(client_id, &SPacket::ChunkDel { chunkpos });
+        }
+        keep
+    });
+}
+
+/// max distance from the player to the voxels it breaks.
+pub const BREAK_REACH: f32 = 12.;
+
+/// break the voxel and spawn its drop if `drop`. returns the modified cell, None if there is nothing to break.
+pub fn break_voxel(chunk_sys: &ServerChunkSystem, p: IVec3, drop: bool, items: &Items, cmds: &mut Commands) -> Option<CellData> {
+    let v = chunk_sys.get_voxel_mut(p)?;
+    if v.is_nil() {
+        return None;
+    }
+    if let Some((str_id, count)) = v.drop_item().filter(|_| drop) {
+        if let Some(item_id) = items.id_of(str_id) {
+            crate::server::item_drop::spawn_item_drop(cmds, p.as_vec3() + 0.5, items.new_stack(item_id, count));
+        }
+    }
+    v.tex_id = VoxTex::Nil;
+    v.set_isovalue(v.isovalue().min(0.));
+    chunk_sys.get_chunk(Chunk::as_chunkpos(p))?.as_mut().is_modified = true;
+    Some(CellData::from_cell(Chunk::local_idx(Chunk::as_localpos(p)) as u16, v))
+}
+
+/// send the modified cells of the chunk to the players that have it loaded, except the one that applied it locally.
+pub fn send_chunk_modify(net_server: &mut RenetServer, serverinfo: &ServerInfo, chunkpos: IVec3, voxel: Vec<CellData>, except: Option<ClientId>) {
+    let packet = SPacket::ChunkModify { chunkpos, voxel };
+    for player in serverinfo.online_players.values() {
+        if Some(player.client_id) != except && player.chunks_loaded.contains(&chunkpos) {
+            net_server.send_packet(player.client_id, &packet);
+        }
+    }
+}
+
+/// the current cells of the voxels, grouped by chunk. to revert a client's rejected prediction.
+pub fn current_cells(chunk_sys: &ServerChunkSystem, positions: &[IVec3]) -> HashMap<IVec3, Vec<CellData>> {
+    let mut cells: HashMap<IVec3, Vec<CellData>> = HashMap::default();
+    for &p in positions {
+        if let Some(v) = chunk_sys.get_voxel(p) {
+            cells
+                .entry(Chunk::as_chunkpos(p))
+                .or_default()
+                .push(CellData::from_cell(Chunk::local_idx(Chunk::as_localpos(p)) as u16, v));
+        }
+    }
+    cells
+}
+
+fn on_break_voxels(
+    mut events: EventReader<VoxelBreakEvent>,
+    chunk_sys: Res<ServerChunkSystem>,
+    serverinfo: Res<ServerInfo>,
+    items: Res<Items>,
+    mut net_server: ResMut<RenetServer>,
+    mut updates: ResMut<ScheduledUpdates>,
+    mut cmds: Commands,
+) {
+    for e in events.read() {
+        let Some(player) = serverinfo.online_players.get(&e.client_id) else {
+            continue;
+        };
+        // only instant breaking players (creative) alive, the others mine. see server::mining
+        if !player.game_mode.instant_break() || !player.is_alive() {
+            warn!("Rejected instant break from {}", player.username);
+            for (chunkpos, voxel) in current_cells(&chunk_sys, &e.positions) {
+                net_server.send_packet(e.client_id, &SPacket::ChunkModify { chunkpos, voxel });
+            }
+            continue;
+        }
+        let mut modified: HashMap<IVec3, Vec<CellData>> = HashMap::default();
+
+        for &p in &e.positions {
+            if p.as_vec3().distance(player.position) > BREAK_REACH {
+                continue;
+            }
+            if let Some(cell) = break_voxel(&chunk_sys, p, !player.game_mode.infinite_items(), &items, &mut cmds) {
+                updates.notify_changed(p);
+                modified.entry(Chunk::as_chunkpos(p)).or_default().push(cell);
+            }
+        }
+
+        // the breaker has applied it locally.
+        for (chunkpos, voxel) in modified {
+            send_chunk_modify(&mut net_server, &serverinfo, chunkpos, voxel, Some(e.client_id));
+        }
+    }
+}
+
+fn on_place_voxels(
+    mut events: EventReader<VoxelPlaceEvent>,
+    chunk_sys: Res<ServerChunkSystem>,
+    mut serverinfo: ResMut<ServerInfo>,
+    items: Res<Items>,
+    mut net_server: ResMut<RenetServer>,
+    mut updates: ResMut<ScheduledUpdates>,
+) {
+    for e in events.read() {
+        let Some(player) = serverinfo.online_players.get_mut(&e.client_id) else {
+            continue;
+        };
+        let pos = e.pos;
+        let slot = player.hotbar_index as usize;
+        let held = player.inventory.items.get(slot).copied().unwrap_or_default();
+        let held_def = items.def(held.item_id).filter(|_| !held.is_empty());
+
+        // the cells the player occupies, it can't place into itself.
+        let feet = player.position.floor().as_ivec3();
+        let eye = (player.position + Vec3::Y * crate::net::EYE_HEIGHT).floor().as_ivec3();
+
+        let valid = player.is_alive()
+            && player.game_mode.can_interact()
+            && pos.as_vec3().distance(player.position) <= BREAK_REACH
+            && pos != feet
+            && pos != eye
+            && chunk_sys.get_voxel(pos).is_some_and(|v| v.is_nil() || v.tex_id == VoxTex::Water)
+            && held_def.is_some_and(|def| def.can_place_on(chunk_sys.get_voxel(pos - IVec3::Y)));
+        let Some((tex_id, shape_id)) = held_def.and_then(|def| Some((def.place_block?, def.place_shape))).filter(|_| valid) else {
+            warn!("Rejected placing at {} from {}", pos, player.username);
+            for (chunkpos, voxel) in current_cells(&chunk_sys, &[pos]) {
+                net_server.send_packet(e.client_id, &SPacket::ChunkModify { chunkpos, voxel });
+            }
+            net_server.send_packet(e.client_id, &crate::server::inventory::content_packet(player));
+            continue;
+        };
+
+        if !player.game_mode.infinite_items() {
+            let before = player.inventory.clone();
+            let stack = &mut player.inventory.items[slot];
+            stack.count -= 1;
+            if stack.count == 0 {
+                stack.clear();
+            }
+            crate::server::inventory::send_changes(&mut net_server, player, &before, player.cursor);
+        }
+
+        let Some(v) = chunk_sys.get_voxel_mut(pos) else {
+            continue;
+        };
+        v.tex_id = tex_id;
+        v.shape_id = shape_id;
+        v.set_isovalue(0.0);
+        let Some(chunkptr) = chunk_sys.get_chunk(Chunk::as_chunkpos(pos)) else {
+            continue;
+        };
+        chunkptr.as_mut().is_modified = true;
+        updates.notify_changed(pos);
+
+        // the placer has applied it locally.
+        let cell = CellData::from_cell(Chunk::local_idx(Chunk::as_localpos(pos)) as u16, v);
+        send_chunk_modify(&mut net_server, &serverinfo, chunkptr.chunkpos, vec![cell], Some(e.client_id));
     }
 }
 
@@ -36,9 +221,12 @@ fn chunks_load(
     mut chunk_sys: ResMut<ServerChunkSystem>,
     mut net_server: ResMut<RenetServer>,
     mut server: ResMut<ServerInfo>,
+    cfg: Res<ServerSettings>,
+    level: Res<LevelData>,
     mut cmds: Commands,
 
     mut chunks_loading: Local<HashSet<IVec3>>, // for detect/skip if is loading
+    mut loading_version: Local<u64>,
     tx_chunks_loading: Res<ChannelTx<ChunkLoadingData>>,
     rx_chunks_loading: Res<ChannelRx<ChunkLoadingData>>,
 ) {
@@ -46,6 +234,15 @@ fn chunks_load(
     // 优化: 仅当某玩家 进入/退出 移动过区块边界时，才针对更新
     // 待改进: 这里可能有多种加载方法，包括Inner-Outer近距离优先加载，填充IVec3待加载列表并排序方法
 
+    let loader = ChunkLoader::new(&cfg.world_dir);
+
+    // the world changed, the chunks still loading are of the previous one. dropped when they complete.
+    let version = chunk_sys.world_version;
+    if *loading_version != version {
+        *loading_version = version;
+        chunks_loading.clear();
+    }
+
     // Dispatch Chunk Load
     for player in server.online_players.values() {
         let vd = player.chunks_load_distance;
@@ -62,14 +259,18 @@ fn chunks_load(
             }
 
             let tx = tx_chunks_loading.clone();
+            let loader = loader.clone();
+            let seed = level.seed;
             let task = AsyncComputeTaskPool::get().spawn(async move {
                 // info!("Load Chunk: {:?}", chunkpos);
                 let mut chunk = Chunk::new(chunkpos);
 
-                super::worldgen::generate_chunk(&mut chunk);
+                if !loader.load_chunk(&mut chunk) {
+                    super::worldgen::generate_chunk(&mut chunk, seed);
+                }
 
                 let chunkptr = Arc::new(chunk);
-                tx.send((chunkpos, chunkptr)).unwrap();
+                tx.send((chunkpos, chunkptr, version)).unwrap();
             });
 
             task.detach();
@@ -80,7 +281,10 @@ fn chunks_load(
     }
 
     // Complete Chunk Load
-    while let Ok((chunkpos, chunkptr)) = rx_chunks_loading.try_recv() {
+    while let Ok((chunkpos, chunkptr, chunk_version)) = rx_chunks_loading.try_recv() {
+        if chunk_version != version {
+            continue;
+        }
         chunks_loading.remove(&chunkpos);
 
         {
@@ -114,9 +318,11 @@ fn chunks_load(
         }
 
         if !any_desire {
-            let chunkptr = chunk_sys.despawn_chunk(chunkpos);
-            let entity = chunkptr.unwrap().as_ref().entity;
-            cmds.entity(entity).despawn_recursive();
+            let chunkptr = chunk_sys.despawn_chunk(chunkpos).unwrap();
+            if chunkptr.is_modified {
+                loader.save_chunk(&chunkptr);
+            }
+            cmds.entity(chunkptr.entity).despawn_recursive();
 
             net_server.broadcast_packet(&SPacket::ChunkDel { chunkpos });
 
@@ -166,6 +372,8 @@ fn chunks_load(
 #[derive(Resource)]
 pub struct ServerChunkSystem {
     pub chunks: HashMap<IVec3, ChunkPtr>,
+    // increased when the world changes, the chunk loads started before are discarded.
+    world_version: u64,
 }
 
 impl ChunkSystem for ServerChunkSystem {
@@ -176,7 +384,10 @@ impl ChunkSystem for ServerChunkSystem {
 
 impl ServerChunkSystem {
     fn new() -> Self {
-        Self { chunks: HashMap::default() }
+        Self {
+            chunks: HashMap::default(),
+            world_version: 0,
+        }
     }
 
     fn spawn_chunk(&mut self, chunkptr: ChunkPtr) {
@@ -187,4 +398,22 @@ impl ServerChunkSystem {
     fn despawn_chunk(&mut self, chunkpos: IVec3) -> Option<ChunkPtr> {
         self.chunks.remove(&chunkpos)
     }
+
+    /// save the modified loaded chunks. e.g. on exit
+    pub fn save_modified(&self, loader: &ChunkLoader) {
+        let mut num_saved = 0;
+        for chunkptr in self.chunks.values().filter(|c| c.is_modified) {
+            loader.save_chunk(chunkptr);
+            chunkptr.as_mut().is_modified = false;
+            num_saved += 1;
+        }
+        info!("Saved {} modified chunks", num_saved);
+    }
+
+    /// unload all chunks without saving, returns their entities. e.g. on switching the world,
+    /// the chunks still loading are discarded.
+    pub fn clear(&mut self) -> Vec<Entity> {
+        self.world_version += 1;
+        self.chunks.drain().map(|(_, chunkptr)| chunkptr.entity).collect()
+    }
 }
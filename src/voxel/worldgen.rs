@@ -6,8 +6,10 @@ use noise::{Fbm, NoiseFn, Perlin};
 use super::*;
 use crate::util::{hash, iter};
 
-pub fn generate_chunk(chunk: &mut Chunk) {
-    let seed = 100;
+/// generate the terrain of the chunk by the world seed.
+pub fn generate_chunk(chunk: &mut Chunk, seed: u64) {
+    // the noise takes a 32-bit seed, fold the higher bits in.
+    let seed = (seed ^ (seed >> 32)) as u32;
     // let perlin = Perlin::new(seed);
     let mut fbm = Fbm::<Perlin>::new(seed);
     // fbm.frequency = 0.2;
@@ -29,18 +31,30 @@ pub fn generate_chunk(chunk: &mut Chunk) {
 
                 let mut tex = VoxTex::Nil; //(p.x / 2 % 24).abs() as u16;
                 if val > 0.0 {
-                    tex = VoxTex::Stone;
+                    tex = if p.y < 0 && is_ore(p, seed, IRON_ORE_CHANCE) { VoxTex::IronOre } else { VoxTex::Stone };
                 }
                 else if p.y < 0 && val < 0. {
                     val = -0.1;
                     tex = VoxTex::Water;
                 }
-                *chunk.at_voxel_mut(lp) = Vox::new(tex, VoxShape::Isosurface, val);
+                let mut vox = Vox::new(tex, VoxShape::Isosurface, val);
+                if tex == VoxTex::Water {
+                    vox.set_fluid_level(Vox::FLUID_SOURCE);
+                }
+                *chunk.at_voxel_mut(lp) = vox;
             }
         }
     }
 }
 
+/// the chance of an underground stone voxel to be iron ore.
+const IRON_ORE_CHANCE: f32 = 0.012;
+
+/// is there ore at the position, by the chance. deterministic per seed.
+fn is_ore(p: IVec3, seed: u32, chance: f32) -> bool {
+    hash(p.x.wrapping_mul(73856093) ^ p.y.wrapping_mul(19349663) ^ p.z.wrapping_mul(83492791) ^ seed as i32) < chance
+}
+
 pub fn populate_chunk(chunk: &mut Chunk) {
     let chunkpos = chunk.chunkpos;
     let perlin = Perlin::new(123);
@@ -152,6 +166,53 @@ pub fn populate_chunk(chunk: &mut Chunk) {
 }
 
 pub fn gen_tree(chunk: &mut Chunk, lp: IVec3, siz: f32) {
+    tree_voxels(siz, |rp, part| {
+        let lp = lp + rp;
+        match part {
+            TreePart::Leaves => {
+                // if let Some(chunkptr) = chunk.get_chunk_rel(lp) {
+                //     let vox = chunkptr.at_voxel_mut(Chunk::as_localpos(lp));
+                //     vox .tex_id =VoxTex::Leaves;
+                //     vox.shape_id = VoxShape::Leaves;
+                // }
+                chunk.set_voxel_rel(lp, |vox| part.apply(vox));
+            }
+            TreePart::Trunk { .. } => {
+                if lp.y > 15 {
+                    return;
+                }
+                part.apply(chunk.at_voxel_mut(lp));
+            }
+        }
+    });
+}
+
+/// a voxel of a tree, see `tree_voxels`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TreePart {
+    Leaves,
+    Trunk { isovalue: f32 },
+}
+
+impl TreePart {
+    pub fn apply(self, vox: &mut Vox) {
+        match self {
+            TreePart::Leaves => {
+                vox.tex_id = VoxTex::Leaves;
+                vox.shape_id = VoxShape::Leaves;
+            }
+            TreePart::Trunk { isovalue } => {
+                vox.tex_id = VoxTex::Log;
+                vox.shape_id = VoxShape::Isosurface;
+                vox.set_isovalue(isovalue);
+            }
+        }
+    }
+}
+
+/// the voxels of a tree of the size [0, 1], relative to its root. the leaves first, then the trunk over them.
+/// shared by the world generation (`gen_tree`) and the growing saplings (server::chunk_tick).
+pub fn tree_voxels(siz: f32, mut f: impl FnMut(IVec3, TreePart)) {
     let trunk_height = 3 + (siz * 6.0) as i32;
     let leaves_rad = 2 + (siz * 5.0) as i32;
 
@@ -160,28 +221,11 @@ pub fn gen_tree(chunk: &mut Chunk, lp: IVec3, siz: f32) {
         if rp.length_squared() >= leaves_rad * leaves_rad {
             return;
         }
-        let lp = lp + IVec3::Y * trunk_height + rp;
-
-        // if let Some(chunkptr) = chunk.get_chunk_rel(lp) {
-        //     let vox = chunkptr.at_voxel_mut(Chunk::as_localpos(lp));
-        //     vox .tex_id =VoxTex::Leaves;
-        //     vox.shape_id = VoxShape::Leaves;
-        // }
-
-        chunk.set_voxel_rel(lp, |vox| {
-            vox.tex_id =VoxTex::Leaves;
-            vox.shape_id = VoxShape::Leaves;
-        });
+        f(IVec3::Y * trunk_height + rp, TreePart::Leaves);
     });
 
     // Trunk
     for i in 0..trunk_height {
-        if i + lp.y > 15 {
-            break;
-        }
-        let c = chunk.at_voxel_mut(lp + IVec3::Y * i);
-        c.tex_id = VoxTex::Log;
-        c.shape_id = VoxShape::Isosurface;
-        c.set_isovalue(2.0 * (1.2 - i as f32 / trunk_height as f32));
+        f(IVec3::Y * i, TreePart::Trunk { isovalue: 2.0 * (1.2 - i as f32 / trunk_height as f32) });
     }
 }